@@ -0,0 +1,119 @@
+use actix_web::{
+    cookie::{Cookie, SameSite},
+    http::Method,
+    HttpMessage,
+};
+use openssl::memcmp;
+
+/// Where the middleware takes tokens from
+#[derive(Clone)]
+pub enum TokenSource {
+    /// The `Authorization: Bearer` header
+    Header,
+    /// An HttpOnly cookie, so scripts never see the token. Requests changing something
+    /// have to repeat the CSRF cookie in a header.
+    Cookie(CookieConfig),
+    /// The header if there is one, the cookie otherwise
+    Both(CookieConfig),
+}
+
+impl TokenSource {
+    pub fn cookie_config(&self) -> Option<&CookieConfig> {
+        match self {
+            TokenSource::Header => None,
+            TokenSource::Cookie(config) | TokenSource::Both(config) => Some(config),
+        }
+    }
+}
+
+/// Names and flags of the cookies for the cookie mode
+#[derive(Clone)]
+pub struct CookieConfig {
+    /// HttpOnly cookie holding the access token
+    pub token_cookie: String,
+    /// Cookie holding the CSRF token, readable by scripts of the frontend
+    pub csrf_cookie: String,
+    /// Header the CSRF token has to be repeated in
+    pub csrf_header: String,
+    /// Only send the cookies over HTTPS, can be turned off for development
+    pub secure: bool,
+    pub same_site: SameSite,
+}
+
+impl Default for CookieConfig {
+    fn default() -> Self {
+        CookieConfig {
+            token_cookie: "udb_token".to_owned(),
+            csrf_cookie: "udb_csrf".to_owned(),
+            csrf_header: "X-CSRF-Token".to_owned(),
+            secure: true,
+            same_site: SameSite::Strict,
+        }
+    }
+}
+
+impl CookieConfig {
+    /// A cookie with the configured flags, removed again if `max_age` is 0
+    pub fn cookie(
+        &self,
+        name: &str,
+        value: &str,
+        path: &str,
+        max_age: i64,
+        http_only: bool,
+    ) -> Cookie<'static> {
+        Cookie::build(name.to_owned(), value.to_owned())
+            .path(path.to_owned())
+            .max_age(max_age)
+            .secure(self.secure)
+            .http_only(http_only)
+            .same_site(self.same_site)
+            .finish()
+    }
+
+    /// The access token and a new CSRF token, set when logging in. The CSRF token can
+    /// outlive the access token, e.g. for refreshing it after it has expired.
+    pub fn token_cookies(
+        &self,
+        token: &str,
+        max_age: i64,
+        csrf_max_age: i64,
+    ) -> Vec<Cookie<'static>> {
+        vec![
+            self.cookie(&self.token_cookie, token, "/", max_age, true),
+            self.cookie(&self.csrf_cookie, &csrf_token(), "/", csrf_max_age, false),
+        ]
+    }
+
+    /// Expired cookies, which remove the tokens when logging out
+    pub fn removal_cookies(&self) -> Vec<Cookie<'static>> {
+        vec![
+            self.cookie(&self.token_cookie, "", "/", 0, true),
+            self.cookie(&self.csrf_cookie, "", "/", 0, false),
+        ]
+    }
+
+    /// Double submit check: other sites can make the browser send the cookie, but can't
+    /// read it for putting it into the header. Also for other cookies than the token's.
+    pub fn csrf_valid<R: HttpMessage>(&self, method: &Method, req: &R) -> bool {
+        if [Method::GET, Method::HEAD, Method::OPTIONS, Method::TRACE].contains(method) {
+            return true;
+        }
+        let cookie = match req.cookie(&self.csrf_cookie) {
+            Some(cookie) => cookie,
+            None => return false,
+        };
+        let header = match req.headers().get(self.csrf_header.as_str()) {
+            Some(header) => header.as_bytes(),
+            None => return false,
+        };
+        let cookie = cookie.value().as_bytes();
+        !cookie.is_empty() && cookie.len() == header.len() && memcmp::eq(cookie, header)
+    }
+}
+
+fn csrf_token() -> String {
+    let mut bytes = [0; 32];
+    openssl::rand::rand_bytes(&mut bytes).expect("Couldn't generate CSRF token");
+    base64::encode_config(&bytes, base64::URL_SAFE_NO_PAD)
+}
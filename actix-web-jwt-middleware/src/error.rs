@@ -0,0 +1,64 @@
+use actix_web::{http::header::WWW_AUTHENTICATE, HttpResponse, ResponseError};
+use std::fmt;
+
+/// Why a request isn't authenticated, kept in the request extensions by `JwtAuthentication`
+/// until a `Policy` or an extractor decides whether the route needs a token
+#[derive(Clone, Debug, PartialEq)]
+pub enum AuthError {
+    /// The request carries no token at all
+    Missing,
+    /// The authorization header can't be read
+    InvalidRequest(&'static str),
+    /// The token is malformed, has a wrong signature, has expired or was revoked
+    InvalidToken(String),
+    /// A cookie authenticated request changing something lacks the CSRF token
+    Csrf,
+    /// The token is valid, but lacks a scope the route requires
+    InsufficientScope(String),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::Missing => write!(f, "The request carries no token"),
+            AuthError::InvalidRequest(description) => write!(f, "{}", description),
+            AuthError::InvalidToken(description) => write!(f, "{}", description),
+            AuthError::Csrf => write!(f, "Missing or wrong CSRF token"),
+            AuthError::InsufficientScope(scope) => {
+                write!(f, "The token lacks the {} scope", scope)
+            }
+        }
+    }
+}
+
+impl ResponseError for AuthError {
+    /// Unauthorized response with a `WWW-Authenticate` challenge as described in RFC 6750,
+    /// containing the error code and reason if the request had a token
+    fn error_response(&self) -> HttpResponse {
+        let challenge = match self {
+            AuthError::Missing => "Bearer".to_owned(),
+            AuthError::InvalidRequest(_) => challenge("invalid_request", self),
+            AuthError::InvalidToken(_) => challenge("invalid_token", self),
+            AuthError::Csrf => return HttpResponse::Forbidden().finish(),
+            AuthError::InsufficientScope(scope) => format!(
+                "{}, scope=\"{}\"",
+                challenge("insufficient_scope", self),
+                scope
+            ),
+        };
+        match self {
+            AuthError::InsufficientScope(_) => HttpResponse::Forbidden(),
+            _ => HttpResponse::Unauthorized(),
+        }
+        .header(WWW_AUTHENTICATE, challenge)
+        .finish()
+    }
+
+    fn render_response(&self) -> HttpResponse {
+        self.error_response()
+    }
+}
+
+fn challenge(code: &str, error: &AuthError) -> String {
+    format!("Bearer error=\"{}\", error_description=\"{}\"", code, error)
+}
@@ -0,0 +1,38 @@
+use crate::error::AuthError;
+use crate::{AuthenticationData, Claims};
+use actix_web::{dev::Payload, Error, FromRequest, HttpRequest};
+
+/// Rejects the request like a required policy if it has no valid token
+impl FromRequest for Claims {
+    type Error = Error;
+    type Future = Result<Self, Error>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let extensions = req.extensions();
+        match extensions.get::<AuthenticationData>() {
+            Some(data) => Ok(data.claims.clone()),
+            None => Err(extensions
+                .get::<AuthError>()
+                .cloned()
+                .unwrap_or(AuthError::Missing)
+                .into()),
+        }
+    }
+}
+
+/// The `sub` claim of the token, for handlers only needing to know the user
+pub struct Subject(pub String);
+
+impl FromRequest for Subject {
+    type Error = Error;
+    type Future = Result<Self, Error>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        match Claims::from_request(req, payload)?.sub {
+            Some(sub) => Ok(Subject(sub)),
+            None => Err(AuthError::InvalidToken("The token has no subject".to_owned()).into()),
+        }
+    }
+}
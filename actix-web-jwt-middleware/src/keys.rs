@@ -0,0 +1,639 @@
+use openssl::{
+    bn::{BigNum, BigNumContext, BigNumRef},
+    ec::EcKey,
+    ecdsa::EcdsaSig,
+    hash::MessageDigest,
+    memcmp,
+    nid::Nid,
+    pkey::{Id, PKey, Private, Public},
+    sign::{Signer, Verifier},
+};
+use serde_json::{json, Value};
+use std::{fmt, fs, path::Path};
+
+/// Signature algorithms of JWS, see RFC 7518 and RFC 8037
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Algorithm {
+    HS256,
+    HS384,
+    HS512,
+    RS256,
+    RS384,
+    RS512,
+    ES256,
+    ES384,
+    ES512,
+    EdDSA,
+}
+
+impl Algorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::HS256 => "HS256",
+            Algorithm::HS384 => "HS384",
+            Algorithm::HS512 => "HS512",
+            Algorithm::RS256 => "RS256",
+            Algorithm::RS384 => "RS384",
+            Algorithm::RS512 => "RS512",
+            Algorithm::ES256 => "ES256",
+            Algorithm::ES384 => "ES384",
+            Algorithm::ES512 => "ES512",
+            Algorithm::EdDSA => "EdDSA",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Algorithm> {
+        [
+            Algorithm::HS256,
+            Algorithm::HS384,
+            Algorithm::HS512,
+            Algorithm::RS256,
+            Algorithm::RS384,
+            Algorithm::RS512,
+            Algorithm::ES256,
+            Algorithm::ES384,
+            Algorithm::ES512,
+            Algorithm::EdDSA,
+        ]
+        .iter()
+        .find(|algorithm| algorithm.name() == name)
+        .cloned()
+    }
+
+    fn is_hmac(self) -> bool {
+        matches!(self, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512)
+    }
+
+    /// The digest used, EdDSA hashes internally
+    fn digest(self) -> Option<MessageDigest> {
+        match self {
+            Algorithm::HS256 | Algorithm::RS256 | Algorithm::ES256 => Some(MessageDigest::sha256()),
+            Algorithm::HS384 | Algorithm::RS384 | Algorithm::ES384 => Some(MessageDigest::sha384()),
+            Algorithm::HS512 | Algorithm::RS512 | Algorithm::ES512 => Some(MessageDigest::sha512()),
+            Algorithm::EdDSA => None,
+        }
+    }
+
+    /// The curve of ECDSA algorithms
+    fn curve(self) -> Option<(Nid, &'static str)> {
+        match self {
+            Algorithm::ES256 => Some((Nid::X9_62_PRIME256V1, "P-256")),
+            Algorithm::ES384 => Some((Nid::SECP384R1, "P-384")),
+            Algorithm::ES512 => Some((Nid::SECP521R1, "P-521")),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum KeyError {
+    Io(std::io::Error),
+    OpenSsl(openssl::error::ErrorStack),
+    /// HMAC secrets must not be empty
+    EmptySecret,
+    /// The key doesn't fit the algorithm, e.g. an RSA key for ES256
+    WrongKeyType(Algorithm),
+    /// Signing needs a private key
+    NoPrivateKey,
+}
+
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyError::Io(e) => write!(f, "Couldn't read key: {}", e),
+            KeyError::OpenSsl(e) => write!(f, "Invalid key: {}", e),
+            KeyError::EmptySecret => write!(f, "The HMAC secret is empty"),
+            KeyError::WrongKeyType(algorithm) => {
+                write!(f, "The key can't be used with {}", algorithm.name())
+            }
+            KeyError::NoPrivateKey => write!(f, "Signing needs a private key"),
+        }
+    }
+}
+
+impl From<std::io::Error> for KeyError {
+    fn from(val: std::io::Error) -> KeyError {
+        KeyError::Io(val)
+    }
+}
+
+impl From<openssl::error::ErrorStack> for KeyError {
+    fn from(val: openssl::error::ErrorStack) -> KeyError {
+        KeyError::OpenSsl(val)
+    }
+}
+
+/// Reason for refusing a token before looking at its claims
+#[derive(Debug, PartialEq)]
+pub enum VerifyError {
+    /// The token isn't three base64 encoded parts with JSON header and claims
+    Malformed,
+    UnknownKey,
+    WrongAlgorithm,
+    InvalidSignature,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::Malformed => write!(f, "The token could not be decoded"),
+            VerifyError::UnknownKey => write!(f, "The key of the token is unknown"),
+            VerifyError::WrongAlgorithm => write!(f, "The token has the wrong algorithm"),
+            VerifyError::InvalidSignature => write!(f, "The signature of the token is invalid"),
+        }
+    }
+}
+
+#[derive(Clone)]
+enum KeyMaterial {
+    Secret(Vec<u8>),
+    Public(PKey<Public>),
+    Private(PKey<Private>),
+}
+
+/// A key for signing or verifying tokens
+#[derive(Clone)]
+pub struct JwtKey {
+    /// Sent as `kid` header, tokens without `kid` are verified with a key without id
+    pub kid: Option<String>,
+    pub algorithm: Algorithm,
+    material: KeyMaterial,
+}
+
+fn encode(data: &[u8]) -> String {
+    base64::encode_config(data, base64::URL_SAFE_NO_PAD)
+}
+
+fn decode_json(part: &str) -> Result<Value, VerifyError> {
+    let bytes =
+        base64::decode_config(part, base64::URL_SAFE_NO_PAD).map_err(|_| VerifyError::Malformed)?;
+    serde_json::from_slice(&bytes).map_err(|_| VerifyError::Malformed)
+}
+
+/// Big-endian bytes of a number, left padded to the given length
+fn padded(number: &BigNumRef, length: usize) -> Vec<u8> {
+    let mut bytes = vec![0; length.saturating_sub(number.num_bytes() as usize)];
+    bytes.extend(number.to_vec());
+    bytes
+}
+
+impl JwtKey {
+    pub fn secret(
+        kid: Option<String>,
+        algorithm: Algorithm,
+        secret: &str,
+    ) -> Result<Self, KeyError> {
+        if !algorithm.is_hmac() {
+            return Err(KeyError::WrongKeyType(algorithm));
+        }
+        if secret.is_empty() {
+            return Err(KeyError::EmptySecret);
+        }
+        Ok(JwtKey {
+            kid,
+            algorithm,
+            material: KeyMaterial::Secret(secret.as_bytes().to_vec()),
+        })
+    }
+
+    /// Reads a PEM encoded private key, which can sign and verify
+    pub fn private_pem(
+        kid: Option<String>,
+        algorithm: Algorithm,
+        pem: &[u8],
+    ) -> Result<Self, KeyError> {
+        let key = PKey::private_key_from_pem(pem)?;
+        Self::check_type(
+            algorithm,
+            key.id(),
+            key.ec_key().ok().map(|key| key.group().curve_name()),
+        )?;
+        Ok(JwtKey {
+            kid,
+            algorithm,
+            material: KeyMaterial::Private(key),
+        })
+    }
+
+    /// Reads a PEM encoded public key, which can only verify
+    pub fn public_pem(
+        kid: Option<String>,
+        algorithm: Algorithm,
+        pem: &[u8],
+    ) -> Result<Self, KeyError> {
+        let key = PKey::public_key_from_pem(pem)?;
+        Self::check_type(
+            algorithm,
+            key.id(),
+            key.ec_key().ok().map(|key| key.group().curve_name()),
+        )?;
+        Ok(JwtKey {
+            kid,
+            algorithm,
+            material: KeyMaterial::Public(key),
+        })
+    }
+
+    pub fn private_pem_file<P: AsRef<Path>>(
+        kid: Option<String>,
+        algorithm: Algorithm,
+        path: P,
+    ) -> Result<Self, KeyError> {
+        Self::private_pem(kid, algorithm, &fs::read(path)?)
+    }
+
+    pub fn public_pem_file<P: AsRef<Path>>(
+        kid: Option<String>,
+        algorithm: Algorithm,
+        path: P,
+    ) -> Result<Self, KeyError> {
+        Self::public_pem(kid, algorithm, &fs::read(path)?)
+    }
+
+    fn check_type(
+        algorithm: Algorithm,
+        id: Id,
+        curve: Option<Option<Nid>>,
+    ) -> Result<(), KeyError> {
+        let fits = match algorithm {
+            Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512 => id == Id::RSA,
+            Algorithm::ES256 | Algorithm::ES384 | Algorithm::ES512 => {
+                id == Id::EC && curve.flatten() == algorithm.curve().map(|(nid, _)| nid)
+            }
+            Algorithm::EdDSA => id == Id::ED25519,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => false,
+        };
+        if fits {
+            Ok(())
+        } else {
+            Err(KeyError::WrongKeyType(algorithm))
+        }
+    }
+
+    pub fn can_sign(&self) -> bool {
+        !matches!(self.material, KeyMaterial::Public(_))
+    }
+
+    /// Creates a signed token with the claims
+    pub fn sign(&self, claims: &Value) -> Result<String, KeyError> {
+        let mut header = json!({ "alg": self.algorithm.name(), "typ": "JWT" });
+        if let Some(kid) = &self.kid {
+            header["kid"] = json!(kid);
+        }
+        let input = format!(
+            "{}.{}",
+            encode(header.to_string().as_bytes()),
+            encode(claims.to_string().as_bytes())
+        );
+        let signature = match &self.material {
+            KeyMaterial::Secret(secret) => {
+                let key = PKey::hmac(secret)?;
+                let mut signer = Signer::new(self.algorithm.digest().unwrap(), &key)?;
+                signer.sign_oneshot_to_vec(input.as_bytes())?
+            }
+            KeyMaterial::Private(key) => match self.algorithm.digest() {
+                Some(digest) => {
+                    let mut signer = Signer::new(digest, key)?;
+                    let signature = signer.sign_oneshot_to_vec(input.as_bytes())?;
+                    match self.algorithm.curve() {
+                        // JWS uses the concatenated coordinates instead of DER
+                        Some(_) => {
+                            let signature = EcdsaSig::from_der(&signature)?;
+                            let length = (key.bits() as usize).div_ceil(8);
+                            let mut raw = padded(signature.r(), length);
+                            raw.extend(padded(signature.s(), length));
+                            raw
+                        }
+                        None => signature,
+                    }
+                }
+                None => Signer::new_without_digest(key)?.sign_oneshot_to_vec(input.as_bytes())?,
+            },
+            KeyMaterial::Public(_) => return Err(KeyError::NoPrivateKey),
+        };
+        Ok(format!("{}.{}", input, encode(&signature)))
+    }
+
+    fn verify_signature(
+        &self,
+        input: &[u8],
+        signature: &[u8],
+    ) -> Result<bool, openssl::error::ErrorStack> {
+        match &self.material {
+            KeyMaterial::Secret(secret) => {
+                let key = PKey::hmac(secret)?;
+                let expected = Signer::new(self.algorithm.digest().unwrap(), &key)?
+                    .sign_oneshot_to_vec(input)?;
+                Ok(expected.len() == signature.len() && memcmp::eq(&expected, signature))
+            }
+            KeyMaterial::Public(key) => verify_asymmetric(self.algorithm, key, input, signature),
+            KeyMaterial::Private(key) => verify_asymmetric(self.algorithm, key, input, signature),
+        }
+    }
+
+    /// The public key as JWK, nothing for secrets
+    pub fn jwk(&self) -> Option<Value> {
+        let result = (|| -> Result<Option<Value>, openssl::error::ErrorStack> {
+            let mut jwk = match &self.material {
+                KeyMaterial::Secret(_) => return Ok(None),
+                KeyMaterial::Public(key) => public_parameters(self.algorithm, key)?,
+                KeyMaterial::Private(key) => public_parameters(self.algorithm, key)?,
+            };
+            jwk["alg"] = json!(self.algorithm.name());
+            jwk["use"] = json!("sig");
+            if let Some(kid) = &self.kid {
+                jwk["kid"] = json!(kid);
+            }
+            Ok(Some(jwk))
+        })();
+        result.unwrap_or_else(|e| {
+            log::error!("Couldn't export public key: {}", e);
+            None
+        })
+    }
+}
+
+fn verify_asymmetric<T: openssl::pkey::HasPublic>(
+    algorithm: Algorithm,
+    key: &PKey<T>,
+    input: &[u8],
+    signature: &[u8],
+) -> Result<bool, openssl::error::ErrorStack> {
+    match algorithm.digest() {
+        Some(digest) => {
+            let signature = match algorithm.curve() {
+                Some(_) => {
+                    let length = (key.bits() as usize).div_ceil(8);
+                    if signature.len() != 2 * length {
+                        return Ok(false);
+                    }
+                    EcdsaSig::from_private_components(
+                        BigNum::from_slice(&signature[..length])?,
+                        BigNum::from_slice(&signature[length..])?,
+                    )?
+                    .to_der()?
+                }
+                None => signature.to_vec(),
+            };
+            Verifier::new(digest, key)?.verify_oneshot(&signature, input)
+        }
+        None => Verifier::new_without_digest(key)?.verify_oneshot(signature, input),
+    }
+}
+
+fn public_parameters<T: openssl::pkey::HasPublic>(
+    algorithm: Algorithm,
+    key: &PKey<T>,
+) -> Result<Value, openssl::error::ErrorStack> {
+    Ok(match algorithm.curve() {
+        Some((_, curve)) => {
+            let ec_key: EcKey<T> = key.ec_key()?;
+            let mut x = BigNum::new()?;
+            let mut y = BigNum::new()?;
+            let mut context = BigNumContext::new()?;
+            ec_key
+                .public_key()
+                .affine_coordinates(ec_key.group(), &mut x, &mut y, &mut context)?;
+            let length = (key.bits() as usize).div_ceil(8);
+            json!({
+                "kty": "EC",
+                "crv": curve,
+                "x": encode(&padded(&x, length)),
+                "y": encode(&padded(&y, length)),
+            })
+        }
+        None if algorithm == Algorithm::EdDSA => json!({
+            "kty": "OKP",
+            "crv": "Ed25519",
+            "x": encode(&key.raw_public_key()?),
+        }),
+        None => {
+            let rsa = key.rsa()?;
+            json!({
+                "kty": "RSA",
+                "n": encode(&rsa.n().to_vec()),
+                "e": encode(&rsa.e().to_vec()),
+            })
+        }
+    })
+}
+
+/// The keys of a deployment: the first one able to sign is used for new tokens, all of
+/// them verify. Keeping old public keys around lets tokens of a rotated key expire.
+#[derive(Clone)]
+pub struct JwtKeys {
+    keys: Vec<JwtKey>,
+}
+
+impl JwtKeys {
+    pub fn new(keys: Vec<JwtKey>) -> Self {
+        JwtKeys { keys }
+    }
+
+    pub fn signing_key(&self) -> Option<&JwtKey> {
+        self.keys.iter().find(|key| key.can_sign())
+    }
+
+    pub fn sign(&self, claims: &Value) -> Result<String, KeyError> {
+        self.signing_key()
+            .ok_or(KeyError::NoPrivateKey)?
+            .sign(claims)
+    }
+
+    /// Checks the signature with the key named by `kid`, returns header and claims
+    pub fn verify(&self, token: &str) -> Result<(Value, Value), VerifyError> {
+        let parts = token.split('.').collect::<Vec<_>>();
+        if parts.len() != 3 {
+            return Err(VerifyError::Malformed);
+        }
+        let header = decode_json(parts[0])?;
+        let kid = match header.get("kid") {
+            None => None,
+            Some(Value::String(kid)) => Some(kid.as_str()),
+            Some(_) => return Err(VerifyError::Malformed),
+        };
+        let key = self
+            .keys
+            .iter()
+            .find(|key| key.kid.as_deref() == kid)
+            .ok_or(VerifyError::UnknownKey)?;
+        // the algorithm is fixed by the key, so tokens can't pick a weaker one
+        if header.get("alg").and_then(Value::as_str) != Some(key.algorithm.name()) {
+            return Err(VerifyError::WrongAlgorithm);
+        }
+        let signature = base64::decode_config(parts[2], base64::URL_SAFE_NO_PAD)
+            .map_err(|_| VerifyError::Malformed)?;
+        let input = format!("{}.{}", parts[0], parts[1]);
+        match key.verify_signature(input.as_bytes(), &signature) {
+            Ok(true) => Ok((header, decode_json(parts[1])?)),
+            Ok(false) => Err(VerifyError::InvalidSignature),
+            Err(e) => {
+                log::debug!("Couldn't verify signature: {}", e);
+                Err(VerifyError::InvalidSignature)
+            }
+        }
+    }
+
+    /// The public keys as JSON Web Key Set
+    pub fn jwks(&self) -> Value {
+        json!({ "keys": self.keys.iter().filter_map(JwtKey::jwk).collect::<Vec<_>>() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::{ec::EcGroup, rsa::Rsa};
+
+    fn claims() -> Value {
+        json!({ "sub": "user", "exp": 1_600_000_000 })
+    }
+
+    fn private_pem(algorithm: Algorithm) -> Vec<u8> {
+        let key = match algorithm.curve() {
+            Some((nid, _)) => {
+                PKey::from_ec_key(EcKey::generate(&EcGroup::from_curve_name(nid).unwrap()).unwrap())
+                    .unwrap()
+            }
+            None if algorithm == Algorithm::EdDSA => PKey::generate_ed25519().unwrap(),
+            None => PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap(),
+        };
+        key.private_key_to_pem_pkcs8().unwrap()
+    }
+
+    fn public_pem(private_pem: &[u8]) -> Vec<u8> {
+        PKey::private_key_from_pem(private_pem)
+            .unwrap()
+            .public_key_to_pem()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_hmac() {
+        let keys = JwtKeys::new(vec![
+            JwtKey::secret(None, Algorithm::HS512, "secret").unwrap()
+        ]);
+        let token = keys.sign(&claims()).unwrap();
+        assert_eq!(keys.verify(&token).unwrap().1, claims());
+        let other = JwtKeys::new(vec![
+            JwtKey::secret(None, Algorithm::HS512, "other").unwrap()
+        ]);
+        assert_eq!(other.verify(&token), Err(VerifyError::InvalidSignature));
+        assert!(keys.jwks()["keys"].as_array().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_empty_secret() {
+        assert!(matches!(
+            JwtKey::secret(None, Algorithm::HS256, ""),
+            Err(KeyError::EmptySecret)
+        ));
+    }
+
+    #[test]
+    fn test_asymmetric() {
+        for algorithm in &[
+            Algorithm::RS256,
+            Algorithm::ES256,
+            Algorithm::ES384,
+            Algorithm::ES512,
+            Algorithm::EdDSA,
+        ] {
+            let pem = private_pem(*algorithm);
+            let signing = JwtKeys::new(vec![JwtKey::private_pem(
+                Some("new".to_owned()),
+                *algorithm,
+                &pem,
+            )
+            .unwrap()]);
+            let token = signing.sign(&claims()).unwrap();
+            let (header, verified) = signing.verify(&token).unwrap();
+            assert_eq!(header["kid"], "new");
+            assert_eq!(verified, claims());
+
+            // verifying only needs the public key
+            let verifying = JwtKeys::new(vec![JwtKey::public_pem(
+                Some("new".to_owned()),
+                *algorithm,
+                &public_pem(&pem),
+            )
+            .unwrap()]);
+            assert_eq!(verifying.verify(&token).unwrap().1, claims());
+            assert!(matches!(
+                verifying.sign(&claims()),
+                Err(KeyError::NoPrivateKey)
+            ));
+
+            let jwk = &signing.jwks()["keys"][0];
+            assert_eq!(jwk["alg"], algorithm.name());
+            assert_eq!(jwk["kid"], "new");
+            assert_eq!(jwk, &verifying.jwks()["keys"][0]);
+
+            let mut tampered = token.clone();
+            tampered.insert(token.find('.').unwrap() + 1, 'e');
+            assert!(verifying.verify(&tampered).is_err());
+        }
+    }
+
+    #[test]
+    fn test_rotation() {
+        let old_pem = private_pem(Algorithm::ES256);
+        let old = JwtKeys::new(vec![JwtKey::private_pem(
+            Some("old".to_owned()),
+            Algorithm::ES256,
+            &old_pem,
+        )
+        .unwrap()]);
+        let old_token = old.sign(&claims()).unwrap();
+        let keys = JwtKeys::new(vec![
+            JwtKey::private_pem(
+                Some("new".to_owned()),
+                Algorithm::EdDSA,
+                &private_pem(Algorithm::EdDSA),
+            )
+            .unwrap(),
+            JwtKey::public_pem(
+                Some("old".to_owned()),
+                Algorithm::ES256,
+                &public_pem(&old_pem),
+            )
+            .unwrap(),
+        ]);
+        assert_eq!(keys.signing_key().unwrap().kid.as_deref(), Some("new"));
+        assert!(keys.verify(&old_token).is_ok());
+        assert!(keys.verify(&keys.sign(&claims()).unwrap()).is_ok());
+        assert_eq!(keys.jwks()["keys"].as_array().unwrap().len(), 2);
+
+        let unknown = JwtKeys::new(vec![JwtKey::private_pem(
+            Some("other".to_owned()),
+            Algorithm::ES256,
+            &old_pem,
+        )
+        .unwrap()]);
+        assert_eq!(unknown.verify(&old_token), Err(VerifyError::UnknownKey));
+    }
+
+    #[test]
+    fn test_wrong_algorithm() {
+        let pem = private_pem(Algorithm::RS256);
+        assert!(matches!(
+            JwtKey::private_pem(None, Algorithm::ES256, &pem),
+            Err(KeyError::WrongKeyType(Algorithm::ES256))
+        ));
+        // a token claiming HS256 must not be checked with the public key as secret
+        let public = public_pem(&pem);
+        let forged = JwtKeys::new(vec![JwtKey::secret(
+            None,
+            Algorithm::HS256,
+            std::str::from_utf8(&public).unwrap(),
+        )
+        .unwrap()])
+        .sign(&claims())
+        .unwrap();
+        let keys = JwtKeys::new(vec![
+            JwtKey::public_pem(None, Algorithm::RS256, &public).unwrap()
+        ]);
+        assert_eq!(keys.verify(&forged), Err(VerifyError::WrongAlgorithm));
+        assert_eq!(keys.verify("abc"), Err(VerifyError::Malformed));
+    }
+}
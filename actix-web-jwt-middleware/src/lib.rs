@@ -1,20 +1,39 @@
+mod cookies;
+mod error;
+mod extract;
+mod keys;
 mod middleware;
-pub use middleware::{Algorithm, JwtAuthentication};
-use std::path::PathBuf;
-
-#[derive(Clone)]
-pub enum JwtKey {
-    Inline(String),
-    File(PathBuf),
-}
+mod policy;
+mod validation;
+pub use cookies::{CookieConfig, TokenSource};
+pub use error::AuthError;
+pub use extract::Subject;
+pub use keys::{Algorithm, JwtKey, JwtKeys, KeyError, VerifyError};
+pub use middleware::{JwtAuthentication, RevocationCheck};
+pub use policy::{Policy, Requirement};
+pub use validation::{Validation, ValidationError};
 
 pub struct AuthenticationData {
     pub header: serde_json::Value,
     pub claims: Claims,
 }
 
+/// The claims of a verified token, also usable as an extractor in handlers
+#[derive(Clone, Debug)]
 pub struct Claims {
     pub sub: Option<String>,
     pub exp: Option<i64>,
+    pub iat: Option<i64>,
+    pub jti: Option<String>,
     pub all: serde_json::Value,
 }
+
+impl Claims {
+    /// Whether the space separated `scope` claim contains the scope
+    pub fn has_scope(&self, scope: &str) -> bool {
+        match self.all.get("scope").and_then(serde_json::Value::as_str) {
+            Some(scopes) => scopes.split_whitespace().any(|other| other == scope),
+            None => false,
+        }
+    }
+}
@@ -1,27 +1,38 @@
-use crate::JwtKey;
+use crate::cookies::{CookieConfig, TokenSource};
+use crate::error::AuthError;
+use crate::keys::JwtKeys;
+use crate::validation::{self, Validation};
+use crate::{AuthenticationData, Claims};
 use actix_web::{
     dev::{Service, ServiceRequest, ServiceResponse, Transform},
     Error, HttpMessage,
-    http::Method,
 };
-use chrono::{TimeZone, Utc};
+use chrono::Utc;
 use futures::{
-    future::{ok, Either, FutureResult},
+    future::{ok, FutureResult},
     Poll,
 };
 use lazy_static::lazy_static;
 use regex::Regex;
-pub use frank_jwt::Algorithm;
+use std::{rc::Rc, sync::Arc};
 
-/// JWT based authentication middleware for actix-web
+/// JWT based authentication middleware for actix-web. It only verifies tokens and attaches
+/// their claims to the request, the `Policy` of a route decides whether it needs one.
 #[derive(Clone)]
 pub struct JwtAuthentication {
-    /// The keys used for verifying the tokens
-    pub key: JwtKey,
-    /// The algorithm used for verifying the tokens
-    pub algorithm: Algorithm,
-    /// Regexes to match paths and a list of methods on those that do not need authentication
-    pub except: Vec<(Regex, Vec<Method>)>,
+    /// The keys used for verifying the tokens, chosen by the `kid` header
+    pub keys: Arc<JwtKeys>,
+    /// The checks done on the claims of valid tokens
+    pub validation: Validation,
+    /// Asked about every token that passed the other checks
+    pub revocation: Option<Rc<dyn RevocationCheck>>,
+    /// Whether tokens are read from the header, a cookie or both
+    pub source: TokenSource,
+}
+
+/// Lets the application reject tokens it has revoked before they expire
+pub trait RevocationCheck {
+    fn is_revoked(&self, req: &ServiceRequest, claims: &Claims) -> bool;
 }
 
 impl<S, B> Transform<S> for JwtAuthentication
@@ -39,18 +50,14 @@ where
 
     fn new_transform(&self, service: S) -> Self::Future {
         ok(JwtAuthenticationMiddleware {
-            key: self.key.clone(),
-            algorithm: self.algorithm,
-            except: self.except.clone(),
+            settings: self.clone(),
             service: service,
         })
     }
 }
 
 pub struct JwtAuthenticationMiddleware<S> {
-    key: JwtKey,
-    algorithm: Algorithm,
-    except: Vec<(Regex, Vec<Method>)>,
+    settings: JwtAuthentication,
     service: S,
 }
 
@@ -63,85 +70,81 @@ where
     type Request = ServiceRequest;
     type Response = ServiceResponse<B>;
     type Error = Error;
-    type Future = futures::future::Either<FutureResult<Self::Response, Self::Error>, S::Future>;
+    type Future = S::Future;
 
     fn poll_ready(&mut self) -> Poll<(), Self::Error> {
         self.service.poll_ready()
     }
 
     fn call(&mut self, req: ServiceRequest) -> Self::Future {
-        for (reg, methods) in self.except.clone() {
-            if reg.is_match(req.path()) && methods.contains(req.method()) {
-                return Either::B(self.service.call(req));
-            }
-        }
-        let token = match get_token(&req) {
-            Ok(token) => token,
+        match self.settings.authenticate(&req) {
+            Ok(auth_data) => req.extensions_mut().insert(auth_data),
+            Err(AuthError::Missing) => (),
             Err(error) => {
-                log::debug!("Could not extract token from request: {}", error);
-                return Either::A(ok(req.into_response(
-                    actix_web::HttpResponse::Unauthorized().finish().into_body(),
-                )));
+                log::debug!("Rejected token: {}", error);
+                req.extensions_mut().insert(error);
             }
-        };
+        }
+        self.service.call(req)
+    }
+}
 
-        match dbg!(match &self.key {
-            JwtKey::Inline(key) => frank_jwt::decode(&token, key, self.algorithm),
-            JwtKey::File(key) => frank_jwt::decode(&token, key, self.algorithm),
-        }) {
-            Ok((header, claims)) => {
-                //TODO: frank_jwt does not validate things yet,
-                //we should either validate things here or patch frank_jwt
-                let auth_data = crate::AuthenticationData {
-                    header: header,
-                    claims: crate::Claims {
-                        all: claims.clone(),
-                        sub: match claims.clone() {
-                            serde_json::Value::Object(map) => match map.get("sub") {
-                                Some(sub) => match sub {
-                                    serde_json::Value::String(sub) => Some(sub.to_owned()),
-                                    _ => None,
-                                },
-                                _ => None,
-                            },
-                            _ => None,
-                        },
-                        exp: match claims {
-                            serde_json::Value::Object(map) => match map.get("exp") {
-                                Some(exp) => match exp {
-                                    serde_json::Value::Number(exp) => exp.as_i64(),
-                                    _ => None,
-                                },
-                                _ => None,
-                            },
-                            _ => None,
-                        },
-                    },
-                };
-                match auth_data.claims.exp {
-                    Some(exp) => {
-                        if Utc.timestamp(exp, 0) > Utc::now() {
-                            return Either::A(ok(req.into_response(
-                                actix_web::HttpResponse::Unauthorized().finish().into_body(),
-                            )));
-                        }
-                    }
-                    _ => (),
-                }
-                req.extensions_mut().insert(auth_data);
-                Either::B(self.service.call(req))
-            }
-            Err(error) => {
-                log::debug!("Could not decode token: {}", error);
-                Either::A(ok(req.into_response(
-                    actix_web::HttpResponse::Unauthorized().finish().into_body(),
-                )))
+impl JwtAuthentication {
+    fn authenticate(&self, req: &ServiceRequest) -> Result<AuthenticationData, AuthError> {
+        let token = get_token(req, &self.source)?;
+        let (header, claims) = self
+            .keys
+            .verify(&token)
+            .map_err(|error| AuthError::InvalidToken(error.to_string()))?;
+        validation::validate(&claims, &self.validation, Utc::now().timestamp())
+            .map_err(|error| AuthError::InvalidToken(error.to_string()))?;
+        let auth_data = AuthenticationData {
+            header: header,
+            claims: Claims {
+                sub: claims
+                    .get("sub")
+                    .and_then(serde_json::Value::as_str)
+                    .map(str::to_owned),
+                exp: claims.get("exp").and_then(serde_json::Value::as_i64),
+                iat: claims.get("iat").and_then(serde_json::Value::as_i64),
+                jti: claims
+                    .get("jti")
+                    .and_then(serde_json::Value::as_str)
+                    .map(str::to_owned),
+                all: claims,
+            },
+        };
+        if let Some(revocation) = &self.revocation {
+            if revocation.is_revoked(req, &auth_data.claims) {
+                return Err(AuthError::InvalidToken(
+                    "The token has been revoked".to_owned(),
+                ));
             }
         }
+        Ok(auth_data)
+    }
+}
+
+fn get_token(req: &ServiceRequest, source: &TokenSource) -> Result<String, AuthError> {
+    match source {
+        TokenSource::Header => get_header_token(req),
+        TokenSource::Cookie(config) => get_cookie_token(req, config),
+        TokenSource::Both(config) => match get_header_token(req) {
+            Err(AuthError::Missing) => get_cookie_token(req, config),
+            result => result,
+        },
     }
 }
 
-fn get_token(req: &ServiceRequest) -> Result<String, String> {
+fn get_cookie_token(req: &ServiceRequest, config: &CookieConfig) -> Result<String, AuthError> {
+    let token = req.cookie(&config.token_cookie).ok_or(AuthError::Missing)?;
+    if !config.csrf_valid(req.method(), req) {
+        return Err(AuthError::Csrf);
+    }
+    Ok(token.value().to_owned())
+}
+
+fn get_header_token(req: &ServiceRequest) -> Result<String, AuthError> {
     match req.headers().get(actix_web::http::header::AUTHORIZATION) {
         Some(header_value) => {
             lazy_static! {
@@ -150,16 +153,321 @@ fn get_token(req: &ServiceRequest) -> Result<String, String> {
             match RE.captures(match header_value.to_str() {
                 Ok(header) => header,
                 Err(_) => {
-                    return Err("Header contains non-ASCII characters".to_string());
+                    return Err(AuthError::InvalidRequest(
+                        "The authorization header contains non-ASCII characters",
+                    ));
                 }
             }) {
                 Some(capture) => match capture.get(1) {
                     Some(matched) => Ok(String::from(matched.as_str())),
-                    None => Err("Couldn't find token in authorization header.".to_owned()),
+                    None => Err(AuthError::InvalidRequest(
+                        "Couldn't find token in authorization header",
+                    )),
                 },
-                None => Err("Invalid authorization header.".to_owned()),
+                None => Err(AuthError::InvalidRequest("Invalid authorization header")),
             }
         }
-        None => Err("No authorization header supplied.".to_owned()),
+        None => Err(AuthError::Missing),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::{Algorithm, JwtKey};
+    use crate::policy::{Policy, Requirement};
+    use crate::Subject;
+    use actix_web::{
+        http::header::{AUTHORIZATION, WWW_AUTHENTICATE},
+        http::{Method, StatusCode},
+        test, web, App, HttpResponse,
+    };
+    use serde_json::json;
+
+    const KEY: &str = "secret";
+
+    /// Revokes the token with the id `revoked`
+    struct RevokedJti;
+
+    impl RevocationCheck for RevokedJti {
+        fn is_revoked(&self, _: &ServiceRequest, claims: &Claims) -> bool {
+            claims.jti.as_deref() == Some("revoked")
+        }
+    }
+
+    fn call_with(source: TokenSource, req: test::TestRequest) -> ServiceResponse {
+        call_policy(Policy::required(), source, req)
+    }
+
+    /// Calls a resource with the policy, which answers with the subject of the token
+    fn call_policy(policy: Policy, source: TokenSource, req: test::TestRequest) -> ServiceResponse {
+        let mut app = test::init_service(
+            App::new()
+                .wrap(JwtAuthentication {
+                    keys: keys(KEY),
+                    validation: Validation::default(),
+                    revocation: Some(Rc::new(RevokedJti)),
+                    source,
+                })
+                .service(
+                    web::resource("/")
+                        .wrap(policy)
+                        .route(web::get().to(subject))
+                        .route(web::post().to(subject)),
+                )
+                .service(web::resource("/claims").route(web::get().to(|sub: Subject| sub.0))),
+        );
+        test::call_service(&mut app, req.to_request())
+    }
+
+    fn subject(claims: Option<Claims>) -> HttpResponse {
+        HttpResponse::Ok().body(claims.and_then(|claims| claims.sub).unwrap_or_default())
+    }
+
+    fn get(path: &str, authorization: Option<String>) -> test::TestRequest {
+        let mut req = test::TestRequest::get().uri(path);
+        if let Some(authorization) = authorization {
+            req = req.header(AUTHORIZATION, authorization);
+        }
+        req
+    }
+
+    fn body(res: ServiceResponse) -> String {
+        String::from_utf8(test::read_body(res).to_vec()).unwrap()
+    }
+
+    fn call(authorization: Option<String>) -> ServiceResponse {
+        call_with(TokenSource::Header, get("/", authorization))
+    }
+
+    /// Request with the token and CSRF cookies, and the given CSRF header
+    fn cookie_request(method: Method, csrf_header: Option<&str>) -> test::TestRequest {
+        let config = CookieConfig::default();
+        let token = keys(KEY).sign(&json!({ "sub": "user" })).unwrap();
+        let mut req = test::TestRequest::default()
+            .method(method)
+            .uri("/")
+            .cookie(config.cookie(&config.token_cookie, &token, "/", 60, true))
+            .cookie(config.cookie(&config.csrf_cookie, "csrf", "/", 60, false));
+        if let Some(csrf_header) = csrf_header {
+            req = req.header(config.csrf_header.as_str(), csrf_header);
+        }
+        req
+    }
+
+    fn keys(secret: &str) -> Arc<JwtKeys> {
+        Arc::new(JwtKeys::new(vec![JwtKey::secret(
+            None,
+            Algorithm::HS512,
+            secret,
+        )
+        .unwrap()]))
+    }
+
+    fn bearer(secret: &str, claims: serde_json::Value) -> Option<String> {
+        Some(format!("Bearer {}", keys(secret).sign(&claims).unwrap()))
+    }
+
+    fn challenge(res: &ServiceResponse) -> &str {
+        res.headers()
+            .get(WWW_AUTHENTICATE)
+            .unwrap()
+            .to_str()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_valid_token() {
+        let now = Utc::now().timestamp();
+        let res = call(bearer(
+            KEY,
+            json!({ "sub": "user", "iat": now, "exp": now + 60 }),
+        ));
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_expired_token() {
+        let now = Utc::now().timestamp();
+        let res = call(bearer(KEY, json!({ "sub": "user", "exp": now - 3600 })));
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            challenge(&res),
+            "Bearer error=\"invalid_token\", error_description=\"The token has expired\""
+        );
+    }
+
+    #[test]
+    fn test_revoked_token() {
+        let res = call(bearer(KEY, json!({ "sub": "user", "jti": "active" })));
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = call(bearer(KEY, json!({ "sub": "user", "jti": "revoked" })));
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            challenge(&res),
+            "Bearer error=\"invalid_token\", error_description=\"The token has been revoked\""
+        );
+    }
+
+    #[test]
+    fn test_cookie() {
+        let source = TokenSource::Cookie(CookieConfig::default());
+        let res = call_with(source.clone(), cookie_request(Method::GET, None));
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = call_with(source.clone(), cookie_request(Method::POST, Some("csrf")));
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = call_with(source.clone(), cookie_request(Method::POST, None));
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let res = call_with(source.clone(), cookie_request(Method::POST, Some("other")));
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        // the header is ignored in cookie mode
+        let res = call_with(
+            source,
+            test::TestRequest::get().uri("/").header(
+                AUTHORIZATION,
+                bearer(KEY, json!({ "sub": "user" })).unwrap(),
+            ),
+        );
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_header_and_cookie() {
+        let source = TokenSource::Both(CookieConfig::default());
+        // requests with header can't be forged by other sites, so they need no CSRF token
+        let res = call_with(
+            source.clone(),
+            test::TestRequest::post().uri("/").header(
+                AUTHORIZATION,
+                bearer(KEY, json!({ "sub": "user" })).unwrap(),
+            ),
+        );
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = call_with(source.clone(), cookie_request(Method::POST, Some("csrf")));
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = call_with(source, cookie_request(Method::POST, None));
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        // cookies are ignored in header mode
+        let res = call_with(TokenSource::Header, cookie_request(Method::GET, None));
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_wrong_signature() {
+        let res = call(bearer("other", json!({ "sub": "user" })));
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            challenge(&res),
+            "Bearer error=\"invalid_token\", error_description=\"The signature of the token is invalid\""
+        );
+    }
+
+    #[test]
+    fn test_missing_token() {
+        let res = call(None);
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(challenge(&res), "Bearer");
+
+        let res = call(Some("Basic dXNlcjpwdw==".to_owned()));
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert!(challenge(&res).starts_with("Bearer error=\"invalid_request\""));
+    }
+
+    #[test]
+    fn test_optional_policy() {
+        let call = |authorization| {
+            call_policy(
+                Policy::optional(),
+                TokenSource::Header,
+                get("/", authorization),
+            )
+        };
+        let res = call(None);
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(body(res), "");
+        let res = call(bearer(KEY, json!({ "sub": "user" })));
+        assert_eq!(body(res), "user");
+        // a bad token is still an error, so clients notice it has to be refreshed
+        let res = call(bearer("other", json!({ "sub": "user" })));
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_anonymous_policy() {
+        let call = |authorization| {
+            call_policy(
+                Policy::anonymous(),
+                TokenSource::Header,
+                get("/", authorization),
+            )
+        };
+        let res = call(bearer("other", json!({ "sub": "user" })));
+        assert_eq!(res.status(), StatusCode::OK);
+        // the claims are not attached either
+        let res = call(bearer(KEY, json!({ "sub": "user" })));
+        assert_eq!(body(res), "");
+    }
+
+    #[test]
+    fn test_method_policy() {
+        let policy = Policy::required().method(Method::GET, Requirement::Anonymous);
+        let res = call_policy(policy.clone(), TokenSource::Header, get("/", None));
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = call_policy(
+            policy,
+            TokenSource::Header,
+            test::TestRequest::post().uri("/"),
+        );
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_scope_policy() {
+        let policy = Policy::required().scope("admin");
+        let res = call_policy(
+            policy.clone(),
+            TokenSource::Header,
+            get(
+                "/",
+                bearer(KEY, json!({ "sub": "user", "scope": "read admin" })),
+            ),
+        );
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = call_policy(
+            policy,
+            TokenSource::Header,
+            get("/", bearer(KEY, json!({ "sub": "user", "scope": "read" }))),
+        );
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        assert_eq!(
+            challenge(&res),
+            "Bearer error=\"insufficient_scope\", error_description=\"The token lacks the admin scope\", scope=\"admin\""
+        );
+    }
+
+    #[test]
+    fn test_extractor() {
+        let call = |authorization| {
+            call_policy(
+                Policy::anonymous(),
+                TokenSource::Header,
+                get("/claims", authorization),
+            )
+        };
+        let res = call(bearer(KEY, json!({ "sub": "user" })));
+        assert_eq!(body(res), "user");
+        let res = call(None);
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(challenge(&res), "Bearer");
+        let res = call(bearer(KEY, json!({ "scope": "read" })));
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        let res = call(bearer(KEY, json!({ "sub": "user", "exp": 0 })));
+        assert_eq!(
+            challenge(&res),
+            "Bearer error=\"invalid_token\", error_description=\"The token has expired\""
+        );
     }
 }
@@ -0,0 +1,149 @@
+use crate::error::AuthError;
+use crate::AuthenticationData;
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::Method,
+    Error, HttpMessage,
+};
+use futures::{
+    future::{ok, Either, FutureResult},
+    Poll,
+};
+
+/// What a route needs from the token of a request
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Requirement {
+    /// Requests without a valid token are rejected
+    Required,
+    /// The claims are attached if there is a token, but invalid tokens are still rejected
+    Optional,
+    /// The token is ignored, e.g. for public pages and logging in
+    Anonymous,
+}
+
+/// Authentication policy of a scope or resource, applied with `wrap` inside an app wrapped
+/// in `JwtAuthentication`. Policies don't nest, so a resource with a policy of its own must
+/// not be inside a scope with one.
+#[derive(Clone, Debug)]
+pub struct Policy {
+    requirement: Requirement,
+    methods: Vec<(Method, Requirement)>,
+    scopes: Vec<String>,
+}
+
+impl Policy {
+    pub fn required() -> Self {
+        Policy::new(Requirement::Required)
+    }
+
+    pub fn optional() -> Self {
+        Policy::new(Requirement::Optional)
+    }
+
+    pub fn anonymous() -> Self {
+        Policy::new(Requirement::Anonymous)
+    }
+
+    fn new(requirement: Requirement) -> Self {
+        Policy {
+            requirement,
+            methods: Vec::new(),
+            scopes: Vec::new(),
+        }
+    }
+
+    /// Uses another requirement for one method, e.g. for public reads of a resource
+    pub fn method(mut self, method: Method, requirement: Requirement) -> Self {
+        self.methods.push((method, requirement));
+        self
+    }
+
+    /// Requires a scope in the `scope` claim of the token, for the methods needing a token
+    pub fn scope(mut self, scope: &str) -> Self {
+        self.scopes.push(scope.to_owned());
+        self
+    }
+
+    pub fn requirement(&self, method: &Method) -> Requirement {
+        self.methods
+            .iter()
+            .find(|(other, _)| other == method)
+            .map(|(_, requirement)| *requirement)
+            .unwrap_or(self.requirement)
+    }
+
+    fn check(&self, req: &ServiceRequest) -> Result<(), AuthError> {
+        let requirement = self.requirement(req.method());
+        if requirement == Requirement::Anonymous {
+            req.extensions_mut().remove::<AuthenticationData>();
+            return Ok(());
+        }
+        let extensions = req.extensions();
+        if let Some(error) = extensions.get::<AuthError>() {
+            return Err(error.clone());
+        }
+        let claims = match extensions.get::<AuthenticationData>() {
+            Some(data) => &data.claims,
+            None if requirement == Requirement::Optional => return Ok(()),
+            None => return Err(AuthError::Missing),
+        };
+        match self.scopes.iter().find(|scope| !claims.has_scope(scope)) {
+            Some(scope) if requirement == Requirement::Required => {
+                Err(AuthError::InsufficientScope(scope.clone()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<S, B> Transform<S> for Policy
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = PolicyMiddleware<S>;
+    type Future = FutureResult<Self::Transform, Self::InitError>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(PolicyMiddleware {
+            policy: self.clone(),
+            service,
+        })
+    }
+}
+
+pub struct PolicyMiddleware<S> {
+    policy: Policy,
+    service: S,
+}
+
+impl<S, B> Service for PolicyMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Either<FutureResult<Self::Response, Self::Error>, S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.service.poll_ready()
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        match self.policy.check(&req) {
+            Ok(()) => Either::B(self.service.call(req)),
+            Err(error) => {
+                log::debug!("Rejected request: {}", error);
+                Either::A(ok(req.error_response(error)))
+            }
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use serde_json::Value;
+use std::fmt;
+
+/// Checks done on the claims of a token after its signature has been verified
+#[derive(Clone, Debug)]
+pub struct Validation {
+    /// Seconds of clock skew tolerated when checking `exp`, `nbf` and `iat`
+    pub leeway: i64,
+    /// Reject tokens without an `exp` claim
+    pub require_exp: bool,
+    /// If set, the `iss` claim has to be equal to this
+    pub issuer: Option<String>,
+    /// If set, the `aud` claim has to be or contain this
+    pub audience: Option<String>,
+}
+
+impl Default for Validation {
+    fn default() -> Self {
+        Validation {
+            leeway: 60,
+            require_exp: false,
+            issuer: None,
+            audience: None,
+        }
+    }
+}
+
+/// Reason for rejecting the claims of a token
+#[derive(Debug, PartialEq)]
+pub enum ValidationError {
+    /// The claims are not a JSON object
+    NoObject,
+    /// A claim has the wrong type, e.g. a string for `exp`
+    InvalidClaim(&'static str),
+    MissingExpiry,
+    Expired,
+    NotYetValid,
+    IssuedInFuture,
+    InvalidIssuer,
+    InvalidAudience,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::NoObject => write!(f, "The claims are not an object"),
+            ValidationError::InvalidClaim(claim) => write!(f, "The {} claim is invalid", claim),
+            ValidationError::MissingExpiry => write!(f, "The token has no expiry"),
+            ValidationError::Expired => write!(f, "The token has expired"),
+            ValidationError::NotYetValid => write!(f, "The token is not valid yet"),
+            ValidationError::IssuedInFuture => write!(f, "The token is issued in the future"),
+            ValidationError::InvalidIssuer => write!(f, "The token has the wrong issuer"),
+            ValidationError::InvalidAudience => write!(f, "The token has the wrong audience"),
+        }
+    }
+}
+
+/// Reads a NumericDate claim, which may have a fractional part
+fn numeric_date(claims: &Value, claim: &'static str) -> Result<Option<i64>, ValidationError> {
+    match claims.get(claim) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::Number(date)) => date
+            .as_i64()
+            .or_else(|| date.as_f64().map(|date| date.floor() as i64))
+            .map(Some)
+            .ok_or(ValidationError::InvalidClaim(claim)),
+        Some(_) => Err(ValidationError::InvalidClaim(claim)),
+    }
+}
+
+/// Validates the registered claims at the time `now`, in seconds since the epoch
+pub fn validate(claims: &Value, validation: &Validation, now: i64) -> Result<(), ValidationError> {
+    if !claims.is_object() {
+        return Err(ValidationError::NoObject);
+    }
+    match numeric_date(claims, "exp")? {
+        Some(exp) if now >= exp + validation.leeway => return Err(ValidationError::Expired),
+        None if validation.require_exp => return Err(ValidationError::MissingExpiry),
+        _ => (),
+    }
+    if let Some(nbf) = numeric_date(claims, "nbf")? {
+        if now < nbf - validation.leeway {
+            return Err(ValidationError::NotYetValid);
+        }
+    }
+    if let Some(iat) = numeric_date(claims, "iat")? {
+        if iat > now + validation.leeway {
+            return Err(ValidationError::IssuedInFuture);
+        }
+    }
+    if let Some(issuer) = &validation.issuer {
+        match claims.get("iss") {
+            Some(Value::String(iss)) if iss == issuer => (),
+            _ => return Err(ValidationError::InvalidIssuer),
+        }
+    }
+    if let Some(audience) = &validation.audience {
+        let matches = match claims.get("aud") {
+            Some(Value::String(aud)) => aud == audience,
+            Some(Value::Array(auds)) => auds
+                .iter()
+                .any(|aud| aud.as_str() == Some(audience.as_str())),
+            _ => false,
+        };
+        if !matches {
+            return Err(ValidationError::InvalidAudience);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const NOW: i64 = 1_567_000_000;
+
+    fn validation() -> Validation {
+        Validation {
+            leeway: 10,
+            ..Validation::default()
+        }
+    }
+
+    #[test]
+    fn test_no_claims() {
+        assert_eq!(validate(&json!({}), &validation(), NOW), Ok(()));
+        assert_eq!(
+            validate(&json!("sub"), &validation(), NOW),
+            Err(ValidationError::NoObject)
+        );
+    }
+
+    #[test]
+    fn test_exp() {
+        let check = |exp: Value| validate(&json!({ "exp": exp }), &validation(), NOW);
+        assert_eq!(check(json!(NOW + 100)), Ok(()));
+        // still accepted within the leeway
+        assert_eq!(check(json!(NOW - 5)), Ok(()));
+        assert_eq!(check(json!(NOW - 10)), Err(ValidationError::Expired));
+        assert_eq!(check(json!(NOW - 100)), Err(ValidationError::Expired));
+        assert_eq!(check(json!((NOW + 100) as f64 + 0.5)), Ok(()));
+        assert_eq!(check(json!(null)), Ok(()));
+        assert_eq!(
+            check(json!("tomorrow")),
+            Err(ValidationError::InvalidClaim("exp"))
+        );
+    }
+
+    #[test]
+    fn test_require_exp() {
+        let validation = Validation {
+            require_exp: true,
+            ..validation()
+        };
+        assert_eq!(
+            validate(&json!({ "sub": "user" }), &validation, NOW),
+            Err(ValidationError::MissingExpiry)
+        );
+        assert_eq!(
+            validate(&json!({ "exp": NOW + 100 }), &validation, NOW),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_nbf() {
+        let check = |nbf: Value| validate(&json!({ "nbf": nbf }), &validation(), NOW);
+        assert_eq!(check(json!(NOW - 100)), Ok(()));
+        assert_eq!(check(json!(NOW + 10)), Ok(()));
+        assert_eq!(check(json!(NOW + 11)), Err(ValidationError::NotYetValid));
+        assert_eq!(
+            check(json!(true)),
+            Err(ValidationError::InvalidClaim("nbf"))
+        );
+    }
+
+    #[test]
+    fn test_iat() {
+        let check = |iat: Value| validate(&json!({ "iat": iat }), &validation(), NOW);
+        assert_eq!(check(json!(NOW - 100)), Ok(()));
+        assert_eq!(check(json!(NOW + 10)), Ok(()));
+        assert_eq!(check(json!(NOW + 11)), Err(ValidationError::IssuedInFuture));
+        assert_eq!(check(json!([])), Err(ValidationError::InvalidClaim("iat")));
+    }
+
+    #[test]
+    fn test_iss() {
+        let validation = Validation {
+            issuer: Some("udb".to_owned()),
+            ..validation()
+        };
+        assert_eq!(validate(&json!({ "iss": "udb" }), &validation, NOW), Ok(()));
+        assert_eq!(
+            validate(&json!({ "iss": "other" }), &validation, NOW),
+            Err(ValidationError::InvalidIssuer)
+        );
+        assert_eq!(
+            validate(&json!({}), &validation, NOW),
+            Err(ValidationError::InvalidIssuer)
+        );
+        // not checked unless configured
+        assert_eq!(
+            validate(&json!({ "iss": "other" }), &self::validation(), NOW),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_aud() {
+        let validation = Validation {
+            audience: Some("api".to_owned()),
+            ..validation()
+        };
+        assert_eq!(validate(&json!({ "aud": "api" }), &validation, NOW), Ok(()));
+        assert_eq!(
+            validate(&json!({ "aud": ["web", "api"] }), &validation, NOW),
+            Ok(())
+        );
+        assert_eq!(
+            validate(&json!({ "aud": ["web"] }), &validation, NOW),
+            Err(ValidationError::InvalidAudience)
+        );
+        assert_eq!(
+            validate(&json!({ "aud": "web" }), &validation, NOW),
+            Err(ValidationError::InvalidAudience)
+        );
+        assert_eq!(
+            validate(&json!({}), &validation, NOW),
+            Err(ValidationError::InvalidAudience)
+        );
+    }
+}
@@ -1,16 +1,38 @@
 use actix_web::{web, App, HttpServer};
+use std::sync::Arc;
 
-use actix_web_jwt_middleware::{Algorithm, JwtAuthentication, JwtKey};
+use actix_web_jwt_middleware::{
+    Algorithm, JwtAuthentication, JwtKey, JwtKeys, Policy, Subject, TokenSource, Validation,
+};
 
 fn main() -> std::io::Result<()> {
     std::env::set_var("RUST_LOG", "actix_web=debug");
     env_logger::init();
 
-    HttpServer::new(|| {
-        App::new().wrap(JwtAuthentication {
-            key: JwtKey::Inline("".to_owned()),
-            algorithm: Algorithm::HS512,
-        })
+    let keys = Arc::new(JwtKeys::new(vec![JwtKey::secret(
+        None,
+        Algorithm::HS512,
+        "secret",
+    )
+    .unwrap()]));
+    HttpServer::new(move || {
+        App::new()
+            .wrap(JwtAuthentication {
+                keys: keys.clone(),
+                validation: Validation::default(),
+                revocation: None,
+                source: TokenSource::Header,
+            })
+            .service(
+                web::resource("/")
+                    .wrap(Policy::required())
+                    .route(web::get().to(|Subject(sub): Subject| format!("Hello {}", sub))),
+            )
+            .service(
+                web::resource("/public")
+                    .wrap(Policy::anonymous())
+                    .route(web::get().to(|| "Hello")),
+            )
     })
     .bind("127.0.0.1:8080")?
     .run()
@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS databases_in_tags;
+DROP TABLE IF EXISTS subtasks_in_tags;
+DROP TABLE IF EXISTS tags;
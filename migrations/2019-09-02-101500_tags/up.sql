@@ -0,0 +1,21 @@
+CREATE TABLE tags (
+    id CHAR(36) PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    category TEXT -- e.g. "topic" or "difficulty"
+);
+
+CREATE TABLE subtasks_in_tags (
+    subtask_id CHAR(36) NOT NULL,
+    tag_id CHAR(36) NOT NULL,
+    FOREIGN KEY (subtask_id) REFERENCES subtasks(id),
+    FOREIGN KEY (tag_id) REFERENCES tags(id),
+    PRIMARY KEY (subtask_id, tag_id)
+);
+
+CREATE TABLE databases_in_tags (
+    database_id CHAR(36) NOT NULL,
+    tag_id CHAR(36) NOT NULL,
+    FOREIGN KEY (database_id) REFERENCES databases(id),
+    FOREIGN KEY (tag_id) REFERENCES tags(id),
+    PRIMARY KEY (database_id, tag_id)
+);
@@ -0,0 +1,12 @@
+-- Tasks, worksheets and courses published to the shared catalog
+CREATE TABLE catalog_entries (
+    id CHAR(36) PRIMARY KEY NOT NULL,
+    object_id CHAR(36) NOT NULL UNIQUE,
+    object_type INTEGER NOT NULL,
+    title TEXT NOT NULL,
+    description TEXT NOT NULL,
+    license TEXT NOT NULL,
+    published_by CHAR(36) NOT NULL,
+    published_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (published_by) REFERENCES users(id)
+);
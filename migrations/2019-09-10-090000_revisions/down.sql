@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS subtask_revisions;
+DROP TABLE IF EXISTS task_revisions;
+DROP TABLE IF EXISTS worksheet_revisions;
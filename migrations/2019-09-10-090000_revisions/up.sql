@@ -0,0 +1,32 @@
+-- Append-only history of subtasks, tasks and worksheets.
+-- content holds the object as JSON-Object, the way the API returns it.
+-- user_id is NULL for the baseline revision recorded before the first tracked change.
+CREATE TABLE subtask_revisions (
+    subtask_id CHAR(36) NOT NULL,
+    revision INTEGER NOT NULL,
+    user_id CHAR(36),
+    created_at TIMESTAMP NOT NULL,
+    restored_from INTEGER,
+    content TEXT NOT NULL,
+    PRIMARY KEY (subtask_id, revision)
+);
+
+CREATE TABLE task_revisions (
+    task_id CHAR(36) NOT NULL,
+    revision INTEGER NOT NULL,
+    user_id CHAR(36),
+    created_at TIMESTAMP NOT NULL,
+    restored_from INTEGER,
+    content TEXT NOT NULL,
+    PRIMARY KEY (task_id, revision)
+);
+
+CREATE TABLE worksheet_revisions (
+    worksheet_id CHAR(36) NOT NULL,
+    revision INTEGER NOT NULL,
+    user_id CHAR(36),
+    created_at TIMESTAMP NOT NULL,
+    restored_from INTEGER,
+    content TEXT NOT NULL,
+    PRIMARY KEY (worksheet_id, revision)
+);
@@ -0,0 +1,10 @@
+-- Deleted objects, kept until they are restored or purged.
+-- The content holds the object and the links it was removed from as JSON.
+CREATE TABLE trash (
+    object_id CHAR(36) PRIMARY KEY NOT NULL,
+    object_type INTEGER NOT NULL,
+    name TEXT,
+    deleted_by CHAR(36) NOT NULL,
+    deleted_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    content TEXT NOT NULL
+);
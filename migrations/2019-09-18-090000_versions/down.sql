@@ -0,0 +1,65 @@
+DROP TRIGGER IF EXISTS courses_version;
+DROP TRIGGER IF EXISTS databases_version;
+DROP TRIGGER IF EXISTS subtasks_version;
+DROP TRIGGER IF EXISTS tasks_version;
+DROP TRIGGER IF EXISTS worksheets_version;
+DROP TRIGGER IF EXISTS worksheets_in_courses_insert_version;
+DROP TRIGGER IF EXISTS worksheets_in_courses_update_version;
+DROP TRIGGER IF EXISTS worksheets_in_courses_delete_version;
+DROP TRIGGER IF EXISTS tasks_in_worksheets_insert_version;
+DROP TRIGGER IF EXISTS tasks_in_worksheets_update_version;
+DROP TRIGGER IF EXISTS tasks_in_worksheets_delete_version;
+DROP TRIGGER IF EXISTS subtasks_in_tasks_insert_version;
+DROP TRIGGER IF EXISTS subtasks_in_tasks_update_version;
+DROP TRIGGER IF EXISTS subtasks_in_tasks_delete_version;
+
+-- DROP COLUMN needs SQLite 3.35, so the tables are copied without the column
+CREATE TABLE courses_old (
+    id CHAR(36) PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    description TEXT
+);
+INSERT INTO courses_old (id, name, description) SELECT id, name, description FROM courses;
+DROP TABLE courses;
+ALTER TABLE courses_old RENAME TO courses;
+
+CREATE TABLE databases_old (
+    id CHAR(36) PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    content TEXT NOT NULL
+);
+INSERT INTO databases_old (id, name, content) SELECT id, name, content FROM databases;
+DROP TABLE databases;
+ALTER TABLE databases_old RENAME TO databases;
+
+CREATE TABLE subtasks_old (
+    id CHAR(36) PRIMARY KEY NOT NULL,
+    instruction TEXT NOT NULL,
+    is_solution_verifiable BOOLEAN NOT NULL DEFAULT 'f',
+    is_solution_visible BOOLEAN NOT NULL DEFAULT 'f',
+    content TEXT NOT NULL
+);
+INSERT INTO subtasks_old (id, instruction, is_solution_verifiable, is_solution_visible, content)
+    SELECT id, instruction, is_solution_verifiable, is_solution_visible, content FROM subtasks;
+DROP TABLE subtasks;
+ALTER TABLE subtasks_old RENAME TO subtasks;
+
+CREATE TABLE tasks_old (
+    id CHAR(36) PRIMARY KEY NOT NULL,
+    database_id CHAR(36) NOT NULL,
+    FOREIGN KEY (database_id) REFERENCES databases(id)
+);
+INSERT INTO tasks_old (id, database_id) SELECT id, database_id FROM tasks;
+DROP TABLE tasks;
+ALTER TABLE tasks_old RENAME TO tasks;
+
+CREATE TABLE worksheets_old (
+    id CHAR(36) PRIMARY KEY NOT NULL,
+    name TEXT,
+    is_online BOOLEAN NOT NULL DEFAULT 'f',
+    is_solution_online BOOLEAN NOT NULL DEFAULT 'f'
+);
+INSERT INTO worksheets_old (id, name, is_online, is_solution_online)
+    SELECT id, name, is_online, is_solution_online FROM worksheets;
+DROP TABLE worksheets;
+ALTER TABLE worksheets_old RENAME TO worksheets;
@@ -0,0 +1,78 @@
+-- Every change of an object increases its version, which is sent as ETag.
+-- Changes to the links of an object change its JSON as well, so they count too.
+
+ALTER TABLE courses ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE databases ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE subtasks ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE tasks ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE worksheets ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+
+CREATE TRIGGER courses_version AFTER UPDATE ON courses WHEN NEW.version = OLD.version
+BEGIN
+    UPDATE courses SET version = version + 1 WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER databases_version AFTER UPDATE ON databases WHEN NEW.version = OLD.version
+BEGIN
+    UPDATE databases SET version = version + 1 WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER subtasks_version AFTER UPDATE ON subtasks WHEN NEW.version = OLD.version
+BEGIN
+    UPDATE subtasks SET version = version + 1 WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER tasks_version AFTER UPDATE ON tasks WHEN NEW.version = OLD.version
+BEGIN
+    UPDATE tasks SET version = version + 1 WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER worksheets_version AFTER UPDATE ON worksheets WHEN NEW.version = OLD.version
+BEGIN
+    UPDATE worksheets SET version = version + 1 WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER worksheets_in_courses_insert_version AFTER INSERT ON worksheets_in_courses
+BEGIN
+    UPDATE courses SET version = version + 1 WHERE id = NEW.course_id;
+END;
+
+CREATE TRIGGER worksheets_in_courses_update_version AFTER UPDATE ON worksheets_in_courses
+BEGIN
+    UPDATE courses SET version = version + 1 WHERE id = NEW.course_id;
+END;
+
+CREATE TRIGGER worksheets_in_courses_delete_version AFTER DELETE ON worksheets_in_courses
+BEGIN
+    UPDATE courses SET version = version + 1 WHERE id = OLD.course_id;
+END;
+
+CREATE TRIGGER tasks_in_worksheets_insert_version AFTER INSERT ON tasks_in_worksheets
+BEGIN
+    UPDATE worksheets SET version = version + 1 WHERE id = NEW.worksheet_id;
+END;
+
+CREATE TRIGGER tasks_in_worksheets_update_version AFTER UPDATE ON tasks_in_worksheets
+BEGIN
+    UPDATE worksheets SET version = version + 1 WHERE id = NEW.worksheet_id;
+END;
+
+CREATE TRIGGER tasks_in_worksheets_delete_version AFTER DELETE ON tasks_in_worksheets
+BEGIN
+    UPDATE worksheets SET version = version + 1 WHERE id = OLD.worksheet_id;
+END;
+
+CREATE TRIGGER subtasks_in_tasks_insert_version AFTER INSERT ON subtasks_in_tasks
+BEGIN
+    UPDATE tasks SET version = version + 1 WHERE id = NEW.task_id;
+END;
+
+CREATE TRIGGER subtasks_in_tasks_update_version AFTER UPDATE ON subtasks_in_tasks
+BEGIN
+    UPDATE tasks SET version = version + 1 WHERE id = NEW.task_id;
+END;
+
+CREATE TRIGGER subtasks_in_tasks_delete_version AFTER DELETE ON subtasks_in_tasks
+BEGIN
+    UPDATE tasks SET version = version + 1 WHERE id = OLD.task_id;
+END;
@@ -0,0 +1,7 @@
+-- Limits of single users, NULL columns fall back to the configured defaults.
+CREATE TABLE quotas (
+    user_id CHAR(36) PRIMARY KEY NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    database_bytes BIGINT,
+    objects BIGINT,
+    aliases BIGINT
+);
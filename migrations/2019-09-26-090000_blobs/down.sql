@@ -0,0 +1,45 @@
+DROP TRIGGER IF EXISTS blobs_unreferenced;
+DROP TRIGGER IF EXISTS trash_blob_delete;
+DROP TRIGGER IF EXISTS trash_blob_insert;
+DROP TRIGGER IF EXISTS databases_blob_delete;
+DROP TRIGGER IF EXISTS databases_blob_update;
+DROP TRIGGER IF EXISTS databases_blob_insert;
+
+-- Compressed dumps can't be decompressed here, so this fails if there are any
+CREATE TEMPORARY TABLE uncompressed_blobs (compressed BOOLEAN NOT NULL CHECK (NOT compressed));
+INSERT INTO uncompressed_blobs SELECT compressed FROM blobs;
+DROP TABLE uncompressed_blobs;
+
+CREATE TABLE databases_old (
+    id CHAR(36) PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    content TEXT NOT NULL,
+    version INTEGER NOT NULL DEFAULT 1
+);
+INSERT INTO databases_old (id, name, content, version)
+    SELECT databases.id, databases.name,
+        CAST(blobs.content AS TEXT),
+        databases.version
+    FROM databases JOIN blobs ON blobs.hash = databases.content_hash;
+DROP TABLE databases;
+ALTER TABLE databases_old RENAME TO databases;
+
+CREATE TRIGGER databases_version AFTER UPDATE ON databases WHEN NEW.version = OLD.version
+BEGIN
+    UPDATE databases SET version = version + 1 WHERE id = NEW.id;
+END;
+
+CREATE TABLE trash_old AS SELECT object_id, object_type, name, deleted_by, deleted_at, content FROM trash;
+DROP TABLE trash;
+CREATE TABLE trash (
+    object_id CHAR(36) PRIMARY KEY NOT NULL,
+    object_type INTEGER NOT NULL,
+    name TEXT,
+    deleted_by CHAR(36) NOT NULL,
+    deleted_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    content TEXT NOT NULL
+);
+INSERT INTO trash SELECT * FROM trash_old;
+DROP TABLE trash_old;
+
+DROP TABLE blobs;
@@ -0,0 +1,67 @@
+-- Database dumps are stored once per content, addressed by their hash.
+-- Rows of databases and trashed databases referring to a blob are counted by triggers,
+-- a blob is removed when nothing refers to it anymore.
+CREATE TABLE blobs (
+    hash CHAR(64) PRIMARY KEY NOT NULL,
+    content BLOB NOT NULL,
+    compressed BOOLEAN NOT NULL,
+    size BIGINT NOT NULL,
+    ref_count INTEGER NOT NULL DEFAULT 0
+);
+
+-- Existing dumps can't be hashed and compressed here, they are kept as they are
+-- until the server repacks them on its next start.
+INSERT INTO blobs (hash, content, compressed, size, ref_count)
+    SELECT 'legacy-' || id, CAST(content AS BLOB), 0, LENGTH(CAST(content AS BLOB)), 0
+    FROM databases;
+
+CREATE TABLE databases_new (
+    id CHAR(36) PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    content_hash CHAR(64) NOT NULL REFERENCES blobs(hash),
+    version INTEGER NOT NULL DEFAULT 1
+);
+INSERT INTO databases_new (id, name, content_hash, version)
+    SELECT id, name, 'legacy-' || id, version FROM databases;
+DROP TABLE databases;
+ALTER TABLE databases_new RENAME TO databases;
+UPDATE blobs SET ref_count = 1;
+
+CREATE TRIGGER databases_version AFTER UPDATE ON databases WHEN NEW.version = OLD.version
+BEGIN
+    UPDATE databases SET version = version + 1 WHERE id = NEW.id;
+END;
+
+ALTER TABLE trash ADD COLUMN content_hash CHAR(64) REFERENCES blobs(hash);
+
+CREATE TRIGGER databases_blob_insert AFTER INSERT ON databases
+BEGIN
+    UPDATE blobs SET ref_count = ref_count + 1 WHERE hash = NEW.content_hash;
+END;
+
+CREATE TRIGGER databases_blob_update AFTER UPDATE OF content_hash ON databases
+WHEN NEW.content_hash IS NOT OLD.content_hash
+BEGIN
+    UPDATE blobs SET ref_count = ref_count + 1 WHERE hash = NEW.content_hash;
+    UPDATE blobs SET ref_count = ref_count - 1 WHERE hash = OLD.content_hash;
+END;
+
+CREATE TRIGGER databases_blob_delete AFTER DELETE ON databases
+BEGIN
+    UPDATE blobs SET ref_count = ref_count - 1 WHERE hash = OLD.content_hash;
+END;
+
+CREATE TRIGGER trash_blob_insert AFTER INSERT ON trash WHEN NEW.content_hash IS NOT NULL
+BEGIN
+    UPDATE blobs SET ref_count = ref_count + 1 WHERE hash = NEW.content_hash;
+END;
+
+CREATE TRIGGER trash_blob_delete AFTER DELETE ON trash WHEN OLD.content_hash IS NOT NULL
+BEGIN
+    UPDATE blobs SET ref_count = ref_count - 1 WHERE hash = OLD.content_hash;
+END;
+
+CREATE TRIGGER blobs_unreferenced AFTER UPDATE OF ref_count ON blobs WHEN NEW.ref_count <= 0
+BEGIN
+    DELETE FROM blobs WHERE hash = NEW.hash;
+END;
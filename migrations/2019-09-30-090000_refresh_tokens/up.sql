@@ -0,0 +1,9 @@
+-- Refresh tokens handed out at login, only their hashes are stored.
+-- Every token can be used once, refreshing replaces it with a new one.
+CREATE TABLE refresh_tokens (
+    token_hash CHAR(64) PRIMARY KEY NOT NULL,
+    user_id CHAR(36) NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at TIMESTAMP NOT NULL
+);
+CREATE INDEX refresh_tokens_user_id ON refresh_tokens(user_id);
@@ -0,0 +1,12 @@
+DROP TABLE revoked_tokens;
+
+CREATE TABLE users_old (
+    id CHAR(36) PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    password_hash TEXT NOT NULL,
+    salt TEXT NOT NULL
+);
+INSERT INTO users_old (id, name, password_hash, salt)
+    SELECT id, name, password_hash, salt FROM users;
+DROP TABLE users;
+ALTER TABLE users_old RENAME TO users;
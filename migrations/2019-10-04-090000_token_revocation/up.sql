@@ -0,0 +1,10 @@
+-- Access tokens revoked before they expire, kept until they would have expired anyway.
+CREATE TABLE revoked_tokens (
+    jti CHAR(36) PRIMARY KEY NOT NULL,
+    user_id CHAR(36) NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    expires_at TIMESTAMP NOT NULL
+);
+
+-- Tokens of a user issued before this time are rejected, set when signing out everywhere
+-- or changing the password.
+ALTER TABLE users ADD COLUMN tokens_valid_after TIMESTAMP;
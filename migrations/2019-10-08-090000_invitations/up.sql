@@ -0,0 +1,10 @@
+-- Invitation codes minted by admins, needed for registering while registration is closed.
+CREATE TABLE invitations (
+    code VARCHAR(32) PRIMARY KEY NOT NULL,
+    created_by CHAR(36) NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    -- NULL means the code never expires
+    expires_at TIMESTAMP,
+    max_uses INTEGER NOT NULL,
+    uses INTEGER NOT NULL DEFAULT 0
+);
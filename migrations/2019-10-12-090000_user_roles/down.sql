@@ -0,0 +1,11 @@
+CREATE TABLE users_old (
+    id CHAR(36) PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    password_hash TEXT NOT NULL,
+    salt TEXT NOT NULL,
+    tokens_valid_after TIMESTAMP
+);
+INSERT INTO users_old (id, name, password_hash, salt, tokens_valid_after)
+    SELECT id, name, password_hash, salt, tokens_valid_after FROM users;
+DROP TABLE users;
+ALTER TABLE users_old RENAME TO users;
@@ -0,0 +1,4 @@
+-- Admins may manage other users, the first one is set with the `admin` command of the server.
+ALTER TABLE users ADD COLUMN is_admin BOOLEAN NOT NULL DEFAULT 0;
+-- Disabled users can't log in anymore, their data is kept.
+ALTER TABLE users ADD COLUMN disabled BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,6 @@
+-- Schemas of dumps as JSON, worked out when they are first asked for.
+-- They are removed together with their blob.
+CREATE TABLE blob_schemas (
+    hash CHAR(64) PRIMARY KEY NOT NULL REFERENCES blobs(hash) ON DELETE CASCADE,
+    schema TEXT NOT NULL
+);
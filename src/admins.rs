@@ -0,0 +1,55 @@
+use crate::database::DatabaseConnectionConfig;
+use crate::schema;
+use crate::tokens;
+use diesel::prelude::*;
+
+/// Scope in the tokens of admins, needed for the admin endpoints
+pub const ADMIN_SCOPE: &str = "admin";
+
+/// Whether the user has the admin role
+pub fn is_admin(conn: &SqliteConnection, user_id: &str) -> Result<bool, diesel::result::Error> {
+    schema::users::table
+        .find(user_id)
+        .select(schema::users::is_admin)
+        .get_result::<bool>(conn)
+}
+
+/// Grants or takes away the admin role. The user's sessions end, so new tokens carry the
+/// changed scope.
+pub fn set_admin(
+    conn: &SqliteConnection,
+    user_id: &str,
+    admin: bool,
+) -> Result<(), diesel::result::Error> {
+    conn.transaction(|| {
+        match diesel::update(schema::users::table.find(user_id))
+            .set(schema::users::is_admin.eq(admin))
+            .execute(conn)?
+        {
+            0 => Err(diesel::result::Error::NotFound),
+            _ => tokens::revoke_all(conn, user_id),
+        }
+    })
+}
+
+/// Entry point of the `admin` command, for making the first admin
+pub fn run_admin(db_connection: &DatabaseConnectionConfig, username: &str, admin: bool) {
+    let conn = match db_connection.create_sqlite_connection_pool().get() {
+        Ok(conn) => conn,
+        Err(e) => {
+            log::error!("Couldn't connect to database: {}", e);
+            return;
+        }
+    };
+    let result = schema::users::table
+        .filter(schema::users::name.eq(username))
+        .select(schema::users::id)
+        .get_result::<String>(&conn)
+        .and_then(|user_id| set_admin(&conn, &user_id, admin));
+    match result {
+        Ok(()) if admin => println!("{} is an admin now", username),
+        Ok(()) => println!("{} is no admin anymore", username),
+        Err(diesel::result::Error::NotFound) => println!("There is no user {}", username),
+        Err(e) => log::error!("Couldn't change the role of {}: {}", username, e),
+    }
+}
@@ -0,0 +1,209 @@
+use crate::models;
+use crate::schema;
+use blake2_rfc::blake2b::blake2b;
+use diesel::{prelude::*, SqliteConnection};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use std::io::{Cursor, Read, Write};
+
+/// Blobs created by the migration, which are neither hashed nor compressed yet
+const LEGACY_PREFIX: &str = "legacy-";
+
+/// Hex encoded 32 byte BLAKE2b hash
+fn hash(content: &[u8]) -> String {
+    blake2b(32, &[], content)
+        .as_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn io_error(e: std::io::Error) -> diesel::result::Error {
+    diesel::result::Error::SerializationError(Box::new(e))
+}
+
+fn compress(content: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(content)?;
+    encoder.finish()
+}
+
+fn decode(blob: models::Blob) -> Result<String, diesel::result::Error> {
+    let content = if blob.compressed {
+        let mut content = Vec::with_capacity(blob.size as usize);
+        ZlibDecoder::new(blob.content.as_slice())
+            .read_to_end(&mut content)
+            .map_err(|e| diesel::result::Error::DeserializationError(Box::new(e)))?;
+        content
+    } else {
+        blob.content
+    };
+    String::from_utf8(content).map_err(|e| diesel::result::Error::DeserializationError(Box::new(e)))
+}
+
+/// Stores a dump unless there is a blob with the same content already, and returns
+/// its hash. The blob is removed again if no database or trash entry refers to it.
+pub fn store(conn: &SqliteConnection, content: &str) -> Result<String, diesel::result::Error> {
+    let hash = hash(content.as_bytes());
+    let exists = diesel::select(diesel::dsl::exists(schema::blobs::table.find(&hash)))
+        .get_result::<bool>(conn)?;
+    if !exists {
+        let compressed = compress(content.as_bytes()).map_err(io_error)?;
+        // small dumps can get bigger when compressed
+        let blob = if compressed.len() < content.len() {
+            (compressed, true)
+        } else {
+            (content.as_bytes().to_vec(), false)
+        };
+        diesel::insert_into(schema::blobs::table)
+            .values(models::Blob {
+                hash: hash.clone(),
+                content: blob.0,
+                compressed: blob.1,
+                size: content.len() as i64,
+                ref_count: 0,
+            })
+            .execute(conn)?;
+    }
+    Ok(hash)
+}
+
+/// Loads the dump stored in a blob
+pub fn load(conn: &SqliteConnection, hash: &str) -> Result<String, diesel::result::Error> {
+    decode(
+        schema::blobs::table
+            .find(hash)
+            .get_result::<models::Blob>(conn)?,
+    )
+}
+
+/// Opens the dump stored in a blob for reading, decompressing it on the way
+pub fn open(conn: &SqliteConnection, hash: &str) -> Result<Box<dyn Read>, diesel::result::Error> {
+    let blob = schema::blobs::table
+        .find(hash)
+        .get_result::<models::Blob>(conn)?;
+    let content = Cursor::new(blob.content);
+    Ok(if blob.compressed {
+        Box::new(ZlibDecoder::new(content))
+    } else {
+        Box::new(content)
+    })
+}
+
+/// Loads the schema kept for a blob as JSON, if it has been worked out already
+pub fn load_schema(
+    conn: &SqliteConnection,
+    hash: &str,
+) -> Result<Option<String>, diesel::result::Error> {
+    schema::blob_schemas::table
+        .find(hash)
+        .select(schema::blob_schemas::schema)
+        .get_result::<String>(conn)
+        .optional()
+}
+
+/// Keeps the schema of a blob as JSON, it is removed together with the blob
+pub fn store_schema(
+    conn: &SqliteConnection,
+    hash: &str,
+    json: &str,
+) -> Result<(), diesel::result::Error> {
+    diesel::insert_or_ignore_into(schema::blob_schemas::table)
+        .values((
+            schema::blob_schemas::hash.eq(hash),
+            schema::blob_schemas::schema.eq(json),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Inserts the row of a database, with its dump stored as blob
+pub fn insert_database(
+    conn: &SqliteConnection,
+    database: models::Database,
+) -> Result<(), diesel::result::Error> {
+    let content_hash = store(conn, &database.content)?;
+    diesel::insert_into(schema::databases::table)
+        .values(models::QueryableDatabase {
+            id: database.id,
+            name: database.name,
+            content_hash,
+        })
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Changes name and dump of a database, the old blob is released by the triggers
+pub fn update_database(
+    conn: &SqliteConnection,
+    id: &str,
+    database: models::Database,
+) -> Result<usize, diesel::result::Error> {
+    let content_hash = store(conn, &database.content)?;
+    diesel::update(schema::databases::table.find(id))
+        .set((
+            schema::databases::name.eq(database.name),
+            schema::databases::content_hash.eq(content_hash),
+        ))
+        .execute(conn)
+}
+
+/// Hashes and compresses the blobs the migration has moved the existing dumps into
+pub fn repack_legacy(conn: &SqliteConnection) -> Result<usize, diesel::result::Error> {
+    let legacy = schema::blobs::table
+        .filter(schema::blobs::hash.like(format!("{}%", LEGACY_PREFIX)))
+        .select(schema::blobs::hash)
+        .load::<String>(conn)?;
+    for old_hash in legacy.iter() {
+        conn.transaction::<(), diesel::result::Error, _>(|| {
+            let new_hash = store(conn, &load(conn, old_hash)?)?;
+            diesel::update(
+                schema::databases::table.filter(schema::databases::content_hash.eq(old_hash)),
+            )
+            .set(schema::databases::content_hash.eq(&new_hash))
+            .execute(conn)?;
+            Ok(())
+        })?;
+    }
+    Ok(legacy.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash() {
+        assert_eq!(hash(b"abc").len(), 64);
+        assert_eq!(hash(b"abc"), hash(b"abc"));
+        assert_ne!(hash(b"abc"), hash(b"abd"));
+    }
+
+    #[test]
+    fn test_compression() {
+        let content = "INSERT INTO t VALUES(1);\n".repeat(100);
+        let compressed = compress(content.as_bytes()).unwrap();
+        assert!(compressed.len() < content.len());
+        let blob = models::Blob {
+            hash: hash(content.as_bytes()),
+            content: compressed,
+            compressed: true,
+            size: content.len() as i64,
+            ref_count: 1,
+        };
+        assert_eq!(decode(blob).unwrap(), content);
+    }
+
+    #[test]
+    fn test_schema() {
+        let conn = crate::database::test_connection();
+        let hash = store(&conn, "CREATE TABLE t (a);").unwrap();
+        assert_eq!(load_schema(&conn, &hash).unwrap(), None);
+        store_schema(&conn, &hash, "{}").unwrap();
+        store_schema(&conn, &hash, "[]").unwrap();
+        assert_eq!(load_schema(&conn, &hash).unwrap(), Some("{}".to_string()));
+        diesel::delete(schema::blobs::table.find(&hash))
+            .execute(&conn)
+            .unwrap();
+        assert_eq!(load_schema(&conn, &hash).unwrap(), None);
+    }
+}
@@ -8,6 +8,15 @@ pub(crate) fn setup_cli() -> clap::ArgMatches<'static> {
         (about: crate_description!())
         (@arg v: -v --verbose ... "Be verbose (you can add this up to 4 times for more logs)")
         (@arg config: -c --config +takes_value "Set config file path")
+        (@subcommand gc =>
+            (about: "Remove orphaned objects, links, access rows, aliases and revisions")
+            (@arg dry_run: --("dry-run") "Only list what would be removed")
+        )
+        (@subcommand admin =>
+            (about: "Make a user an admin, e.g. the first one")
+            (@arg username: +required "Name of the user")
+            (@arg revoke: --revoke "Take the admin role away instead")
+        )
     )
     .get_matches()
 }
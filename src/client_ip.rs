@@ -0,0 +1,73 @@
+use actix_web::http::HeaderMap;
+use std::net::{IpAddr, SocketAddr};
+
+/// The address of the client. Requests from one of the trusted proxies are attributed to the
+/// address it put into `X-Forwarded-For`. Anything left of that could be made up by the
+/// client, so the header is read from the right up to the first hop that isn't a trusted proxy.
+pub fn client_ip(
+    peer_addr: Option<SocketAddr>,
+    headers: &HeaderMap,
+    trusted_proxies: &[IpAddr],
+) -> Option<IpAddr> {
+    let mut ip = peer_addr?.ip();
+    if !trusted_proxies.contains(&ip) {
+        return Some(ip);
+    }
+    let hops = headers
+        .get_all("x-forwarded-for")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect::<Vec<_>>();
+    for hop in hops.iter().rev() {
+        // some proxies append the port
+        match hop
+            .parse::<IpAddr>()
+            .or_else(|_| hop.parse::<SocketAddr>().map(|addr| addr.ip()))
+        {
+            Ok(hop) => {
+                ip = hop;
+                if !trusted_proxies.contains(&hop) {
+                    break;
+                }
+            }
+            Err(_) => break,
+        }
+    }
+    Some(ip)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::client_ip;
+    use actix_web::http::{header::HeaderValue, HeaderMap};
+
+    #[test]
+    fn forwarded_only_through_trusted_proxies() {
+        let proxy = "10.0.0.1".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-forwarded-for".parse().unwrap(),
+            HeaderValue::from_static("6.6.6.6, 1.2.3.4:5678, 10.0.0.2"),
+        );
+        let trusted = ["10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap()];
+
+        assert_eq!(
+            client_ip(Some("10.0.0.1:443".parse().unwrap()), &headers, &trusted),
+            Some("1.2.3.4".parse().unwrap())
+        );
+        assert_eq!(
+            client_ip(Some("5.5.5.5:443".parse().unwrap()), &headers, &trusted),
+            Some("5.5.5.5".parse().unwrap())
+        );
+        assert_eq!(
+            client_ip(
+                Some("10.0.0.1:443".parse().unwrap()),
+                &HeaderMap::new(),
+                &trusted
+            ),
+            Some(proxy)
+        );
+        assert_eq!(client_ip(None, &headers, &trusted), None);
+    }
+}
@@ -0,0 +1,200 @@
+use crate::models::{self, ObjectType};
+use crate::schema;
+use diesel::{prelude::*, SqliteConnection};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Deep copies objects into a user's account. Children are copied as well, so the
+/// copy can be edited without affecting the original. Databases shared by several
+/// tasks are only copied once per `Cloner`.
+pub struct Cloner<'a> {
+    conn: &'a SqliteConnection,
+    user_id: String,
+    databases: HashMap<String, String>,
+}
+
+impl<'a> Cloner<'a> {
+    pub fn new(conn: &'a SqliteConnection, user_id: String) -> Self {
+        Self {
+            conn,
+            user_id,
+            databases: HashMap::new(),
+        }
+    }
+
+    /// Copies the object and returns the id of the copy
+    pub fn clone_object(
+        &mut self,
+        object_id: &str,
+        object_type: ObjectType,
+    ) -> Result<String, diesel::result::Error> {
+        match object_type {
+            ObjectType::COURSE => self.clone_course(object_id),
+            ObjectType::WORKSHEET => self.clone_worksheet(object_id),
+            ObjectType::TASK => self.clone_task(object_id),
+            ObjectType::SUBTASK => self.clone_subtask(object_id),
+            ObjectType::DATABASE => self.clone_database(object_id),
+        }
+    }
+
+    fn grant_access(&self, object_id: &str) -> Result<(), diesel::result::Error> {
+        diesel::insert_into(schema::access::table)
+            .values(models::Access {
+                user_id: self.user_id.clone(),
+                object_id: object_id.to_string(),
+            })
+            .execute(self.conn)?;
+        Ok(())
+    }
+
+    pub fn clone_database(&mut self, database_id: &str) -> Result<String, diesel::result::Error> {
+        if let Some(id) = self.databases.get(database_id) {
+            return Ok(id.clone());
+        }
+        // the copy shares the blob holding the dump
+        let mut database = schema::databases::table
+            .find(database_id)
+            .select(models::QueryableDatabase::COLUMNS)
+            .get_result::<models::QueryableDatabase>(self.conn)?;
+        let id = Uuid::new_v4().to_string();
+        // database names are unique, so the copy gets a suffix
+        database.name = format!("{} ({})", database.name, &id[..8]);
+        database.id = id.clone();
+
+        diesel::insert_into(schema::databases::table)
+            .values(database)
+            .execute(self.conn)?;
+        self.grant_access(&id)?;
+        self.databases.insert(database_id.to_string(), id.clone());
+        Ok(id)
+    }
+
+    pub fn clone_subtask(&mut self, subtask_id: &str) -> Result<String, diesel::result::Error> {
+        let mut subtask = schema::subtasks::table
+            .find(subtask_id)
+            .select((
+                schema::subtasks::columns::id,
+                schema::subtasks::columns::instruction,
+                schema::subtasks::columns::is_solution_verifiable,
+                schema::subtasks::columns::is_solution_visible,
+                schema::subtasks::columns::content,
+            ))
+            .get_result::<models::Subtask>(self.conn)?;
+        let id = Uuid::new_v4().to_string();
+        subtask.id = id.clone();
+
+        diesel::insert_into(schema::subtasks::table)
+            .values(subtask)
+            .execute(self.conn)?;
+        self.grant_access(&id)?;
+        Ok(id)
+    }
+
+    pub fn clone_task(&mut self, task_id: &str) -> Result<String, diesel::result::Error> {
+        let task = schema::tasks::table
+            .find(task_id)
+            .select((
+                schema::tasks::columns::id,
+                schema::tasks::columns::database_id,
+            ))
+            .get_result::<models::QueryableTask>(self.conn)?;
+        let subtasks = schema::subtasks_in_tasks::table
+            .filter(schema::subtasks_in_tasks::columns::task_id.eq(task_id))
+            .select(schema::subtasks_in_tasks::columns::subtask_id)
+            .order(schema::subtasks_in_tasks::position)
+            .load::<String>(self.conn)?;
+
+        let id = Uuid::new_v4().to_string();
+        let database_id = self.clone_database(&task.database_id)?;
+        diesel::insert_into(schema::tasks::table)
+            .values(models::QueryableTask {
+                id: id.clone(),
+                database_id,
+            })
+            .execute(self.conn)?;
+        self.grant_access(&id)?;
+
+        for (position, subtask_id) in subtasks.iter().enumerate() {
+            let subtask_id = self.clone_subtask(subtask_id)?;
+            diesel::insert_into(schema::subtasks_in_tasks::table)
+                .values(models::SubtasksInTask {
+                    subtask_id,
+                    task_id: id.clone(),
+                    position: position as i32,
+                })
+                .execute(self.conn)?;
+        }
+        Ok(id)
+    }
+
+    pub fn clone_worksheet(&mut self, worksheet_id: &str) -> Result<String, diesel::result::Error> {
+        let mut worksheet = schema::worksheets::table
+            .find(worksheet_id)
+            .select((
+                schema::worksheets::columns::id,
+                schema::worksheets::columns::name,
+                schema::worksheets::columns::is_online,
+                schema::worksheets::columns::is_solution_online,
+            ))
+            .get_result::<models::QueryableWorksheet>(self.conn)?;
+        let tasks = schema::tasks_in_worksheets::table
+            .filter(schema::tasks_in_worksheets::columns::worksheet_id.eq(worksheet_id))
+            .select(schema::tasks_in_worksheets::columns::task_id)
+            .order(schema::tasks_in_worksheets::position)
+            .load::<String>(self.conn)?;
+
+        let id = Uuid::new_v4().to_string();
+        worksheet.id = id.clone();
+        diesel::insert_into(schema::worksheets::table)
+            .values(worksheet)
+            .execute(self.conn)?;
+        self.grant_access(&id)?;
+
+        for (position, task_id) in tasks.iter().enumerate() {
+            let task_id = self.clone_task(task_id)?;
+            diesel::insert_into(schema::tasks_in_worksheets::table)
+                .values(models::TasksInWorksheet {
+                    task_id,
+                    worksheet_id: id.clone(),
+                    position: position as i32,
+                })
+                .execute(self.conn)?;
+        }
+        Ok(id)
+    }
+
+    pub fn clone_course(&mut self, course_id: &str) -> Result<String, diesel::result::Error> {
+        let mut course = schema::courses::table
+            .find(course_id)
+            .select((
+                schema::courses::columns::id,
+                schema::courses::columns::name,
+                schema::courses::columns::description,
+            ))
+            .get_result::<models::QueryableCourse>(self.conn)?;
+        let worksheets = schema::worksheets_in_courses::table
+            .filter(schema::worksheets_in_courses::columns::course_id.eq(course_id))
+            .select(schema::worksheets_in_courses::columns::worksheet_id)
+            .order(schema::worksheets_in_courses::position)
+            .load::<String>(self.conn)?;
+
+        let id = Uuid::new_v4().to_string();
+        course.id = id.clone();
+        diesel::insert_into(schema::courses::table)
+            .values(course)
+            .execute(self.conn)?;
+        self.grant_access(&id)?;
+
+        for (position, worksheet_id) in worksheets.iter().enumerate() {
+            let worksheet_id = self.clone_worksheet(worksheet_id)?;
+            diesel::insert_into(schema::worksheets_in_courses::table)
+                .values(models::WorksheetsInCourse {
+                    worksheet_id,
+                    course_id: id.clone(),
+                    position: position as i32,
+                })
+                .execute(self.conn)?;
+        }
+        Ok(id)
+    }
+}
@@ -1,4 +1,5 @@
-use diesel::r2d2::{self, ConnectionManager};
+use diesel::connection::SimpleConnection;
+use diesel::r2d2::{self, ConnectionManager, CustomizeConnection};
 use diesel::SqliteConnection;
 use serde::Deserialize;
 
@@ -21,9 +22,63 @@ impl DatabaseConnectionConfig {
     }
 }
 
+/// SQLite only enforces foreign keys when asked to, once per connection. Writers also wait
+/// for each other instead of failing at once, since changes take the write lock up front.
+#[derive(Debug)]
+struct EnableForeignKeys;
+
+impl CustomizeConnection<SqliteConnection, r2d2::Error> for EnableForeignKeys {
+    fn on_acquire(&self, conn: &mut SqliteConnection) -> Result<(), r2d2::Error> {
+        conn.batch_execute("PRAGMA foreign_keys = ON; PRAGMA busy_timeout = 5000;")
+            .map_err(r2d2::Error::QueryError)
+    }
+}
+
 fn sqlite(file: &str) -> r2d2::Pool<ConnectionManager<SqliteConnection>> {
     r2d2::Pool::builder()
         .max_size(15)
+        .connection_customizer(Box::new(EnableForeignKeys))
         .build(ConnectionManager::<SqliteConnection>::new(file))
         .expect("Failed to create database connection Pool.")
 }
+
+/// An in-memory database with the migrations applied, for tests of the queries
+#[cfg(test)]
+pub fn test_connection() -> SqliteConnection {
+    use diesel::Connection;
+
+    let conn = SqliteConnection::establish(":memory:").unwrap();
+    conn.batch_execute("PRAGMA foreign_keys = ON;").unwrap();
+    let mut migrations = std::fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/migrations"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    // the directory names start with their date
+    migrations.sort();
+    for migration in migrations {
+        conn.batch_execute(&std::fs::read_to_string(migration.join("up.sql")).unwrap())
+            .unwrap();
+    }
+    conn
+}
+
+/// Objects shared by the tests of the queries: the user `user`, the course `course` with
+/// the worksheets a, b, c and d in this order, the worksheet `orphan` which is part of
+/// nothing, and the task `task` using the database `database`. The user has access to the
+/// course, worksheet a, the task and the database.
+#[cfg(test)]
+pub fn seed(conn: &SqliteConnection) {
+    conn.batch_execute(
+        "INSERT INTO users (id, name, password_hash, salt) VALUES ('user', 'alice', '', '');
+         INSERT INTO courses (id, name) VALUES ('course', 'Databases');
+         INSERT INTO worksheets (id) VALUES ('a'), ('b'), ('c'), ('d'), ('orphan');
+         INSERT INTO worksheets_in_courses (worksheet_id, course_id, position)
+             VALUES ('a', 'course', 0), ('b', 'course', 1), ('c', 'course', 2), ('d', 'course', 3);
+         INSERT INTO blobs (hash, content, compressed, size) VALUES ('hash', '', 0, 0);
+         INSERT INTO databases (id, name, content_hash) VALUES ('database', 'shop', 'hash');
+         INSERT INTO tasks (id, database_id) VALUES ('task', 'database');
+         INSERT INTO access (user_id, object_id)
+             VALUES ('user', 'course'), ('user', 'a'), ('user', 'task'), ('user', 'database');",
+    )
+    .unwrap();
+}
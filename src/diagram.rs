@@ -0,0 +1,392 @@
+use crate::models::{DatabaseSchema, TableSchema};
+use std::fmt::Write;
+
+/// Approximate size of the monospace font used in SVG diagrams
+const CHAR_WIDTH: usize = 7;
+const ROW_HEIGHT: usize = 18;
+const TABLE_SPACING: usize = 60;
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Mermaid only accepts plain words as names and types
+fn mermaid_word(text: &str) -> String {
+    let word = text
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    if word.is_empty() {
+        "_".to_string()
+    } else {
+        word
+    }
+}
+
+fn column_label(table: &TableSchema, column: usize) -> (String, bool, bool) {
+    let column = &table.columns[column];
+    let primary = table.primary_key.contains(&column.name);
+    let foreign = table
+        .foreign_keys
+        .iter()
+        .any(|key| key.columns.contains(&column.name));
+    let label = if column.column_type.is_empty() {
+        column.name.clone()
+    } else {
+        format!("{} {}", column.name, column.column_type)
+    };
+    (label, primary, foreign)
+}
+
+fn column_index(schema: &DatabaseSchema, table: &str, column: &str) -> Option<(usize, usize)> {
+    let t = schema.tables.iter().position(|t| t.name == table)?;
+    let c = schema.tables[t]
+        .columns
+        .iter()
+        .position(|c| c.name == column)?;
+    Some((t, c))
+}
+
+/// Graphviz diagram with a node per table and an edge per foreign key column
+pub fn to_dot(schema: &DatabaseSchema) -> String {
+    let mut dot =
+        String::from("digraph schema {\n    graph [rankdir=LR];\n    node [shape=plaintext];\n");
+    for (t, table) in schema.tables.iter().enumerate() {
+        let _ = write!(
+            dot,
+            "    t{} [label=<<table border=\"0\" cellborder=\"1\" cellspacing=\"0\">\
+             <tr><td bgcolor=\"lightgrey\"><b>{}</b></td></tr>",
+            t,
+            escape_xml(&table.name)
+        );
+        for c in 0..table.columns.len() {
+            let (label, primary, foreign) = column_label(table, c);
+            let mut label = escape_xml(&label);
+            if primary {
+                label = format!("<u>{}</u>", label);
+            }
+            if foreign {
+                label = format!("<i>{}</i>", label);
+            }
+            let _ = write!(
+                dot,
+                "<tr><td port=\"c{}\" align=\"left\">{}</td></tr>",
+                c, label
+            );
+        }
+        let _ = writeln!(dot, "</table>>, tooltip=\"{}\"];", escape_dot(&table.name));
+    }
+    for (t, table) in schema.tables.iter().enumerate() {
+        for key in &table.foreign_keys {
+            for (column, referenced) in key.columns.iter().zip(&key.referenced_columns) {
+                let from = column_index(schema, &table.name, column);
+                let to = column_index(schema, &key.referenced_table, referenced);
+                if let (Some((_, c)), Some((rt, rc))) = (from, to) {
+                    let _ = writeln!(dot, "    t{}:c{} -> t{}:c{};", t, c, rt, rc);
+                }
+            }
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// Mermaid ER diagram, where every foreign key is a one to many relationship
+pub fn to_mermaid(schema: &DatabaseSchema) -> String {
+    let mut mermaid = String::from("erDiagram\n");
+    for table in &schema.tables {
+        let _ = writeln!(mermaid, "    {} {{", mermaid_word(&table.name));
+        for (c, column) in table.columns.iter().enumerate() {
+            let (_, primary, foreign) = column_label(table, c);
+            let keys = match (primary, foreign) {
+                (true, true) => " PK, FK",
+                (true, false) => " PK",
+                (false, true) => " FK",
+                (false, false) => "",
+            };
+            let column_type = if column.column_type.is_empty() {
+                "ANY".to_string()
+            } else {
+                mermaid_word(&column.column_type)
+            };
+            let _ = writeln!(
+                mermaid,
+                "        {} {}{}",
+                column_type,
+                mermaid_word(&column.name),
+                keys
+            );
+        }
+        mermaid.push_str("    }\n");
+    }
+    for table in &schema.tables {
+        for key in &table.foreign_keys {
+            // a nullable foreign key means the referenced row is optional
+            let optional = key.columns.iter().any(|name| {
+                table
+                    .columns
+                    .iter()
+                    .any(|column| &column.name == name && column.nullable)
+            });
+            let _ = writeln!(
+                mermaid,
+                "    {} {}--o{{ {} : \"{}\"",
+                mermaid_word(&key.referenced_table),
+                if optional { "|o" } else { "||" },
+                mermaid_word(&table.name),
+                key.columns.join(", ").replace('"', "'")
+            );
+        }
+    }
+    mermaid
+}
+
+/// Position and size of a table in an SVG diagram
+struct TableBox {
+    x: usize,
+    y: usize,
+    width: usize,
+}
+
+/// SVG diagram with the tables laid out in a grid and lines for the foreign keys
+pub fn to_svg(schema: &DatabaseSchema) -> String {
+    let per_row = (1..).find(|n| n * n >= schema.tables.len()).unwrap_or(1);
+    let widths = schema
+        .tables
+        .iter()
+        .map(|table| {
+            let longest = (0..table.columns.len())
+                .map(|c| column_label(table, c).0.chars().count())
+                .chain(std::iter::once(table.name.chars().count()))
+                .max()
+                .unwrap_or(0);
+            longest * CHAR_WIDTH + 20
+        })
+        .collect::<Vec<_>>();
+    let heights = schema
+        .tables
+        .iter()
+        .map(|table| (table.columns.len() + 1) * ROW_HEIGHT + 6)
+        .collect::<Vec<_>>();
+
+    // every grid column is as wide as its widest table, every grid row as high as its highest
+    let mut column_widths = vec![0; per_row];
+    let mut row_heights = vec![0; schema.tables.len().div_ceil(per_row)];
+    for (t, (width, height)) in widths.iter().zip(&heights).enumerate() {
+        column_widths[t % per_row] = column_widths[t % per_row].max(*width);
+        row_heights[t / per_row] = row_heights[t / per_row].max(*height);
+    }
+    let boxes = widths
+        .iter()
+        .enumerate()
+        .map(|(t, width)| TableBox {
+            x: TABLE_SPACING / 2
+                + column_widths[..t % per_row]
+                    .iter()
+                    .map(|w| w + TABLE_SPACING)
+                    .sum::<usize>(),
+            y: TABLE_SPACING / 2
+                + row_heights[..t / per_row]
+                    .iter()
+                    .map(|h| h + TABLE_SPACING)
+                    .sum::<usize>(),
+            width: *width,
+        })
+        .collect::<Vec<_>>();
+    let total_width = column_widths
+        .iter()
+        .map(|w| w + TABLE_SPACING)
+        .sum::<usize>();
+    let total_height = row_heights.iter().map(|h| h + TABLE_SPACING).sum::<usize>();
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+         font-family=\"monospace\" font-size=\"12\">",
+        total_width, total_height
+    );
+    svg.push_str(
+        "<defs><marker id=\"arrow\" viewBox=\"0 0 10 10\" refX=\"10\" refY=\"5\" \
+         markerWidth=\"8\" markerHeight=\"8\" orient=\"auto\">\
+         <path d=\"M 0 0 L 10 5 L 0 10 z\"/></marker></defs>\n",
+    );
+
+    for (t, table) in schema.tables.iter().enumerate() {
+        let b = &boxes[t];
+        let _ = writeln!(
+            svg,
+            "<g><rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"white\" stroke=\"black\"/>\
+             <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"lightgrey\" stroke=\"black\"/>\
+             <text x=\"{}\" y=\"{}\" font-weight=\"bold\">{}</text>",
+            b.x,
+            b.y,
+            b.width,
+            heights[t],
+            b.x,
+            b.y,
+            b.width,
+            ROW_HEIGHT + 2,
+            b.x + 10,
+            b.y + ROW_HEIGHT - 4,
+            escape_xml(&table.name)
+        );
+        for c in 0..table.columns.len() {
+            let (label, primary, foreign) = column_label(table, c);
+            let _ = writeln!(
+                svg,
+                "<text x=\"{}\" y=\"{}\"{}{}>{}</text>",
+                b.x + 10,
+                b.y + (c + 2) * ROW_HEIGHT,
+                if primary {
+                    " text-decoration=\"underline\""
+                } else {
+                    ""
+                },
+                if foreign {
+                    " font-style=\"italic\""
+                } else {
+                    ""
+                },
+                escape_xml(&label)
+            );
+        }
+        svg.push_str("</g>\n");
+    }
+
+    for (t, table) in schema.tables.iter().enumerate() {
+        for key in &table.foreign_keys {
+            let to = key
+                .columns
+                .first()
+                .zip(key.referenced_columns.first())
+                .and_then(|(column, referenced)| {
+                    let from = column_index(schema, &table.name, column)?;
+                    Some((
+                        from.1,
+                        column_index(schema, &key.referenced_table, referenced)?,
+                    ))
+                });
+            let (c, (rt, rc)) = match to {
+                Some(to) => to,
+                None => continue,
+            };
+            let (from, target) = (&boxes[t], &boxes[rt]);
+            let from_y = from.y + (c + 2) * ROW_HEIGHT - 4;
+            let to_y = target.y + (rc + 2) * ROW_HEIGHT - 4;
+            // leave on the side facing the referenced table, references within one table
+            // and to tables in the same grid column loop around the right side
+            let (from_x, to_x, bend) = if target.x > from.x {
+                (from.x + from.width, target.x, None)
+            } else if target.x + target.width < from.x {
+                (from.x, target.x + target.width, None)
+            } else {
+                let right = from.x.max(target.x) + from.width.max(target.width);
+                (
+                    from.x + from.width,
+                    target.x + target.width,
+                    Some(right + 20),
+                )
+            };
+            let points = match bend {
+                Some(bend) => format!(
+                    "{},{} {},{} {},{} {},{}",
+                    from_x, from_y, bend, from_y, bend, to_y, to_x, to_y
+                ),
+                None => format!("{},{} {},{}", from_x, from_y, to_x, to_y),
+            };
+            let _ = writeln!(
+                svg,
+                "<polyline points=\"{}\" fill=\"none\" stroke=\"black\" marker-end=\"url(#arrow)\"/>",
+                points
+            );
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ColumnSchema, ForeignKeySchema};
+
+    fn column(name: &str, column_type: &str, nullable: bool) -> ColumnSchema {
+        ColumnSchema {
+            name: name.to_string(),
+            column_type: column_type.to_string(),
+            nullable,
+            default: None,
+        }
+    }
+
+    fn schema() -> DatabaseSchema {
+        DatabaseSchema {
+            tables: vec![
+                TableSchema {
+                    name: "artist".to_string(),
+                    columns: vec![column("id", "INTEGER", false)],
+                    primary_key: vec!["id".to_string()],
+                    foreign_keys: vec![],
+                    row_count: 0,
+                },
+                TableSchema {
+                    name: "album".to_string(),
+                    columns: vec![
+                        column("id", "INTEGER", false),
+                        column("artist id", "", true),
+                    ],
+                    primary_key: vec!["id".to_string()],
+                    foreign_keys: vec![ForeignKeySchema {
+                        columns: vec!["artist id".to_string()],
+                        referenced_table: "artist".to_string(),
+                        referenced_columns: vec!["id".to_string()],
+                    }],
+                    row_count: 0,
+                },
+            ],
+            views: vec![],
+        }
+    }
+
+    #[test]
+    fn test_mermaid() {
+        assert_eq!(
+            to_mermaid(&schema()),
+            "erDiagram\n    artist {\n        INTEGER id PK\n    }\n    album {\n        \
+             INTEGER id PK\n        ANY artist_id FK\n    }\n    artist |o--o{ album : \"artist id\"\n"
+        );
+    }
+
+    #[test]
+    fn test_dot() {
+        assert!(to_dot(&schema()).contains("    t1:c1 -> t0:c0;\n"));
+    }
+
+    #[test]
+    fn test_svg_escaping() {
+        let schema = DatabaseSchema {
+            tables: vec![TableSchema {
+                name: "<a>\"&".to_string(),
+                columns: vec![column("<b>", "\"&", true)],
+                primary_key: vec![],
+                foreign_keys: vec![],
+                row_count: 0,
+            }],
+            views: vec![],
+        };
+        let svg = to_svg(&schema);
+        assert!(svg.contains(">&lt;a&gt;&quot;&amp;</text>"));
+        assert!(svg.contains("&lt;b&gt;"));
+        assert!(svg.contains("&quot;&amp;"));
+        assert!(!svg.contains("<a>"));
+        assert!(!svg.contains("<b>"));
+    }
+}
@@ -0,0 +1,719 @@
+use crate::middlewares::conditional::PreconditionFailed;
+use crate::models::{ColumnSchema, DatabaseSchema, ForeignKeySchema, TableSchema, ViewSchema};
+use crate::quota::Exceeded;
+use actix_web::HttpResponse;
+use diesel::{
+    dsl::sql,
+    sql_types::{BigInt, Bool, Integer, Nullable, Text},
+    Connection, RunQueryDsl, SqliteConnection,
+};
+use libsqlite3_sys as ffi;
+use serde::Serialize;
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::os::raw::{c_int, c_void};
+use std::path::PathBuf;
+use std::ptr::{self, NonNull};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Every SQLite file starts with this
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+/// Statements which could touch other files than the database being built
+const FORBIDDEN_KEYWORDS: &[&str] = &["ATTACH", "VACUUM"];
+
+/// How long loading a dump may take, recursive queries could run forever otherwise
+const LOAD_TIMEOUT: Duration = Duration::from_secs(10);
+/// Pages of a loaded dump, 64 MiB with the page size below
+const MAX_PAGES: u32 = 16384;
+const PAGE_SIZE: u32 = 4096;
+/// Longest string or blob a statement may create
+const MAX_LENGTH: c_int = 33554432;
+
+#[derive(Debug)]
+pub enum DumpError {
+    Io(std::io::Error),
+    Connection(diesel::ConnectionError),
+    Diesel(diesel::result::Error),
+    /// The input can't be used as a database, with the reason for the user
+    Invalid(String),
+    InvalidStatement(InvalidStatement),
+    Quota(Exceeded),
+    PreconditionFailed,
+}
+
+/// A statement of a dump which can't be run
+#[derive(Debug, Serialize)]
+pub struct InvalidStatement {
+    pub line: usize,
+    pub message: String,
+}
+
+impl From<std::io::Error> for DumpError {
+    fn from(val: std::io::Error) -> DumpError {
+        DumpError::Io(val)
+    }
+}
+
+impl From<diesel::ConnectionError> for DumpError {
+    fn from(val: diesel::ConnectionError) -> DumpError {
+        DumpError::Connection(val)
+    }
+}
+
+impl From<Exceeded> for DumpError {
+    fn from(val: Exceeded) -> DumpError {
+        DumpError::Quota(val)
+    }
+}
+
+impl From<PreconditionFailed> for DumpError {
+    fn from(_: PreconditionFailed) -> DumpError {
+        DumpError::PreconditionFailed
+    }
+}
+
+impl From<diesel::result::Error> for DumpError {
+    fn from(val: diesel::result::Error) -> DumpError {
+        DumpError::Diesel(val)
+    }
+}
+
+impl DumpError {
+    pub fn into_response(self, action: &str) -> HttpResponse {
+        match self {
+            DumpError::Io(e) => {
+                log::error!("Couldn't {}: {}", action, e);
+                HttpResponse::InternalServerError().finish()
+            }
+            DumpError::Connection(e) => {
+                log::error!("Couldn't {}: {}", action, e);
+                HttpResponse::InternalServerError().finish()
+            }
+            DumpError::Diesel(diesel::result::Error::NotFound) => HttpResponse::NotFound().finish(),
+            DumpError::Diesel(diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                _,
+            )) => HttpResponse::Conflict().body("A database with this name exists already."),
+            DumpError::Diesel(e) => {
+                log::error!("Couldn't {}: {}", action, e);
+                HttpResponse::InternalServerError().finish()
+            }
+            DumpError::Invalid(reason) => HttpResponse::UnprocessableEntity().body(reason),
+            DumpError::InvalidStatement(statement) => {
+                HttpResponse::UnprocessableEntity().json(statement)
+            }
+            DumpError::Quota(exceeded) => exceeded.into_response(),
+            DumpError::PreconditionFailed => HttpResponse::PreconditionFailed().finish(),
+        }
+    }
+}
+
+/// A SQLite file in the temp directory, which is removed when dropped
+struct TempDatabase {
+    path: PathBuf,
+}
+
+impl TempDatabase {
+    fn new() -> Self {
+        TempDatabase {
+            path: std::env::temp_dir().join(format!("upowdb-{}.sqlite", Uuid::new_v4())),
+        }
+    }
+
+    fn connect(&self) -> Result<SqliteConnection, DumpError> {
+        Ok(SqliteConnection::establish(&self.path.to_string_lossy())?)
+    }
+}
+
+impl Drop for TempDatabase {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+pub fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+pub fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Converts a SQLite file into the SQL statements stored as database content
+pub fn from_sqlite_file(file: &[u8]) -> Result<String, DumpError> {
+    if !file.starts_with(SQLITE_HEADER) {
+        return Err(DumpError::Invalid(
+            "The file is not a SQLite database.".to_string(),
+        ));
+    }
+    let database = TempDatabase::new();
+    fs::write(&database.path, file)?;
+    let conn = database.connect()?;
+    dump_tables(&conn).map_err(|e| DumpError::Invalid(format!("The database can't be read: {}", e)))
+}
+
+fn dump_tables(conn: &SqliteConnection) -> Result<String, diesel::result::Error> {
+    let mut dump = String::new();
+    let tables = diesel::select(sql::<Text>(
+        "name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY rowid",
+    ))
+    .load::<String>(conn)?;
+    for table in tables {
+        let create = diesel::select(
+            sql::<Text>("sql FROM sqlite_master WHERE name = ").bind::<Text, _>(&table),
+        )
+        .get_result::<String>(conn)?;
+        dump.push_str(&format!("{};\n", create));
+
+        // SQLite quotes the values itself, so all types and blobs survive
+        let columns = diesel::select(
+            sql::<Text>("name FROM pragma_table_info(")
+                .bind::<Text, _>(&table)
+                .sql(")"),
+        )
+        .load::<String>(conn)?
+        .iter()
+        .map(|column| format!("quote({})", quote_identifier(column)))
+        .collect::<Vec<_>>()
+        .join(" || ',' || ");
+        let rows = diesel::select(sql::<Text>(&format!(
+            "{} || {} || ');' FROM {}",
+            quote_literal(&format!("INSERT INTO {} VALUES(", quote_identifier(&table))),
+            columns,
+            quote_identifier(&table),
+        )))
+        .load::<String>(conn)?;
+        for row in rows {
+            dump.push_str(&row);
+            dump.push('\n');
+        }
+    }
+
+    let others = diesel::select(sql::<Text>(
+        "sql FROM sqlite_master \
+         WHERE type IN ('index', 'view', 'trigger') AND sql IS NOT NULL ORDER BY rowid",
+    ))
+    .load::<String>(conn)?;
+    for other in others {
+        dump.push_str(&format!("{};\n", other));
+    }
+    Ok(dump)
+}
+
+/// The SQL type all values of a CSV column fit into
+fn infer_type<'a>(values: impl Iterator<Item = &'a str>) -> &'static str {
+    let mut integer = true;
+    let mut real = true;
+    for value in values.filter(|value| !value.is_empty()) {
+        // leading zeros, as in postal codes, would get lost in a number
+        let digits = value.trim_start_matches(&['-', '+'][..]);
+        if digits.len() > 1 && digits.starts_with('0') && !digits.starts_with("0.") {
+            return "TEXT";
+        }
+        integer = integer && value.parse::<i64>().is_ok();
+        real = real && matches!(value.parse::<f64>(), Ok(value) if value.is_finite());
+    }
+    if integer {
+        "INTEGER"
+    } else if real {
+        "REAL"
+    } else {
+        "TEXT"
+    }
+}
+
+/// Converts CSV files, given with their table name, into SQL statements.
+/// The first line holds the column names, the column types are inferred from the values.
+pub fn from_csv_files(files: &[(String, Vec<u8>)]) -> Result<String, DumpError> {
+    let mut dump = String::new();
+    let mut names = Vec::new();
+    for (name, file) in files {
+        if names.contains(&name.to_lowercase()) {
+            return Err(DumpError::Invalid(format!(
+                "The table {} exists twice.",
+                name
+            )));
+        }
+        names.push(name.to_lowercase());
+
+        // spreadsheets in many locales separate values with semicolons
+        let first_line = file.split(|b| *b == b'\n').next().unwrap_or_default();
+        let semicolons = first_line.iter().filter(|b| **b == b';').count();
+        let commas = first_line.iter().filter(|b| **b == b',').count();
+        let delimiter = if semicolons > commas { b';' } else { b',' };
+
+        let invalid =
+            |e: csv::Error| DumpError::Invalid(format!("The table {} can't be read: {}", name, e));
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(delimiter)
+            .from_reader(file.as_slice());
+        let columns = reader
+            .headers()
+            .map_err(invalid)?
+            .iter()
+            .map(|column| column.trim().to_string())
+            .collect::<Vec<_>>();
+        if columns.iter().any(String::is_empty) {
+            return Err(DumpError::Invalid(format!(
+                "The table {} has a column without a name.",
+                name
+            )));
+        }
+        let rows = reader
+            .records()
+            .collect::<Result<Vec<csv::StringRecord>, csv::Error>>()
+            .map_err(invalid)?;
+
+        let types = (0..columns.len())
+            .map(|i| infer_type(rows.iter().map(|row| row.get(i).unwrap_or_default())))
+            .collect::<Vec<_>>();
+        let definitions = columns
+            .iter()
+            .zip(&types)
+            .map(|(column, sql_type)| format!("{} {}", quote_identifier(column), sql_type))
+            .collect::<Vec<_>>();
+        dump.push_str(&format!(
+            "CREATE TABLE {} ({});\n",
+            quote_identifier(name),
+            definitions.join(", ")
+        ));
+
+        for row in &rows {
+            let values = row
+                .iter()
+                .zip(&types)
+                .map(|(value, sql_type)| {
+                    if value.is_empty() {
+                        "NULL".to_string()
+                    } else if *sql_type == "TEXT" {
+                        quote_literal(value)
+                    } else {
+                        value.to_string()
+                    }
+                })
+                .collect::<Vec<_>>();
+            dump.push_str(&format!(
+                "INSERT INTO {} VALUES({});\n",
+                quote_identifier(name),
+                values.join(",")
+            ));
+        }
+    }
+    Ok(dump)
+}
+
+/// A statement of a dump, with the line it starts in
+#[derive(Debug, PartialEq)]
+struct Statement<'a> {
+    line: usize,
+    sql: &'a str,
+}
+
+/// Splits a dump into its statements, which are separated by semicolons outside of
+/// string literals, identifiers and comments. Triggers only end with `END;`.
+fn split_statements(sql: &str) -> Result<Vec<Statement<'_>>, DumpError> {
+    let mut statements = Vec::new();
+    let mut chars = sql.char_indices().peekable();
+    let mut line = 1;
+    let mut start = None;
+    let mut words = Vec::new();
+    let mut word = String::new();
+
+    loop {
+        let next = chars.next();
+        let is_word = matches!(next, Some((_, c)) if c.is_ascii_alphanumeric() || c == '_');
+        if !is_word && !word.is_empty() {
+            let keyword = word.to_uppercase();
+            if FORBIDDEN_KEYWORDS.contains(&keyword.as_str()) {
+                return Err(DumpError::InvalidStatement(InvalidStatement {
+                    line,
+                    message: format!("{} isn't allowed.", keyword),
+                }));
+            }
+            words.push(keyword);
+            word.clear();
+        }
+        let (i, c) = match next {
+            Some(next) => next,
+            None => break,
+        };
+        let following = chars.peek().map(|(_, c)| *c);
+        let comment = (c == '-' && following == Some('-')) || (c == '/' && following == Some('*'));
+        if start.is_none() && !c.is_whitespace() && !comment {
+            start = Some((i, line));
+        }
+        if is_word {
+            word.push(c);
+            continue;
+        }
+
+        match c {
+            '\n' => line += 1,
+            '\'' | '"' | '`' | '[' => {
+                let end = if c == '[' { ']' } else { c };
+                // a doubled quote is part of the literal, which the loop handles
+                // as the end of one literal and the start of the next one
+                for (_, c) in chars.by_ref() {
+                    if c == '\n' {
+                        line += 1;
+                    }
+                    if c == end {
+                        break;
+                    }
+                }
+            }
+            '-' if comment => {
+                for (_, c) in chars.by_ref() {
+                    if c == '\n' {
+                        line += 1;
+                        break;
+                    }
+                }
+            }
+            '/' if comment => {
+                chars.next();
+                let mut previous = ' ';
+                for (_, c) in chars.by_ref() {
+                    if c == '\n' {
+                        line += 1;
+                    }
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            ';' => {
+                let trigger = words.iter().take(4).any(|word| word == "TRIGGER");
+                if !trigger || words.last().map(String::as_str) == Some("END") {
+                    if let Some((start, line)) = start.take() {
+                        statements.push(Statement {
+                            line,
+                            sql: &sql[start..=i],
+                        });
+                    }
+                    words.clear();
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some((start, line)) = start {
+        statements.push(Statement {
+            line,
+            sql: &sql[start..],
+        });
+    }
+    Ok(statements)
+}
+
+/// A connection for loading dumps, which are written by users. Diesel doesn't give access
+/// to the handle of its connections, which the limits need. Statements are interrupted
+/// once the deadline has passed, and the database can't grow beyond `MAX_PAGES`.
+struct LimitedConnection {
+    db: NonNull<ffi::sqlite3>,
+    /// The progress handler points at it, so it's boxed to stay in place
+    deadline: Box<Instant>,
+}
+
+/// Progress handler, a non-zero result interrupts the running statement
+extern "C" fn past_deadline(deadline: *mut c_void) -> c_int {
+    let deadline = unsafe { &*(deadline as *const Instant) };
+    (Instant::now() >= *deadline) as c_int
+}
+
+impl LimitedConnection {
+    fn open(path: &str) -> Result<Self, DumpError> {
+        let path = CString::new(path).map_err(|e| DumpError::Invalid(e.to_string()))?;
+        let mut db = ptr::null_mut();
+        let status = unsafe { ffi::sqlite3_open(path.as_ptr(), &mut db) };
+        let db = NonNull::new(db).ok_or_else(|| {
+            DumpError::Connection(diesel::ConnectionError::BadConnection(
+                "Out of memory".to_string(),
+            ))
+        })?;
+        let conn = LimitedConnection {
+            db,
+            deadline: Box::new(Instant::now() + LOAD_TIMEOUT),
+        };
+        if status != ffi::SQLITE_OK {
+            return Err(DumpError::Connection(
+                diesel::ConnectionError::BadConnection(conn.message(status, ptr::null_mut())),
+            ));
+        }
+        unsafe {
+            ffi::sqlite3_limit(db.as_ptr(), ffi::SQLITE_LIMIT_LENGTH, MAX_LENGTH);
+            ffi::sqlite3_progress_handler(
+                db.as_ptr(),
+                1000,
+                Some(past_deadline),
+                &*conn.deadline as *const Instant as *mut c_void,
+            );
+        }
+        conn.execute(&format!(
+            "PRAGMA page_size = {}; PRAGMA max_page_count = {};",
+            PAGE_SIZE, MAX_PAGES
+        ))
+        .map_err(|(_, message)| {
+            DumpError::Connection(diesel::ConnectionError::BadConnection(message))
+        })?;
+        Ok(conn)
+    }
+
+    /// Runs statements, errors come with the result code of SQLite
+    fn execute(&self, sql: &str) -> Result<(), (c_int, String)> {
+        let sql = CString::new(sql).map_err(|e| (ffi::SQLITE_ERROR, e.to_string()))?;
+        let mut message = ptr::null_mut();
+        let status = unsafe {
+            ffi::sqlite3_exec(
+                self.db.as_ptr(),
+                sql.as_ptr(),
+                None,
+                ptr::null_mut(),
+                &mut message,
+            )
+        };
+        if status == ffi::SQLITE_OK {
+            Ok(())
+        } else {
+            Err((status, self.message(status, message)))
+        }
+    }
+
+    /// The message SQLite has written, or the one of the result code, which is freed
+    fn message(&self, status: c_int, message: *mut std::os::raw::c_char) -> String {
+        unsafe {
+            if message.is_null() {
+                CStr::from_ptr(ffi::sqlite3_errstr(status))
+                    .to_string_lossy()
+                    .into_owned()
+            } else {
+                let text = CStr::from_ptr(message).to_string_lossy().into_owned();
+                ffi::sqlite3_free(message as *mut c_void);
+                text
+            }
+        }
+    }
+}
+
+impl Drop for LimitedConnection {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::sqlite3_close(self.db.as_ptr());
+        }
+    }
+}
+
+/// Runs all statements of a dump, errors carry the line of the failed statement
+fn load(conn: &LimitedConnection, sql: &str) -> Result<(), DumpError> {
+    for statement in split_statements(sql)? {
+        conn.execute(statement.sql).map_err(|(status, message)| {
+            let message = match status {
+                ffi::SQLITE_INTERRUPT => format!(
+                    "Loading the database takes longer than {} seconds.",
+                    LOAD_TIMEOUT.as_secs()
+                ),
+                ffi::SQLITE_FULL => format!(
+                    "The database gets larger than {} MiB.",
+                    MAX_PAGES * PAGE_SIZE / 1024 / 1024
+                ),
+                _ => message,
+            };
+            DumpError::InvalidStatement(InvalidStatement {
+                line: statement.line,
+                message,
+            })
+        })?;
+    }
+    Ok(())
+}
+
+/// Checks whether the SQL statements of a dump can be loaded into an empty database
+pub fn validate(sql: &str) -> Result<(), DumpError> {
+    load(&LimitedConnection::open(":memory:")?, sql)
+}
+
+/// Loads the stored SQL statements into a SQLite file
+fn load_into_file(sql: &str) -> Result<TempDatabase, DumpError> {
+    let database = TempDatabase::new();
+    let conn = LimitedConnection::open(&database.path.to_string_lossy())?;
+    // the file is thrown away on errors anyway
+    conn.execute("PRAGMA journal_mode = OFF; PRAGMA synchronous = OFF;")
+        .map_err(|(_, message)| DumpError::Invalid(message))?;
+    load(&conn, sql)?;
+    Ok(database)
+}
+
+/// Builds a SQLite file from the stored SQL statements
+pub fn to_sqlite_file(sql: &str) -> Result<Vec<u8>, DumpError> {
+    let database = load_into_file(sql)?;
+    Ok(fs::read(&database.path)?)
+}
+
+fn columns(
+    conn: &SqliteConnection,
+    table: &str,
+) -> Result<Vec<(ColumnSchema, i32)>, diesel::result::Error> {
+    let columns = diesel::select(
+        sql::<(Text, Text, Bool, Nullable<Text>, Integer)>(
+            "name, type, \"notnull\", dflt_value, pk FROM pragma_table_info(",
+        )
+        .bind::<Text, _>(table)
+        .sql(") ORDER BY cid"),
+    )
+    .load::<(String, String, bool, Option<String>, i32)>(conn)?;
+    Ok(columns
+        .into_iter()
+        .map(|(name, column_type, not_null, default, pk)| {
+            let column = ColumnSchema {
+                name,
+                column_type,
+                nullable: !not_null && pk == 0,
+                default,
+            };
+            (column, pk)
+        })
+        .collect())
+}
+
+fn primary_key(columns: &[(ColumnSchema, i32)]) -> Vec<String> {
+    let mut key = columns.iter().filter(|(_, pk)| *pk > 0).collect::<Vec<_>>();
+    key.sort_by_key(|(_, pk)| *pk);
+    key.into_iter()
+        .map(|(column, _)| column.name.clone())
+        .collect()
+}
+
+fn foreign_keys(
+    conn: &SqliteConnection,
+    table: &str,
+) -> Result<Vec<ForeignKeySchema>, diesel::result::Error> {
+    let references = diesel::select(
+        sql::<(Integer, Text, Text, Nullable<Text>)>(
+            "id, \"table\", \"from\", \"to\" FROM pragma_foreign_key_list(",
+        )
+        .bind::<Text, _>(table)
+        .sql(") ORDER BY id, seq"),
+    )
+    .load::<(i32, String, String, Option<String>)>(conn)?;
+
+    let mut keys: Vec<(i32, ForeignKeySchema, bool)> = Vec::new();
+    for (id, referenced_table, from, to) in references {
+        if keys.last().map(|(last, _, _)| *last) != Some(id) {
+            let key = ForeignKeySchema {
+                columns: Vec::new(),
+                referenced_table,
+                referenced_columns: Vec::new(),
+            };
+            keys.push((id, key, false));
+        }
+        let (_, key, implicit) = keys.last_mut().unwrap();
+        key.columns.push(from);
+        match to {
+            Some(to) => key.referenced_columns.push(to),
+            None => *implicit = true,
+        }
+    }
+    // without column names, the primary key of the referenced table is meant
+    let mut result = Vec::new();
+    for (_, mut key, implicit) in keys {
+        if implicit {
+            key.referenced_columns = primary_key(&columns(conn, &key.referenced_table)?);
+        }
+        result.push(key);
+    }
+    Ok(result)
+}
+
+/// Loads a dump and describes its tables and views
+pub fn schema(dump: &str) -> Result<DatabaseSchema, DumpError> {
+    let database = load_into_file(dump)?;
+    let conn = database.connect()?;
+    let objects = diesel::select(sql::<(Text, Text)>(
+        "type, name FROM sqlite_master \
+         WHERE type IN ('table', 'view') AND name NOT LIKE 'sqlite_%' ORDER BY name",
+    ))
+    .load::<(String, String)>(&conn)?;
+
+    let mut schema = DatabaseSchema {
+        tables: Vec::new(),
+        views: Vec::new(),
+    };
+    for (object_type, name) in objects {
+        let columns = columns(&conn, &name)?;
+        if object_type == "view" {
+            schema.views.push(ViewSchema {
+                name,
+                columns: columns.into_iter().map(|(column, _)| column).collect(),
+            });
+            continue;
+        }
+        let row_count = diesel::select(sql::<BigInt>(&format!(
+            "count(*) FROM {}",
+            quote_identifier(&name)
+        )))
+        .get_result::<i64>(&conn)?;
+        schema.tables.push(TableSchema {
+            primary_key: primary_key(&columns),
+            foreign_keys: foreign_keys(&conn, &name)?,
+            columns: columns.into_iter().map(|(column, _)| column).collect(),
+            row_count,
+            name,
+        });
+    }
+    Ok(schema)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_infer_type() {
+        assert_eq!(infer_type(vec!["1", "", "-3"].into_iter()), "INTEGER");
+        assert_eq!(infer_type(vec!["1", "2.5"].into_iter()), "REAL");
+        assert_eq!(infer_type(vec!["1", "NaN"].into_iter()), "TEXT");
+        assert_eq!(infer_type(vec!["1", "one"].into_iter()), "TEXT");
+        assert_eq!(infer_type(vec!["01234", "76131"].into_iter()), "TEXT");
+        assert_eq!(infer_type(vec!["0", "0.5"].into_iter()), "REAL");
+    }
+
+    #[test]
+    fn test_split_statements() {
+        let sql = "CREATE TABLE t (a TEXT);\n-- a; comment\nINSERT INTO t VALUES('x;\ny');\n\
+                   CREATE TRIGGER r AFTER INSERT ON t BEGIN DELETE FROM t; END;\nSELECT 1";
+        let lines = split_statements(sql)
+            .unwrap()
+            .iter()
+            .map(|statement| statement.line)
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec![1, 3, 5, 6]);
+        assert_eq!(split_statements(sql).unwrap()[3].sql, "SELECT 1");
+    }
+
+    #[test]
+    fn test_forbidden_keywords() {
+        assert!(split_statements("SELECT 1;\nattach 'x' AS y;").is_err());
+        assert!(split_statements("INSERT INTO t VALUES('attach');").is_ok());
+        assert!(split_statements("-- vacuum\nSELECT 1; /* attach */").is_ok());
+        assert!(split_statements("SELECT 1;vacuum").is_err());
+    }
+
+    #[test]
+    fn test_limits() {
+        assert!(validate("CREATE TABLE t (a);\nINSERT INTO t VALUES (1);").is_ok());
+        let too_large = "CREATE TABLE t (a);
+            WITH RECURSIVE c(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM c LIMIT 1000000)
+            INSERT INTO t SELECT randomblob(100) FROM c;";
+        match validate(too_large) {
+            Err(DumpError::InvalidStatement(statement)) => {
+                assert_eq!(statement.line, 2);
+                assert!(statement.message.contains("MiB"));
+            }
+            result => panic!("loaded too large a database: {:?}", result),
+        }
+        assert!(validate("SELECT zeroblob(100000000);").is_err());
+    }
+}
@@ -1,46 +1,99 @@
+use crate::client_ip::client_ip;
+use crate::invitations;
+use crate::login_attempts::TooManyAttempts;
+use crate::tokens;
 use crate::{models, schema};
-use actix_web::{dev::ServiceRequest, web, Error, HttpMessage, HttpRequest, HttpResponse, Scope};
+use actix_web::{
+    dev::HttpResponseBuilder, dev::ServiceRequest, web, Error, HttpMessage, HttpRequest,
+    HttpResponse, Scope,
+};
 use actix_web_httpauth::{extractors::basic::BasicAuth, middleware::HttpAuthentication};
+use actix_web_jwt_middleware::{AuthError, Claims, Policy, Subject, TokenSource};
 use diesel::{
     r2d2::{self, ConnectionManager},
     Connection, ExpressionMethods, QueryDsl, RunQueryDsl, SqliteConnection,
 };
 use futures::future::{self, Future, FutureResult, IntoFuture};
-use serde_json::json;
 use uuid::Uuid;
 
 pub fn get_scope() -> Scope {
     let validator =
         |req: ServiceRequest, credentials: BasicAuth| -> FutureResult<ServiceRequest, Error> {
+            let appdata = req.app_data::<crate::AppData>().unwrap();
+            let attempts = &appdata.login_attempts;
+            let username = credentials.user_id();
+            let ip = client_ip(
+                req.peer_addr(),
+                req.headers(),
+                appdata.settings.trusted_proxies(),
+            );
             let result: Result<Uuid, _> = (|| {
+                // checked before the password, so guesses while blocked reveal nothing
+                if let Some(wait) = attempts.retry_after(username, ip) {
+                    return Err(BasicAuthError::Throttled(wait));
+                }
                 let extensions = req.extensions();
                 let conn = extensions
                     .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
                     .unwrap();
-                let user = schema::users::table
-                    .filter(schema::users::name.eq(credentials.user_id()))
-                    .get_result::<models::User>(&*conn)?;
+                let user = match schema::users::table
+                    .filter(schema::users::name.eq(username.as_ref()))
+                    .get_result::<models::User>(&*conn)
+                {
+                    // hashing anyway, so the time taken doesn't tell whether the user exists
+                    Err(diesel::result::Error::NotFound) => {
+                        if let Some(password) = credentials.password() {
+                            appdata.settings.password_hashing().verify_dummy(password);
+                        }
+                        return Err(diesel::result::Error::NotFound.into());
+                    }
+                    result => result?,
+                };
                 let password = credentials
                     .password()
                     .ok_or(BasicAuthError::WrongPwError)
                     .map(|pw| pw.clone().into_owned())?;
-                if user.verify_password(password) {
-                    Ok(Uuid::parse_str(&user.get_raw_id())?)
-                } else {
-                    Err(BasicAuthError::WrongPwError)
+                if !user.verify_password(&password) {
+                    return Err(BasicAuthError::WrongPwError);
+                } else if user.disabled {
+                    return Err(BasicAuthError::Disabled);
                 }
+                // the password is only known now, so this is the chance to upgrade the hash
+                let hashing = appdata.settings.password_hashing();
+                if user.needs_rehash(&hashing) {
+                    if let Err(e) = diesel::update(schema::users::table.find(user.get_raw_id()))
+                        .set((
+                            schema::users::password_hash.eq(hashing.hash(&password)),
+                            schema::users::salt.eq(""),
+                        ))
+                        .execute(&*conn)
+                    {
+                        log::error!("Couldn't rehash password of {}: {}", user.name, e);
+                    }
+                }
+                Ok(Uuid::parse_str(&user.get_raw_id())?)
             })();
             match result {
                 Ok(user_id) => {
+                    attempts.record_success(username);
                     req.extensions_mut().insert(user_id);
                     future::ok(req)
                 }
                 Err(e) => {
                     match e {
-                        BasicAuthError::WrongPwError | BasicAuthError::UserLoadingError(_) => {}
+                        // unknown usernames count too, so they can't be told apart
+                        BasicAuthError::WrongPwError
+                        | BasicAuthError::UserLoadingError(diesel::result::Error::NotFound) => {
+                            log::info!("Failed login of {} from {:?}", username, ip);
+                            attempts.record_failure(username, ip);
+                        }
+                        BasicAuthError::Disabled | BasicAuthError::UserLoadingError(_) => {}
                         BasicAuthError::InvalidUserID => {
                             log::error!("Invalid user id in database, here's request: {:?}", req)
                         }
+                        BasicAuthError::Throttled(wait) => {
+                            return future::err(TooManyAttempts(wait).into())
+                        }
                     };
                     future::err(actix_web::Error::from(()))
                 }
@@ -51,22 +104,47 @@ pub fn get_scope() -> Scope {
         .service(
             web::resource("")
                 .wrap(auth.clone())
+                .wrap(Policy::anonymous())
                 .route(web::get().to_async(get_account))
                 .route(web::put().to_async(update_account))
                 .route(web::delete().to_async(delete_account)),
         )
-        .service(web::resource("/register").route(web::post().to_async(create_account)))
+        .service(
+            web::resource("/register")
+                .wrap(Policy::anonymous())
+                .route(web::post().to_async(create_account)),
+        )
         .service(
             web::resource("/login")
                 .wrap(auth)
+                .wrap(Policy::anonymous())
                 .route(web::post().to_async(login)),
         )
+        .service(
+            web::resource("/refresh")
+                .wrap(Policy::anonymous())
+                .route(web::post().to_async(refresh)),
+        )
+        .service(
+            web::resource("/logout")
+                .wrap(Policy::required())
+                .route(web::post().to_async(logout)),
+        )
+        .service(
+            web::resource("/sessions")
+                .wrap(Policy::required())
+                .route(web::delete().to_async(delete_sessions)),
+        )
 }
 
 enum BasicAuthError {
     UserLoadingError(diesel::result::Error),
     InvalidUserID,
     WrongPwError,
+    /// The password is right, but an admin disabled the account
+    Disabled,
+    /// Too many failed logins for the username or from the address, with the time to wait
+    Throttled(std::time::Duration),
 }
 
 impl From<diesel::result::Error> for BasicAuthError {
@@ -107,50 +185,129 @@ fn update_account(
     req: HttpRequest,
     json: web::Json<models::Account>,
 ) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let appdata: &crate::AppData = req.app_data().unwrap();
     let extensions = req.extensions();
     let conn = extensions
         .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
         .unwrap();
     let user = extensions.get::<Uuid>().unwrap();
     let account_template = json.into_inner();
-    match conn.transaction::<(), diesel::result::Error, _>(|| {
+    match conn.transaction::<(), AccountError, _>(|| {
+        let password_changed = !schema::users::table
+            .find(format!("{}", user))
+            .get_result::<models::User>(&*conn)?
+            .verify_password(&account_template.password);
+        // passwords from before the policy stay usable until they're changed
+        if password_changed {
+            appdata
+                .settings
+                .password_policy()
+                .check(&account_template.username, &account_template.password)
+                .map_err(AccountError::WeakPassword)?;
+        }
+        let updated = models::User::new(
+            account_template.username,
+            &account_template.password,
+            Some(*user),
+            &appdata.settings.password_hashing(),
+        );
+        // the role and state of the user are left alone
         diesel::update(schema::users::table.find(format!("{}", user)))
-            .set(models::User::new(
-                account_template.username,
-                account_template.password,
-                Some(*user),
+            .set((
+                schema::users::name.eq(updated.name),
+                schema::users::password_hash.eq(updated.password_hash),
+                schema::users::salt.eq(updated.salt),
             ))
             .execute(&*conn)?;
+        // a new password signs out every session, e.g. after it was leaked
+        if password_changed {
+            tokens::revoke_all(conn, &user.to_string())?;
+        }
         Ok(())
     }) {
         Ok(_) => Box::new(Ok(HttpResponse::Ok().finish()).into_future()),
-        Err(_) => {
-            Box::new(Ok(HttpResponse::BadRequest().body("Username already taken")).into_future())
-        }
+        Err(e) => Box::new(Ok(e.into_response("update account")).into_future()),
     }
 }
+/// Registers a new account. While registration is closed, it needs an invitation code.
 fn create_account(
     req: HttpRequest,
     json: web::Json<models::Account>,
 ) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let appdata: &crate::AppData = req.app_data().unwrap();
     let extensions = req.extensions();
     let conn = extensions
         .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
         .unwrap();
     let account_template = json.into_inner();
-    match conn.transaction::<(), diesel::result::Error, _>(|| {
+    match conn.transaction::<(), AccountError, _>(|| {
+        appdata
+            .settings
+            .password_policy()
+            .check(&account_template.username, &account_template.password)
+            .map_err(AccountError::WeakPassword)?;
+        if !appdata.settings.open_registration.unwrap_or(true) {
+            let code = account_template
+                .invitation
+                .as_ref()
+                .ok_or(AccountError::InvitationRequired)?;
+            if !invitations::redeem(conn, code)? {
+                return Err(AccountError::InvalidInvitation);
+            }
+        }
         diesel::insert_into(schema::users::table)
             .values(models::User::new(
                 account_template.username,
-                account_template.password,
+                &account_template.password,
                 None,
+                &appdata.settings.password_hashing(),
             ))
             .execute(&*conn)?;
         Ok(())
     }) {
         Ok(_) => Box::new(Ok(HttpResponse::Ok().finish()).into_future()),
-        Err(_) => {
-            Box::new(Ok(HttpResponse::BadRequest().body("Username already taken")).into_future())
+        Err(e) => Box::new(Ok(e.into_response("create account")).into_future()),
+    }
+}
+
+enum AccountError {
+    Diesel(diesel::result::Error),
+    UsernameTaken,
+    /// The new password doesn't follow the password policy, with the reason
+    WeakPassword(String),
+    InvitationRequired,
+    InvalidInvitation,
+}
+
+impl From<diesel::result::Error> for AccountError {
+    fn from(val: diesel::result::Error) -> AccountError {
+        match val {
+            diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                _,
+            ) => AccountError::UsernameTaken,
+            val => AccountError::Diesel(val),
+        }
+    }
+}
+
+impl AccountError {
+    fn into_response(self, action: &str) -> HttpResponse {
+        match self {
+            AccountError::Diesel(e) => {
+                log::error!("Couldn't {}: {}", action, e);
+                HttpResponse::InternalServerError().finish()
+            }
+            AccountError::UsernameTaken => {
+                HttpResponse::BadRequest().body("Username already taken")
+            }
+            AccountError::WeakPassword(reason) => HttpResponse::BadRequest().body(reason),
+            AccountError::InvitationRequired => {
+                HttpResponse::Forbidden().body("Registration needs an invitation code")
+            }
+            AccountError::InvalidInvitation => {
+                HttpResponse::Forbidden().body("The invitation code is invalid, expired or used up")
+            }
         }
     }
 }
@@ -160,18 +317,173 @@ fn delete_account(req: HttpRequest) -> Box<dyn Future<Item = HttpResponse, Error
 fn login(req: HttpRequest) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
     let appdata: &crate::AppData = req.app_data().unwrap();
     let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
     let user = extensions.get::<Uuid>().unwrap();
 
-    match frank_jwt::encode(
-        json!({}),
-        &appdata.settings.jwt_key,
-        &json!({ "sub": user }),
-        frank_jwt::Algorithm::HS512,
+    match tokens::issue(
+        conn,
+        &appdata.settings,
+        &appdata.jwt_keys,
+        &user.to_string(),
     ) {
-        Ok(token) => Box::new(Ok(HttpResponse::Ok().json(json!({ "token": token }))).into_future()),
-        Err(e) => {
-            log::error!("Couldn't encode JWT: {}", e);
-            Box::new(Ok(HttpResponse::InternalServerError().finish()).into_future())
+        Ok(token) => Box::new(Ok(token_response(&appdata.settings, token)).into_future()),
+        Err(e) => Box::new(Ok(e.into_response("log in")).into_future()),
+    }
+}
+
+/// Cookie holding the refresh token in cookie mode, only sent to the account routes
+const REFRESH_COOKIE: &str = "udb_refresh";
+const REFRESH_COOKIE_PATH: &str = "/api/v1/account";
+
+/// Responds with new tokens. In cookie mode they are set as cookies and left out of the body,
+/// so scripts never see them.
+fn token_response(settings: &crate::settings::Settings, token: models::Token) -> HttpResponse {
+    let source = settings.token_source();
+    let config = match source.cookie_config() {
+        Some(config) => config,
+        None => return HttpResponse::Ok().json(token),
+    };
+    let mut response = HttpResponse::Ok();
+    if let (Some(access_token), Some(expires_in)) = (&token.token, token.expires_in) {
+        for cookie in config.token_cookies(
+            access_token,
+            expires_in,
+            tokens::refresh_token_lifetime(settings),
+        ) {
+            response.cookie(cookie);
+        }
+    }
+    if let Some(refresh_token) = &token.refresh_token {
+        response.cookie(config.cookie(
+            REFRESH_COOKIE,
+            refresh_token,
+            REFRESH_COOKIE_PATH,
+            tokens::refresh_token_lifetime(settings),
+            true,
+        ));
+    }
+    match source {
+        TokenSource::Cookie(_) => response.json(models::Token {
+            token: None,
+            expires_in: token.expires_in,
+            refresh_token: None,
+        }),
+        _ => response.json(token),
+    }
+}
+
+/// Removes the token cookies, if there are any
+fn remove_cookies(settings: &crate::settings::Settings, response: &mut HttpResponseBuilder) {
+    if let Some(config) = settings.token_source().cookie_config() {
+        for cookie in config.removal_cookies() {
+            response.cookie(cookie);
+        }
+        response.cookie(config.cookie(REFRESH_COOKIE, "", REFRESH_COOKIE_PATH, 0, true));
+    }
+}
+
+/// The refresh token from the body, or from its cookie in cookie mode
+fn refresh_token(
+    req: &HttpRequest,
+    json: Option<web::Json<models::RefreshRequest>>,
+) -> Option<String> {
+    json.map(|json| json.into_inner().refresh_token)
+        .or_else(|| {
+            req.cookie(REFRESH_COOKIE)
+                .map(|cookie| cookie.value().to_owned())
+        })
+}
+
+/// Trades a refresh token for a new access token and refresh token
+fn refresh(
+    req: HttpRequest,
+    json: Option<web::Json<models::RefreshRequest>>,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let appdata: &crate::AppData = req.app_data().unwrap();
+    let from_cookie = json.is_none();
+    let refresh_token = match refresh_token(&req, json) {
+        Some(refresh_token) => refresh_token,
+        None => {
+            return Box::new(
+                Ok(tokens::TokenError::InvalidRefreshToken.into_response("refresh token"))
+                    .into_future(),
+            )
+        }
+    };
+    // other sites can make the browser send the cookie as well
+    if from_cookie {
+        let csrf_valid = match appdata.settings.token_source().cookie_config() {
+            Some(config) => config.csrf_valid(req.method(), &req),
+            None => false,
+        };
+        if !csrf_valid {
+            return Box::new(Err(AuthError::Csrf.into()).into_future());
+        }
+    }
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    match tokens::refresh(conn, &appdata.settings, &appdata.jwt_keys, &refresh_token) {
+        Ok(token) => Box::new(Ok(token_response(&appdata.settings, token)).into_future()),
+        Err(e) => Box::new(Ok(e.into_response("refresh token")).into_future()),
+    }
+}
+
+/// Revokes the access token of the request and the given refresh token. A missing or stale
+/// refresh token leaves nothing to revoke, the cookies are cleared anyway.
+fn logout(
+    req: HttpRequest,
+    json: Option<web::Json<models::RefreshRequest>>,
+    claims: Claims,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let appdata: &crate::AppData = req.app_data().unwrap();
+    let refresh_token = refresh_token(&req, json);
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    match conn.transaction::<(), tokens::TokenError, _>(|| {
+        tokens::revoke_access(conn, &appdata.settings, &claims)?;
+        match &refresh_token {
+            Some(refresh_token) => tokens::revoke(conn, &sub, refresh_token),
+            // e.g. the refresh cookie has expired already
+            None => Ok(()),
+        }
+    }) {
+        Ok(()) => {
+            let mut response = HttpResponse::NoContent();
+            remove_cookies(&appdata.settings, &mut response);
+            Box::new(Ok(response.finish()).into_future())
+        }
+        Err(e) => Box::new(Ok(e.into_response("log out")).into_future()),
+    }
+}
+
+/// Signs out everywhere, e.g. after using a shared computer
+fn delete_sessions(
+    req: HttpRequest,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let appdata: &crate::AppData = req.app_data().unwrap();
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    match conn.transaction(|| tokens::revoke_all(conn, &sub)) {
+        Ok(()) => {
+            let mut response = HttpResponse::NoContent();
+            remove_cookies(&appdata.settings, &mut response);
+            Box::new(Ok(response.finish()).into_future())
         }
+        Err(e) => Box::new(
+            Ok(tokens::TokenError::from(e).into_response("sign out everywhere")).into_future(),
+        ),
     }
 }
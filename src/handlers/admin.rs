@@ -0,0 +1,247 @@
+use crate::admins;
+use crate::handlers::quotas::quota_usage;
+use crate::tokens;
+use crate::{models, schema};
+use actix_web::{web, Error, HttpRequest, HttpResponse, Scope};
+use actix_web_jwt_middleware::Subject;
+use diesel::{
+    prelude::*,
+    r2d2::{self, ConnectionManager},
+    SqliteConnection,
+};
+use futures::future::{Future, IntoFuture};
+
+/// Endpoints for managing users, to be wrapped in a policy requiring the admin scope
+pub fn get_scope() -> Scope {
+    web::scope("/admin")
+        .service(web::resource("/users").route(web::get().to_async(get_users)))
+        .service(web::resource("/users/{id}").route(web::patch().to_async(update_user)))
+        .service(web::resource("/users/{id}/password").route(web::put().to_async(reset_password)))
+        .service(web::resource("/users/{id}/usage").route(web::get().to_async(get_usage)))
+        .service(
+            web::resource("/users/{id}/reassign").route(web::post().to_async(reassign_objects)),
+        )
+        .service(web::resource("/access/{object_id}").route(web::put().to_async(reassign_object)))
+}
+
+enum AdminError {
+    Diesel(diesel::result::Error),
+    /// Admins can't disable themselves or take away their own role, so there's always one left
+    OwnAccount,
+    /// The new password doesn't follow the password policy, with the reason
+    WeakPassword(String),
+}
+
+impl From<diesel::result::Error> for AdminError {
+    fn from(val: diesel::result::Error) -> AdminError {
+        AdminError::Diesel(val)
+    }
+}
+
+impl AdminError {
+    fn into_response(self, action: &str) -> HttpResponse {
+        match self {
+            AdminError::Diesel(diesel::result::Error::NotFound) => {
+                HttpResponse::NotFound().finish()
+            }
+            AdminError::Diesel(e) => {
+                log::error!("Couldn't {}: {}", action, e);
+                HttpResponse::InternalServerError().finish()
+            }
+            AdminError::OwnAccount => HttpResponse::BadRequest()
+                .body("Admins can't disable their own account or take away their own role."),
+            AdminError::WeakPassword(reason) => HttpResponse::BadRequest().body(reason),
+        }
+    }
+}
+
+fn get_user(conn: &SqliteConnection, id: &str) -> Result<models::UserSummary, AdminError> {
+    Ok(schema::users::table
+        .find(id)
+        .select((
+            schema::users::id,
+            schema::users::name,
+            schema::users::is_admin,
+            schema::users::disabled,
+        ))
+        .get_result::<models::UserSummary>(conn)?)
+}
+
+fn get_users(req: HttpRequest) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    match schema::users::table
+        .select((
+            schema::users::id,
+            schema::users::name,
+            schema::users::is_admin,
+            schema::users::disabled,
+        ))
+        .order(schema::users::name)
+        .load::<models::UserSummary>(&*conn)
+    {
+        Ok(users) => Box::new(Ok(HttpResponse::Ok().json(users)).into_future()),
+        Err(e) => Box::new(Ok(AdminError::from(e).into_response("get users")).into_future()),
+    }
+}
+
+/// Grants or takes away the admin role and disables or enables the account. The sessions of
+/// the user end, so new tokens carry the changed role and disabled users are signed out.
+fn update_user(
+    req: HttpRequest,
+    id: web::Path<String>,
+    json: web::Json<models::UserUpdate>,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+    let update = json.into_inner();
+
+    match conn.transaction::<models::UserSummary, AdminError, _>(|| {
+        if *id == sub && (update.is_admin == Some(false) || update.disabled == Some(true)) {
+            return Err(AdminError::OwnAccount);
+        }
+        let user = get_user(conn, &id)?;
+        if let Some(admin) = update.is_admin {
+            admins::set_admin(conn, &user.id, admin)?;
+        }
+        if let Some(disabled) = update.disabled {
+            diesel::update(schema::users::table.find(&user.id))
+                .set(schema::users::disabled.eq(disabled))
+                .execute(conn)?;
+            tokens::revoke_all(conn, &user.id)?;
+        }
+        get_user(conn, &id)
+    }) {
+        Ok(user) => Box::new(Ok(HttpResponse::Ok().json(user)).into_future()),
+        Err(e) => Box::new(Ok(e.into_response("update user")).into_future()),
+    }
+}
+
+/// Sets a new password, e.g. for users who forgot theirs, and ends their sessions
+fn reset_password(
+    req: HttpRequest,
+    id: web::Path<String>,
+    json: web::Json<models::PasswordReset>,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let appdata: &crate::AppData = req.app_data().unwrap();
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    match conn.transaction::<(), AdminError, _>(|| {
+        let user = get_user(conn, &id)?;
+        appdata
+            .settings
+            .password_policy()
+            .check(&user.name, &json.password)
+            .map_err(AdminError::WeakPassword)?;
+        diesel::update(schema::users::table.find(&user.id))
+            .set((
+                schema::users::password_hash
+                    .eq(appdata.settings.password_hashing().hash(&json.password)),
+                schema::users::salt.eq(""),
+            ))
+            .execute(conn)?;
+        tokens::revoke_all(conn, &user.id)?;
+        Ok(())
+    }) {
+        Ok(()) => Box::new(Ok(HttpResponse::NoContent().finish()).into_future()),
+        Err(e) => Box::new(Ok(e.into_response("reset password")).into_future()),
+    }
+}
+
+fn get_usage(
+    req: HttpRequest,
+    id: web::Path<String>,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let appdata: &crate::AppData = req.app_data().unwrap();
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    match get_user(conn, &id).and_then(|user| Ok(quota_usage(conn, &appdata.settings, &user.id)?)) {
+        Ok(usage) => Box::new(Ok(HttpResponse::Ok().json(usage)).into_future()),
+        Err(e) => Box::new(Ok(e.into_response("get usage")).into_future()),
+    }
+}
+
+/// Gives the user access to the objects, keeping access the user had already
+fn grant_access(
+    conn: &SqliteConnection,
+    user_id: &str,
+    object_ids: &[String],
+) -> Result<(), diesel::result::Error> {
+    for object_id in object_ids {
+        diesel::insert_or_ignore_into(schema::access::table)
+            .values(models::Access {
+                user_id: user_id.to_owned(),
+                object_id: object_id.clone(),
+            })
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+/// Moves the access to every object of a user to another one, e.g. when someone leaves
+fn reassign_objects(
+    req: HttpRequest,
+    id: web::Path<String>,
+    json: web::Json<models::AccessReassignment>,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    match conn.transaction::<usize, AdminError, _>(|| {
+        let from = get_user(conn, &id)?;
+        let to = get_user(conn, &json.user_id)?;
+        let object_ids = schema::access::table
+            .filter(schema::access::user_id.eq(&from.id))
+            .select(schema::access::object_id)
+            .load::<String>(conn)?;
+        grant_access(conn, &to.id, &object_ids)?;
+        diesel::delete(schema::access::table.filter(schema::access::user_id.eq(&from.id)))
+            .execute(conn)?;
+        Ok(object_ids.len())
+    }) {
+        Ok(count) => Box::new(Ok(HttpResponse::Ok().body(count.to_string())).into_future()),
+        Err(e) => Box::new(Ok(e.into_response("reassign objects")).into_future()),
+    }
+}
+
+/// Makes a user the only one with access to an object
+fn reassign_object(
+    req: HttpRequest,
+    object_id: web::Path<String>,
+    json: web::Json<models::AccessReassignment>,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    match conn.transaction::<(), AdminError, _>(|| {
+        let to = get_user(conn, &json.user_id)?;
+        // only objects somebody has access to are known
+        match diesel::delete(
+            schema::access::table.filter(schema::access::object_id.eq(object_id.as_str())),
+        )
+        .execute(conn)?
+        {
+            0 => Err(diesel::result::Error::NotFound.into()),
+            _ => Ok(grant_access(conn, &to.id, &[object_id.to_string()])?),
+        }
+    }) {
+        Ok(()) => Box::new(Ok(HttpResponse::NoContent().finish()).into_future()),
+        Err(e) => Box::new(Ok(e.into_response("reassign object")).into_future()),
+    }
+}
@@ -1,7 +1,9 @@
 use crate::alias_generator::AliasGenerator;
 use crate::models;
+use crate::quota::{self, Exceeded};
 use crate::schema;
 use actix_web::{web, Error, HttpRequest, HttpResponse, Scope};
+use actix_web_jwt_middleware::{Policy, Subject};
 use diesel::{
     r2d2::{self, ConnectionManager},
     sqlite::SqliteConnection,
@@ -13,55 +15,71 @@ use uuid::Uuid;
 
 pub fn get_scope() -> Scope {
     web::scope("/alias")
-        .service(web::resource("").route(web::post().to_async(create_alias)))
-        .service(web::resource("/{id}").route(web::get().to_async(get_alias)))
-        .service(web::resource("/uuid/{alias}").route(web::get().to_async(get_uuid)))
+        .service(
+            web::resource("")
+                .wrap(Policy::required())
+                .route(web::post().to_async(create_alias)),
+        )
+        .service(
+            web::resource("/{id}")
+                .wrap(Policy::anonymous())
+                .route(web::get().to_async(get_alias)),
+        )
+        .service(
+            web::resource("/uuid/{alias}")
+                .wrap(Policy::anonymous())
+                .route(web::get().to_async(get_uuid)),
+        )
 }
 
 fn create_alias(
     req: HttpRequest,
     json: web::Json<models::AliasRequest>,
+    Subject(sub): Subject,
 ) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let appdata: &crate::AppData = req.app_data().unwrap();
     let extensions = req.extensions();
     let conn = extensions
         .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
         .unwrap();
 
     match conn.transaction::<String, AliasError, _>(|| {
-        let alias_req = json.into_inner();
-        lazy_static! {
-            static ref GENERATOR: AliasGenerator = AliasGenerator::default();
-        }
-        let mut alias = models::Alias {
-            alias: GENERATOR.generate(4),
-            object_id: alias_req.object_id,
-            object_type: alias_req.object_type,
-        };
-        // Try to find a free alias 20 times
-        for i in 0..20 {
-            match diesel::insert_into(schema::aliases::table)
-                .values(alias.clone())
-                .execute(&*conn)
-            {
-                Ok(_) => return Ok(alias.alias),
-                Err(e) => match e {
-                    diesel::result::Error::DatabaseError(
-                        diesel::result::DatabaseErrorKind::ForeignKeyViolation,
-                        _,
-                    )
-                    | diesel::result::Error::DatabaseError(
-                        diesel::result::DatabaseErrorKind::UniqueViolation,
-                        _,
-                    ) => {
-                        // Try to find a four word alias for five times, then five words for five times,
-                        // then six for five times and then seven for five times.
-                        alias.alias = GENERATOR.generate(4 + i / 5);
-                    }
-                    e => return Err(AliasError::from(e)),
-                },
+        quota::enforce(conn, &appdata.settings, &sub, || {
+            let alias_req = json.into_inner();
+            lazy_static! {
+                static ref GENERATOR: AliasGenerator = AliasGenerator::default();
             }
-        }
-        Err(AliasError::NoFreeAliases)
+            let mut alias = models::Alias {
+                alias: GENERATOR.generate(4),
+                object_id: alias_req.object_id,
+                object_type: alias_req.object_type,
+            };
+            // Try to find a free alias 20 times
+            for i in 0..20 {
+                match diesel::insert_into(schema::aliases::table)
+                    .values(alias.clone())
+                    .execute(&*conn)
+                {
+                    Ok(_) => return Ok(alias.alias),
+                    Err(e) => match e {
+                        diesel::result::Error::DatabaseError(
+                            diesel::result::DatabaseErrorKind::ForeignKeyViolation,
+                            _,
+                        )
+                        | diesel::result::Error::DatabaseError(
+                            diesel::result::DatabaseErrorKind::UniqueViolation,
+                            _,
+                        ) => {
+                            // Try to find a four word alias for five times, then five words for five times,
+                            // then six for five times and then seven for five times.
+                            alias.alias = GENERATOR.generate(4 + i / 5);
+                        }
+                        e => return Err(AliasError::from(e)),
+                    },
+                }
+            }
+            Err(AliasError::NoFreeAliases)
+        })
     }) {
         Ok(alias) => Box::new(Ok(HttpResponse::Ok().body(alias)).into_future()),
         Err(e) => match e {
@@ -73,6 +91,7 @@ fn create_alias(
                 Ok(HttpResponse::InternalServerError().body("Couldn't find a free alias."))
                     .into_future(),
             ),
+            AliasError::Quota(exceeded) => Box::new(Ok(exceeded.into_response()).into_future()),
         },
     }
 }
@@ -80,6 +99,13 @@ fn create_alias(
 enum AliasError {
     Diesel(diesel::result::Error),
     NoFreeAliases,
+    Quota(Exceeded),
+}
+
+impl From<Exceeded> for AliasError {
+    fn from(val: Exceeded) -> AliasError {
+        AliasError::Quota(val)
+    }
 }
 
 impl From<diesel::result::Error> for AliasError {
@@ -0,0 +1,275 @@
+use crate::cloning::Cloner;
+use crate::middlewares::ownership::has_access;
+use crate::models::{self, ObjectType};
+use crate::quota::{self, Exceeded};
+use crate::schema;
+use actix_web::{web, Error, HttpRequest, HttpResponse, Scope};
+use actix_web_jwt_middleware::Subject;
+use diesel::{
+    prelude::*,
+    r2d2::{self, ConnectionManager},
+    SqliteConnection,
+};
+use futures::future::{Future, IntoFuture};
+use serde::Deserialize;
+use uuid::Uuid;
+
+pub fn get_scope() -> Scope {
+    web::scope("/catalog")
+        .service(
+            web::resource("")
+                .route(web::get().to_async(get_catalog))
+                .route(web::post().to_async(publish)),
+        )
+        .service(
+            web::resource("/{id}")
+                .route(web::get().to_async(get_entry))
+                .route(web::put().to_async(update_entry))
+                .route(web::delete().to_async(unpublish)),
+        )
+        .service(web::resource("/{id}/clone").route(web::post().to_async(clone_entry)))
+}
+
+/// Query string for browsing the catalog, e.g. `?q=joins&type=WORKSHEET`
+#[derive(Debug, Deserialize)]
+pub struct CatalogQuery {
+    q: Option<String>,
+    #[serde(rename = "type")]
+    object_type: Option<ObjectType>,
+}
+
+enum CatalogError {
+    Diesel(diesel::result::Error),
+    NoAccess,
+    NotPublishable,
+    AlreadyPublished,
+    Quota(Exceeded),
+}
+
+impl From<Exceeded> for CatalogError {
+    fn from(val: Exceeded) -> CatalogError {
+        CatalogError::Quota(val)
+    }
+}
+
+impl From<diesel::result::Error> for CatalogError {
+    fn from(val: diesel::result::Error) -> CatalogError {
+        match val {
+            diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                _,
+            ) => CatalogError::AlreadyPublished,
+            val => CatalogError::Diesel(val),
+        }
+    }
+}
+
+impl CatalogError {
+    fn into_response(self, action: &str) -> HttpResponse {
+        match self {
+            CatalogError::Diesel(diesel::result::Error::NotFound) => {
+                HttpResponse::NotFound().finish()
+            }
+            CatalogError::Diesel(e) => {
+                log::error!("Couldn't {}: {}", action, e);
+                HttpResponse::InternalServerError().finish()
+            }
+            CatalogError::NoAccess => HttpResponse::Forbidden().finish(),
+            CatalogError::NotPublishable => HttpResponse::BadRequest()
+                .body("Only tasks, worksheets and courses can be published."),
+            CatalogError::AlreadyPublished => {
+                HttpResponse::Conflict().body("This object has already been published.")
+            }
+            CatalogError::Quota(exceeded) => exceeded.into_response(),
+        }
+    }
+}
+
+fn get_catalog(
+    req: HttpRequest,
+    query: web::Query<CatalogQuery>,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    let mut entries = schema::catalog_entries::table
+        .order(schema::catalog_entries::published_at.desc())
+        .into_boxed();
+    if let Some(q) = &query.q {
+        // the wildcards of LIKE are matched literally in the search term
+        let pattern = format!(
+            "%{}%",
+            q.replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        );
+        entries = entries.filter(
+            schema::catalog_entries::title
+                .like(pattern.clone())
+                .escape('\\')
+                .or(schema::catalog_entries::description
+                    .like(pattern)
+                    .escape('\\')),
+        );
+    }
+    if let Some(object_type) = query.object_type {
+        entries = entries.filter(schema::catalog_entries::object_type.eq(object_type));
+    }
+
+    match entries.load::<models::CatalogEntry>(&*conn) {
+        Ok(result) => Box::new(Ok(HttpResponse::Ok().json(result)).into_future()),
+        Err(e) => {
+            log::error!("Couldn't get catalog: {}", e);
+            Box::new(Ok(HttpResponse::InternalServerError().finish()).into_future())
+        }
+    }
+}
+
+fn publish(
+    req: HttpRequest,
+    json: web::Json<models::CatalogEntryRequest>,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    match conn.transaction::<Uuid, CatalogError, _>(|| {
+        let entry_req = json.into_inner();
+        match entry_req.object_type {
+            ObjectType::COURSE | ObjectType::WORKSHEET | ObjectType::TASK => {}
+            _ => return Err(CatalogError::NotPublishable),
+        }
+        if !has_access(conn, &sub, &entry_req.object_id)? {
+            return Err(CatalogError::NoAccess);
+        }
+
+        let entry_id = Uuid::new_v4();
+
+        // insert access for user, so the entry can be changed and unpublished later
+        diesel::insert_into(schema::access::table)
+            .values(models::Access {
+                user_id: sub.clone(),
+                object_id: entry_id.to_string(),
+            })
+            .execute(&*conn)?;
+
+        diesel::insert_into(schema::catalog_entries::table)
+            .values(models::CatalogEntry {
+                id: entry_id.to_string(),
+                object_id: entry_req.object_id,
+                object_type: entry_req.object_type,
+                title: entry_req.title,
+                description: entry_req.description,
+                license: entry_req.license,
+                published_by: sub.clone(),
+                published_at: chrono::Utc::now().naive_utc(),
+            })
+            .execute(&*conn)?;
+
+        Ok(entry_id)
+    }) {
+        Ok(id) => Box::new(Ok(HttpResponse::Ok().body(id.to_string())).into_future()),
+        Err(e) => Box::new(Ok(e.into_response("publish to catalog")).into_future()),
+    }
+}
+
+fn get_entry(
+    req: HttpRequest,
+    id: web::Path<Uuid>,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    match schema::catalog_entries::table
+        .find(format!("{}", id))
+        .get_result::<models::CatalogEntry>(&*conn)
+    {
+        Ok(entry) => Box::new(Ok(HttpResponse::Ok().json(entry)).into_future()),
+        Err(e) => {
+            Box::new(Ok(CatalogError::from(e).into_response("get catalog entry")).into_future())
+        }
+    }
+}
+
+fn update_entry(
+    req: HttpRequest,
+    id: web::Path<Uuid>,
+    json: web::Json<models::CatalogEntryRequest>,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    // the published object itself can't be swapped out, only its description
+    let entry_req = json.into_inner();
+    match diesel::update(schema::catalog_entries::table.find(format!("{}", id)))
+        .set((
+            schema::catalog_entries::title.eq(entry_req.title),
+            schema::catalog_entries::description.eq(entry_req.description),
+            schema::catalog_entries::license.eq(entry_req.license),
+        ))
+        .execute(&*conn)
+    {
+        Ok(_) => Box::new(Ok(HttpResponse::Ok().finish()).into_future()),
+        Err(e) => {
+            log::error!("Couldn't update catalog entry: {}", e);
+            Box::new(Ok(HttpResponse::InternalServerError().finish()).into_future())
+        }
+    }
+}
+
+fn unpublish(
+    req: HttpRequest,
+    id: web::Path<Uuid>,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    let uuid = id.into_inner().to_string();
+
+    match conn.transaction::<(), diesel::result::Error, _>(|| {
+        diesel::delete(schema::access::table.filter(schema::access::object_id.eq(&uuid)))
+            .execute(&*conn)?;
+        diesel::delete(schema::catalog_entries::table.find(&uuid)).execute(&*conn)?;
+        Ok(())
+    }) {
+        Ok(_) => Box::new(Ok(HttpResponse::Ok().finish()).into_future()),
+        Err(e) => {
+            log::error!("Couldn't unpublish catalog entry: {}", e);
+            Box::new(Ok(HttpResponse::InternalServerError().finish()).into_future())
+        }
+    }
+}
+
+fn clone_entry(
+    req: HttpRequest,
+    id: web::Path<Uuid>,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let appdata: &crate::AppData = req.app_data().unwrap();
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    match conn.transaction::<String, CatalogError, _>(|| {
+        quota::enforce(conn, &appdata.settings, &sub, || {
+            let entry = schema::catalog_entries::table
+                .find(format!("{}", id))
+                .get_result::<models::CatalogEntry>(&*conn)?;
+            Ok(Cloner::new(conn, sub.clone()).clone_object(&entry.object_id, entry.object_type)?)
+        })
+    }) {
+        Ok(object_id) => Box::new(Ok(HttpResponse::Ok().body(object_id)).into_future()),
+        Err(e) => Box::new(Ok(e.into_response("clone catalog entry")).into_future()),
+    }
+}
@@ -0,0 +1,185 @@
+use crate::integrity::{IntegrityError, InvalidReferences};
+use crate::middlewares::conditional::{self, PreconditionFailed};
+use crate::middlewares::ownership::has_access;
+use crate::ordering::{self, ChildLinks};
+use actix_web::{http::header::ETAG, web, Error, HttpRequest, HttpResponse, Scope};
+use actix_web_jwt_middleware::Subject;
+use diesel::{
+    r2d2::{self, ConnectionManager},
+    SqliteConnection,
+};
+use futures::future::{Future, IntoFuture};
+use serde::Deserialize;
+use uuid::Uuid;
+
+/// Endpoints for inserting, moving and removing single children, to be nested
+/// into the scope of the parent's object type, e.g. with `/{id}/worksheets`
+pub fn get_scope<L: ChildLinks + 'static>(path: &str) -> Scope {
+    web::scope(path)
+        .service(web::resource("").route(web::post().to_async(insert_child::<L>)))
+        .service(
+            web::resource("/{child}")
+                .route(web::put().to_async(move_child::<L>))
+                .route(web::delete().to_async(remove_child::<L>)),
+        )
+}
+
+/// Body for inserting a child, it is appended if no position is given
+#[derive(Debug, Deserialize)]
+pub struct InsertChild {
+    id: String,
+    position: Option<u32>,
+}
+
+/// Body for moving a child
+#[derive(Debug, Deserialize)]
+pub struct MoveChild {
+    position: u32,
+}
+
+enum ChildError {
+    Integrity(IntegrityError),
+    NoAccess,
+    Conflict,
+}
+
+impl From<diesel::result::Error> for ChildError {
+    fn from(val: diesel::result::Error) -> ChildError {
+        match val {
+            diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                _,
+            ) => ChildError::Conflict,
+            val => ChildError::Integrity(val.into()),
+        }
+    }
+}
+
+impl From<PreconditionFailed> for ChildError {
+    fn from(_: PreconditionFailed) -> ChildError {
+        ChildError::Integrity(IntegrityError::PreconditionFailed)
+    }
+}
+
+impl From<IntegrityError> for ChildError {
+    fn from(val: IntegrityError) -> ChildError {
+        ChildError::Integrity(val)
+    }
+}
+
+impl ChildError {
+    fn into_response(self, action: &str) -> HttpResponse {
+        match self {
+            ChildError::Integrity(e) => e.into_response(action),
+            ChildError::NoAccess => HttpResponse::Forbidden().finish(),
+            ChildError::Conflict => HttpResponse::Conflict().body("The child is already linked."),
+        }
+    }
+}
+
+/// Runs a change of the children of `parent` and answers with the new order. The change
+/// is one of the parent, so `If-Match` and the `ETag` are those of the parent.
+fn change_children<L: ChildLinks, F>(
+    req: &HttpRequest,
+    conn: &SqliteConnection,
+    user_id: &str,
+    parent: &str,
+    change: F,
+) -> Result<HttpResponse, ChildError>
+where
+    F: FnOnce() -> Result<(), ChildError>,
+{
+    let (children, version) = conn.immediate_transaction::<_, ChildError, _>(|| {
+        if !has_access(conn, user_id, parent)? {
+            return Err(ChildError::NoAccess);
+        }
+        conditional::check_if_match::<ChildError>(req, conn, L::PARENTS, parent)?;
+        L::before_change(conn, parent)?;
+        change()?;
+        L::after_change(conn, parent, user_id)?;
+        Ok((
+            L::children(conn, parent)?,
+            conditional::current_version(conn, L::PARENTS, parent)?,
+        ))
+    })?;
+    let mut response = HttpResponse::Ok();
+    if let Some(version) = version {
+        response.header(ETAG, conditional::etag(version));
+    }
+    Ok(response.json(children))
+}
+
+fn insert_child<L: ChildLinks>(
+    req: HttpRequest,
+    id: web::Path<Uuid>,
+    child: web::Json<InsertChild>,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    let parent = id.into_inner().to_string();
+    let child = child.into_inner();
+    match change_children::<L, _>(&req, conn, &sub, &parent, || {
+        InvalidReferences::default()
+            .check(conn, &sub, L::CHILD, std::slice::from_ref(&child.id))?
+            .into_result()?;
+        Ok(ordering::insert::<L>(
+            conn,
+            &parent,
+            &child.id,
+            child.position,
+        )?)
+    }) {
+        Ok(response) => Box::new(Ok(response).into_future()),
+        Err(e) => Box::new(Ok(e.into_response("insert child")).into_future()),
+    }
+}
+
+fn move_child<L: ChildLinks>(
+    req: HttpRequest,
+    path: web::Path<(Uuid, Uuid)>,
+    target: web::Json<MoveChild>,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    let (parent, child) = path.into_inner();
+    let parent = parent.to_string();
+    match change_children::<L, _>(&req, conn, &sub, &parent, || {
+        Ok(ordering::move_child::<L>(
+            conn,
+            &parent,
+            &child.to_string(),
+            target.position,
+        )?)
+    }) {
+        Ok(response) => Box::new(Ok(response).into_future()),
+        Err(e) => Box::new(Ok(e.into_response("move child")).into_future()),
+    }
+}
+
+fn remove_child<L: ChildLinks>(
+    req: HttpRequest,
+    path: web::Path<(Uuid, Uuid)>,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    let (parent, child) = path.into_inner();
+    let parent = parent.to_string();
+    match change_children::<L, _>(&req, conn, &sub, &parent, || {
+        Ok(ordering::remove::<L>(conn, &parent, &child.to_string())?)
+    }) {
+        Ok(response) => Box::new(Ok(response).into_future()),
+        Err(e) => Box::new(Ok(e.into_response("remove child")).into_future()),
+    }
+}
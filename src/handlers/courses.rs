@@ -1,7 +1,15 @@
+use crate::handlers::{children, public_read};
+use crate::integrity::{self, IntegrityError, InvalidReferences};
+use crate::middlewares::conditional;
 use crate::models;
-use crate::models::WorksheetsInCourse;
+use crate::models::{ObjectType, WorksheetsInCourse};
+use crate::ordering::CourseWorksheets;
+use crate::patch::{self, PatchError};
+use crate::quota;
 use crate::schema;
+use crate::trash;
 use actix_web::{web, Error, HttpRequest, HttpResponse, Scope};
+use actix_web_jwt_middleware::{Policy, Subject};
 use diesel::{
     prelude::*,
     r2d2::{self, ConnectionManager},
@@ -14,29 +22,54 @@ pub fn get_scope() -> Scope {
     web::scope("/courses")
         .service(
             web::resource("")
+                .wrap(Policy::required())
                 .route(web::get().to_async(get_courses))
                 .route(web::post().to_async(create_course)),
         )
         .service(
             web::resource("/{id}")
+                .wrap(public_read())
                 .route(web::get().to_async(get_course))
                 .route(web::put().to_async(update_course))
+                .route(web::patch().to_async(patch_course))
                 .route(web::delete().to_async(delete_course)),
         )
+        .service(
+            children::get_scope::<CourseWorksheets>("/{id}/worksheets").wrap(Policy::required()),
+        )
+}
+
+pub fn load_course(
+    conn: &SqliteConnection,
+    id: &str,
+) -> Result<models::Course, diesel::result::Error> {
+    let course = schema::courses::table
+        .find(id)
+        .select(models::QueryableCourse::COLUMNS)
+        .get_result::<models::QueryableCourse>(conn)?;
+
+    let worksheets = schema::worksheets_in_courses::table
+        .filter(schema::worksheets_in_courses::columns::course_id.eq(id))
+        .select(schema::worksheets_in_courses::columns::worksheet_id)
+        .order(schema::worksheets_in_courses::position)
+        .load::<String>(conn)?;
+
+    Ok(models::Course {
+        id: course.id,
+        name: course.name,
+        description: course.description,
+        worksheets,
+    })
 }
 
-fn get_courses(req: HttpRequest) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+fn get_courses(
+    req: HttpRequest,
+    Subject(current_user): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
     let extensions = req.extensions();
     let conn = extensions
         .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
         .unwrap();
-    let current_user = extensions
-        .get::<actix_web_jwt_middleware::AuthenticationData>()
-        .unwrap()
-        .claims
-        .sub
-        .clone()
-        .unwrap();
 
     let query = schema::courses::table
         .inner_join(
@@ -79,60 +112,57 @@ fn get_courses(req: HttpRequest) -> Box<dyn Future<Item = HttpResponse, Error =
 fn create_course(
     req: HttpRequest,
     json: web::Json<models::Course>,
+    Subject(sub): Subject,
 ) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let appdata: &crate::AppData = req.app_data().unwrap();
     let extensions = req.extensions();
     let conn = extensions
         .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
         .unwrap();
-    let sub = extensions
-        .get::<actix_web_jwt_middleware::AuthenticationData>()
-        .unwrap()
-        .claims
-        .sub
-        .clone()
-        .unwrap();
 
-    match conn.transaction::<Uuid, diesel::result::Error, _>(|| {
-        // create course object
-        let course = json.into_inner();
-        let course_id = Uuid::new_v4();
-        let new_course = models::QueryableCourse {
-            id: course_id.to_string(),
-            name: course.name,
-            description: course.description,
-        };
+    match conn.transaction::<Uuid, IntegrityError, _>(|| {
+        quota::enforce(conn, &appdata.settings, &sub, || {
+            // create course object
+            let course = json.into_inner();
+            InvalidReferences::default()
+                .check(conn, &sub, ObjectType::WORKSHEET, &course.worksheets)?
+                .into_result()?;
+            let course_id = Uuid::new_v4();
+            let new_course = models::QueryableCourse {
+                id: course_id.to_string(),
+                name: course.name,
+                description: course.description,
+            };
 
-        // insert access for user
-        diesel::insert_into(schema::access::table)
-            .values(models::Access {
-                user_id: sub,
-                object_id: course_id.to_string(),
-            })
-            .execute(&*conn)?;
-
-        // set worksheets belonging to course
-        for (position, worksheet) in course.worksheets.iter().enumerate() {
-            diesel::insert_into(schema::worksheets_in_courses::table)
-                .values(models::WorksheetsInCourse {
-                    worksheet_id: worksheet.to_string(),
-                    course_id: course_id.to_string(),
-                    position: position as i32,
+            // insert access for user
+            diesel::insert_into(schema::access::table)
+                .values(models::Access {
+                    user_id: sub.clone(),
+                    object_id: course_id.to_string(),
                 })
                 .execute(&*conn)?;
-        }
 
-        // insert course object
-        diesel::insert_into(schema::courses::table)
-            .values(new_course)
-            .execute(&*conn)?;
+            // insert course object
+            diesel::insert_into(schema::courses::table)
+                .values(new_course)
+                .execute(&*conn)?;
+
+            // set worksheets belonging to course
+            for (position, worksheet) in course.worksheets.iter().enumerate() {
+                diesel::insert_into(schema::worksheets_in_courses::table)
+                    .values(models::WorksheetsInCourse {
+                        worksheet_id: worksheet.to_string(),
+                        course_id: course_id.to_string(),
+                        position: position as i32,
+                    })
+                    .execute(&*conn)?;
+            }
 
-        Ok(course_id)
+            Ok(course_id)
+        })
     }) {
         Ok(course_id) => Box::new(Ok(HttpResponse::Ok().body(course_id.to_string())).into_future()),
-        Err(e) => {
-            log::error!("Could not create course: {}", e);
-            Box::new(Ok(HttpResponse::InternalServerError().finish()).into_future())
-        }
+        Err(e) => Box::new(Ok(e.into_response("create course")).into_future()),
     }
 }
 
@@ -145,27 +175,8 @@ fn get_course(
         .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
         .unwrap();
 
-    match schema::courses::table
-        .find(format!("{}", id))
-        .get_result::<models::QueryableCourse>(&*conn)
-    {
-        Ok(course) => {
-            let worksheets_query = schema::worksheets_in_courses::table
-                .filter(schema::worksheets_in_courses::columns::course_id.eq(format!("{}", id)))
-                .select(schema::worksheets_in_courses::columns::worksheet_id)
-                .order(schema::worksheets_in_courses::position)
-                .load::<String>(&*conn);
-
-            Box::new(
-                Ok(HttpResponse::Ok().json(models::Course {
-                    id: course.id,
-                    name: course.name,
-                    description: course.description,
-                    worksheets: worksheets_query.unwrap(),
-                }))
-                .into_future(),
-            )
-        }
+    match load_course(conn, &id.to_string()) {
+        Ok(course) => Box::new(Ok(HttpResponse::Ok().json(course)).into_future()),
         Err(e) => match e {
             diesel::result::Error::NotFound => {
                 Box::new(Ok(HttpResponse::NotFound().finish()).into_future())
@@ -178,92 +189,107 @@ fn get_course(
     }
 }
 
+pub fn save_course(
+    conn: &SqliteConnection,
+    id: &str,
+    mut course: models::Course,
+) -> Result<(), diesel::result::Error> {
+    course.id = id.to_string();
+
+    // update course
+    diesel::update(schema::courses::table.find(id))
+        .set(models::QueryableCourse::from_course(course.clone()))
+        .execute(conn)?;
+
+    // update which worksheets belong to course
+    // first delete old ones
+    diesel::delete(
+        schema::worksheets_in_courses::table
+            .filter(schema::worksheets_in_courses::course_id.eq(id)),
+    )
+    .execute(conn)?;
+    // then insert new ones
+    for (position, worksheet_id) in course.worksheets.iter().enumerate() {
+        diesel::insert_into(schema::worksheets_in_courses::table)
+            .values(WorksheetsInCourse {
+                worksheet_id: worksheet_id.to_string(),
+                course_id: id.to_string(),
+                position: position as i32,
+            })
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
 fn update_course(
     req: HttpRequest,
     id: web::Path<Uuid>,
     json: web::Json<models::Course>,
+    Subject(sub): Subject,
 ) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
     let extensions = req.extensions();
     let conn = extensions
         .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
         .unwrap();
 
-    let course = json.into_inner();
-    let id = format!("{}", id.into_inner());
-    match conn.transaction::<(), diesel::result::Error, _>(|| {
-        // update course
-        diesel::update(schema::courses::table.filter(schema::courses::id.eq(id)))
-            .set(models::QueryableCourse::from_course(course.clone()))
-            .execute(&*conn)?;
-
-        // update which worksheets belong to course
-        // first delete old ones
-        diesel::delete(
-            schema::worksheets_in_courses::table
-                .filter(schema::worksheets_in_courses::course_id.eq(course.id.clone())),
-        )
-        .execute(&*conn)?;
-        // then list new ones
-        let mut pos = -1;
-        let course_id = course.id.clone();
-        let worksheets_in_course: Vec<WorksheetsInCourse> = course
-            .worksheets
-            .iter()
-            .map(|worksheet_id| {
-                pos += 1;
-                models::WorksheetsInCourse {
-                    worksheet_id: worksheet_id.to_string(),
-                    course_id: course_id.clone(),
-                    position: pos,
-                }
-            })
-            .collect();
-        // finally insert them into the table
-        for sheet in worksheets_in_course {
-            diesel::insert_into(schema::worksheets_in_courses::table)
-                .values(sheet)
-                .execute(&*conn)?;
-        }
-        Ok(())
+    let id = id.into_inner().to_string();
+    match conn.immediate_transaction::<(), IntegrityError, _>(|| {
+        conditional::check_if_match::<IntegrityError>(&req, conn, "courses", &id)?;
+        let course = json.into_inner();
+        InvalidReferences::default()
+            .check(conn, &sub, ObjectType::WORKSHEET, &course.worksheets)?
+            .into_result()?;
+        Ok(save_course(conn, &id, course)?)
     }) {
         Ok(_) => Box::new(Ok(HttpResponse::Ok().finish()).into_future()),
-        Err(e) => {
-            log::error!("Couldn't update course: {}", e);
-            Box::new(Ok(HttpResponse::InternalServerError().finish()).into_future())
-        }
+        Err(e) => Box::new(Ok(e.into_response("update course")).into_future()),
     }
 }
 
-fn delete_course(
+fn patch_course(
     req: HttpRequest,
     id: web::Path<Uuid>,
+    json: web::Json<json_patch::Patch>,
+    Subject(sub): Subject,
 ) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
     let extensions = req.extensions();
     let conn = extensions
         .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
         .unwrap();
 
-    let uuid = id.into_inner();
+    let id = id.into_inner().to_string();
+    match conn.immediate_transaction::<(), PatchError, _>(|| {
+        conditional::check_if_match::<PatchError>(&req, conn, "courses", &id)?;
+        let course = patch::apply(&load_course(conn, &id)?, &json)?;
+        InvalidReferences::default()
+            .check(conn, &sub, ObjectType::WORKSHEET, &course.worksheets)?
+            .into_result()?;
+        Ok(save_course(conn, &id, course)?)
+    }) {
+        Ok(_) => Box::new(Ok(HttpResponse::Ok().finish()).into_future()),
+        Err(e) => Box::new(Ok(e.into_response("patch course")).into_future()),
+    }
+}
 
-    match conn.transaction::<(), diesel::result::Error, _>(|| {
-        diesel::delete(
-            schema::access::table.filter(schema::access::object_id.eq(uuid.to_string())),
-        )
-        .execute(&*conn)?;
+fn delete_course(
+    req: HttpRequest,
+    id: web::Path<Uuid>,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
 
-        diesel::delete(
-            schema::worksheets_in_courses::table
-                .filter(schema::worksheets_in_courses::course_id.eq(uuid.to_string())),
-        )
-        .execute(&*conn)?;
+    let uuid = id.into_inner().to_string();
 
-        diesel::delete(schema::courses::table.find(format!("{}", uuid))).execute(&*conn)?;
-        Ok(())
+    // the course is kept in the trash, so it can be restored later
+    match conn.immediate_transaction::<(), IntegrityError, _>(|| {
+        conditional::check_if_match::<IntegrityError>(&req, conn, "courses", &uuid)?;
+        integrity::check_unreferenced(conn, &uuid, ObjectType::COURSE)?;
+        Ok(trash::move_to_trash(conn, &uuid, ObjectType::COURSE, &sub)?)
     }) {
         Ok(_) => Box::new(Ok(HttpResponse::Ok().finish()).into_future()),
-        Err(e) => {
-            log::error!("Couldn't delete course: {}", e);
-            Box::new(Ok(HttpResponse::InternalServerError().finish()).into_future())
-        }
+        Err(e) => Box::new(Ok(e.into_response("delete course")).into_future()),
     }
 }
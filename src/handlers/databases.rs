@@ -1,8 +1,22 @@
-use crate::models;
+use crate::blobs;
+use crate::diagram;
+use crate::dump::{self, DumpError};
+use crate::handlers::{public_read, tags::TagFilter};
+use crate::integrity::{self, IntegrityError};
+use crate::middlewares::conditional;
+use crate::models::{self, ObjectType};
+use crate::quota;
+use crate::sample_data;
 use crate::schema;
-use actix_web::{web, Error, FromRequest, HttpRequest, HttpResponse, Scope};
+use crate::streaming;
+use crate::trash;
+use actix_multipart::Multipart;
+use actix_web::{error, http::header, web, Error, FromRequest, HttpRequest, HttpResponse, Scope};
+use actix_web_jwt_middleware::{Policy, Subject};
+use serde::Deserialize;
 
-use futures::future::{Future, IntoFuture};
+use futures::future::{self, Either, Future, IntoFuture};
+use futures::Stream;
 use uuid::Uuid;
 
 use diesel::{
@@ -10,6 +24,11 @@ use diesel::{
     Connection, ExpressionMethods, JoinOnDsl, QueryDsl, RunQueryDsl, SqliteConnection,
 };
 
+/// Limit for all uploaded files together, the SQL generated from them may be bigger
+const IMPORT_LIMIT: usize = 33554432; //32MB limit
+/// Limit for the number of files and fields of an import
+const IMPORT_PARTS_LIMIT: usize = 64;
+
 pub fn get_scope() -> Scope {
     let json_config = web::Json::<models::Database>::configure(|cfg| {
         cfg.limit(4194304) //4MB limit
@@ -17,46 +36,170 @@ pub fn get_scope() -> Scope {
     web::scope("/databases")
         .service(
             web::resource("")
+                .wrap(Policy::required())
                 .data(json_config.clone())
                 .route(web::get().to_async(get_databases))
                 .route(web::post().to_async(create_database)),
         )
+        .service(
+            web::resource("/import")
+                .wrap(Policy::required())
+                .route(web::post().to_async(import_database)),
+        )
+        .service(
+            web::resource("/generate")
+                .wrap(Policy::required())
+                .route(web::post().to_async(generate_database)),
+        )
         .service(
             web::resource("/{id}")
+                .wrap(public_read())
                 .data(json_config.clone())
                 .route(web::get().to_async(get_database))
                 .route(web::put().to_async(update_database))
                 .route(web::delete().to_async(delete_database)),
         )
+        .service(
+            web::resource("/{id}/export")
+                .wrap(public_read())
+                .route(web::get().to_async(export_database)),
+        )
+        .service(
+            web::resource("/{id}/schema")
+                .wrap(public_read())
+                .route(web::get().to_async(get_schema)),
+        )
+        .service(
+            web::resource("/{id}/diagram")
+                .wrap(public_read())
+                .route(web::get().to_async(get_diagram)),
+        )
+}
+
+/// Query string for exporting a database, e.g. `?format=sqlite`
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    format: Option<ExportFormat>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Sql,
+    Sqlite,
 }
 
-pub fn get_databases(req: HttpRequest) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+/// Query string for the diagram of a database, e.g. `?format=mermaid`
+#[derive(Debug, Deserialize)]
+pub struct DiagramQuery {
+    format: Option<DiagramFormat>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiagramFormat {
+    Svg,
+    Dot,
+    Mermaid,
+}
+
+/// A part of a multipart upload
+struct UploadedPart {
+    name: Option<String>,
+    filename: Option<String>,
+    data: Vec<u8>,
+}
+
+fn insert_database(
+    conn: &SqliteConnection,
+    user_id: &str,
+    mut database: models::Database,
+) -> Result<Uuid, diesel::result::Error> {
+    let id = Uuid::new_v4();
+    database.id = id.to_string();
+
+    // insert access for user
+    diesel::insert_into(schema::access::table)
+        .values(models::Access {
+            user_id: user_id.to_string(),
+            object_id: id.to_string(),
+        })
+        .execute(conn)?;
+
+    // insert database object
+    blobs::insert_database(conn, database)?;
+
+    Ok(id)
+}
+
+/// Query string for listing databases, `?content=false` leaves out the dumps
+#[derive(Debug, Deserialize)]
+pub struct ListQuery {
+    content: Option<bool>,
+}
+
+pub fn get_databases(
+    req: HttpRequest,
+    filter: web::Query<TagFilter>,
+    list: web::Query<ListQuery>,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
     let extensions = req.extensions();
     let conn = extensions
         .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
         .unwrap();
-    let sub = extensions
-        .get::<actix_web_jwt_middleware::AuthenticationData>()
-        .unwrap()
-        .claims
-        .sub
-        .clone()
-        .unwrap();
 
-    match schema::databases::table
+    let mut query = schema::databases::table
         .inner_join(
             schema::access::table
                 .on(schema::databases::columns::id.eq(schema::access::columns::object_id)),
         )
+        .inner_join(schema::blobs::table)
         .filter(schema::access::columns::user_id.eq(sub))
         .select((
-            schema::databases::columns::id,
-            schema::databases::columns::name,
-            schema::databases::columns::content,
+            models::QueryableDatabase::COLUMNS,
+            schema::blobs::columns::size,
         ))
-        .load::<models::Database>(&*conn)
-    {
-        Ok(result) => Box::new(Ok(HttpResponse::Ok().json(result)).into_future()),
+        .into_boxed();
+    // only keep databases carrying every requested tag
+    for tag_id in filter.tag_ids() {
+        query = query.filter(
+            schema::databases::columns::id.eq_any(
+                schema::databases_in_tags::table
+                    .filter(schema::databases_in_tags::columns::tag_id.eq(tag_id))
+                    .select(schema::databases_in_tags::columns::database_id),
+            ),
+        );
+    }
+
+    match query
+        .load::<(models::QueryableDatabase, i64)>(&*conn)
+        .and_then(|databases| {
+            if list.content == Some(false) {
+                return Ok(HttpResponse::Ok().json(
+                    databases
+                        .into_iter()
+                        .map(|(database, size)| models::DatabaseSummary {
+                            id: database.id,
+                            name: database.name,
+                            size,
+                        })
+                        .collect::<Vec<_>>(),
+                ));
+            }
+            let databases = databases
+                .into_iter()
+                .map(|(database, _)| {
+                    Ok(models::Database {
+                        content: blobs::load(conn, &database.content_hash)?,
+                        id: database.id,
+                        name: database.name,
+                    })
+                })
+                .collect::<Result<Vec<_>, diesel::result::Error>>()?;
+            Ok(HttpResponse::Ok().json(databases))
+        }) {
+        Ok(response) => Box::new(Ok(response).into_future()),
         Err(e) => {
             log::error!("Couldn't load database: {}", e);
             Box::new(Ok(HttpResponse::InternalServerError().finish()).into_future())
@@ -67,45 +210,26 @@ pub fn get_databases(req: HttpRequest) -> Box<dyn Future<Item = HttpResponse, Er
 pub fn create_database(
     req: HttpRequest,
     json: web::Json<models::Database>,
+    Subject(sub): Subject,
 ) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let appdata: &crate::AppData = req.app_data().unwrap();
     let extensions = req.extensions();
     let conn = extensions
         .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
         .unwrap();
-    let sub = extensions
-        .get::<actix_web_jwt_middleware::AuthenticationData>()
-        .unwrap()
-        .claims
-        .sub
-        .clone()
-        .unwrap();
-
-    match conn.transaction::<Uuid, diesel::result::Error, _>(|| {
-        // create database object
-        let mut new_database = json.into_inner();
-        let id = Uuid::new_v4();
-        new_database.id = id.to_string();
-
-        // insert access for user
-        diesel::insert_into(schema::access::table)
-            .values(models::Access {
-                user_id: sub,
-                object_id: id.to_string(),
-            })
-            .execute(&*conn)?;
 
-        // insert database object
-        diesel::insert_into(schema::databases::table)
-            .values(new_database)
-            .execute(&*conn)?;
-
-        Ok(id)
+    // loading the dump can take a while, other writers shouldn't wait for it
+    let database = json.into_inner();
+    if let Err(e) = dump::validate(&database.content) {
+        return Box::new(Ok(e.into_response("create database")).into_future());
+    }
+    match conn.transaction::<Uuid, DumpError, _>(|| {
+        quota::enforce(conn, &appdata.settings, &sub, || {
+            Ok(insert_database(conn, &sub, database)?)
+        })
     }) {
         Ok(id) => Box::new(Ok(HttpResponse::Ok().body(id.to_string())).into_future()),
-        Err(e) => {
-            log::error!("Couldn't create database: {}", e);
-            Box::new(Ok(HttpResponse::InternalServerError().finish()).into_future())
-        }
+        Err(e) => Box::new(Ok(e.into_response("create database")).into_future()),
     }
 }
 
@@ -118,11 +242,25 @@ pub fn get_database(
         .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
         .unwrap();
 
-    match schema::databases::table
-        .find(format!("{}", id))
-        .get_result::<models::Database>(&*conn)
-    {
-        Ok(result) => Box::new(Ok(HttpResponse::Ok().json(result)).into_future()),
+    // the dump is decompressed and escaped while sending, instead of serializing it at once
+    match (|| -> Result<HttpResponse, diesel::result::Error> {
+        let database = schema::databases::table
+            .find(id.to_string())
+            .select(models::QueryableDatabase::COLUMNS)
+            .get_result::<models::QueryableDatabase>(conn)?;
+        let prefix = format!(
+            r#"{{"id":{},"name":{},"database":""#,
+            serde_json::to_string(&database.id).unwrap(),
+            serde_json::to_string(&database.name).unwrap()
+        );
+        Ok(HttpResponse::Ok()
+            .content_type("application/json")
+            .streaming(streaming::json_with_string(
+                prefix,
+                blobs::open(conn, &database.content_hash)?,
+            )))
+    })() {
+        Ok(response) => Box::new(Ok(response).into_future()),
         Err(e) => match e {
             diesel::result::Error::NotFound => {
                 Box::new(Ok(HttpResponse::NotFound().finish()).into_future())
@@ -139,40 +277,329 @@ pub fn update_database(
     req: HttpRequest,
     id: web::Path<Uuid>,
     json: web::Json<models::Database>,
+    Subject(sub): Subject,
 ) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let appdata: &crate::AppData = req.app_data().unwrap();
     let extensions = req.extensions();
     let conn = extensions
         .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
         .unwrap();
 
-    match diesel::update(schema::databases::table.find(format!("{}", id)))
-        .set(json.into_inner())
-        .execute(&*conn)
-    {
+    let database = json.into_inner();
+    if let Err(e) = dump::validate(&database.content) {
+        return Box::new(Ok(e.into_response("update database")).into_future());
+    }
+    match conn.immediate_transaction::<(), DumpError, _>(|| {
+        conditional::check_if_match::<DumpError>(&req, conn, "databases", &id.to_string())?;
+        quota::enforce(conn, &appdata.settings, &sub, || {
+            // a trashed database is only gone from the table, which leaves nothing to update
+            match blobs::update_database(conn, &id.to_string(), database)? {
+                0 => Err(diesel::result::Error::NotFound.into()),
+                _ => Ok(()),
+            }
+        })
+    }) {
         Ok(_) => Box::new(Ok(HttpResponse::Ok().finish()).into_future()),
-        Err(e) => {
-            log::error!("Couldn't update database: {}", e);
-            Box::new(Ok(HttpResponse::InternalServerError().finish()).into_future())
-        }
+        Err(e) => Box::new(Ok(e.into_response("update database")).into_future()),
     }
 }
 
 pub fn delete_database(
     req: HttpRequest,
     id: web::Path<Uuid>,
+    Subject(sub): Subject,
 ) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
     let extensions = req.extensions();
     let conn = extensions
         .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
         .unwrap();
 
-    match diesel::delete(schema::databases::table.find(format!("{}", id.into_inner())))
-        .execute(&*conn)
-    {
+    let uuid = id.into_inner().to_string();
+
+    // the database is kept in the trash, so it can be restored later
+    match conn.immediate_transaction::<(), IntegrityError, _>(|| {
+        conditional::check_if_match::<IntegrityError>(&req, conn, "databases", &uuid)?;
+        integrity::check_unreferenced(conn, &uuid, ObjectType::DATABASE)?;
+        Ok(trash::move_to_trash(
+            conn,
+            &uuid,
+            ObjectType::DATABASE,
+            &sub,
+        )?)
+    }) {
         Ok(_) => Box::new(Ok(HttpResponse::Ok().finish()).into_future()),
-        Err(e) => {
-            log::error!("Couldn't delete database: {}", e);
-            Box::new(Ok(HttpResponse::InternalServerError().finish()).into_future())
+        Err(e) => Box::new(Ok(e.into_response("delete database")).into_future()),
+    }
+}
+
+/// Creates a database from an uploaded SQLite file, or from CSV files which become one
+/// table each. The name is taken from a `name` field, or else from the first file.
+pub fn import_database(
+    req: HttpRequest,
+    multipart: Multipart,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    Box::new(
+        multipart
+            .map_err(Error::from)
+            .fold(Vec::new(), |mut parts: Vec<UploadedPart>, field| {
+                if parts.len() >= IMPORT_PARTS_LIMIT {
+                    return Either::A(future::err(error::ErrorPayloadTooLarge(
+                        "Too many files have been uploaded.",
+                    )));
+                }
+                // the limit counts for all parts together, the next one gets what is left
+                let left = IMPORT_LIMIT - parts.iter().map(|part| part.data.len()).sum::<usize>();
+                let disposition = field.content_disposition();
+                let name = disposition
+                    .as_ref()
+                    .and_then(|d| d.get_name().map(String::from));
+                let filename = disposition
+                    .as_ref()
+                    .and_then(|d| d.get_filename().map(String::from));
+                Either::B(
+                    field
+                        .map_err(Error::from)
+                        .fold(Vec::new(), move |mut data, bytes| {
+                            if data.len() + bytes.len() > left {
+                                Err(error::ErrorPayloadTooLarge("The files are too big."))
+                            } else {
+                                data.extend_from_slice(&bytes);
+                                Ok(data)
+                            }
+                        })
+                        .map(move |data| {
+                            parts.push(UploadedPart {
+                                name,
+                                filename,
+                                data,
+                            });
+                            parts
+                        }),
+                )
+            })
+            .map(move |parts| {
+                let appdata: &crate::AppData = req.app_data().unwrap();
+                let extensions = req.extensions();
+                let conn = extensions
+                    .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+                    .unwrap();
+
+                // converting is expensive, so it's skipped if the quota is used up anyway
+                if let Err(e) = quota::check_room::<DumpError>(conn, &appdata.settings, &sub) {
+                    return e.into_response("import database");
+                }
+                let database = match database_from_parts(parts).and_then(|database| {
+                    dump::validate(&database.content)?;
+                    Ok(database)
+                }) {
+                    Ok(database) => database,
+                    Err(e) => return e.into_response("import database"),
+                };
+                match conn.transaction::<Uuid, DumpError, _>(|| {
+                    quota::enforce(conn, &appdata.settings, &sub, || {
+                        Ok(insert_database(conn, &sub, database)?)
+                    })
+                }) {
+                    Ok(id) => HttpResponse::Ok().body(id.to_string()),
+                    Err(e) => e.into_response("import database"),
+                }
+            }),
+    )
+}
+
+/// Creates a database filled with sample data for the given tables
+pub fn generate_database(
+    req: HttpRequest,
+    json: web::Json<models::GeneratorRequest>,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let appdata: &crate::AppData = req.app_data().unwrap();
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    let request = json.into_inner();
+    let content = match sample_data::generate(&request).and_then(|content| {
+        dump::validate(&content)?;
+        Ok(content)
+    }) {
+        Ok(content) => content,
+        Err(e) => return Box::new(Ok(e.into_response("generate database")).into_future()),
+    };
+    match conn.transaction::<Uuid, DumpError, _>(|| {
+        quota::enforce(conn, &appdata.settings, &sub, || {
+            Ok(insert_database(
+                conn,
+                &sub,
+                models::Database {
+                    id: String::new(),
+                    name: request.name,
+                    content,
+                },
+            )?)
+        })
+    }) {
+        Ok(id) => Box::new(Ok(HttpResponse::Ok().body(id.to_string())).into_future()),
+        Err(e) => Box::new(Ok(e.into_response("generate database")).into_future()),
+    }
+}
+
+fn database_from_parts(parts: Vec<UploadedPart>) -> Result<models::Database, DumpError> {
+    let mut name = None;
+    let mut files = Vec::new();
+    for part in parts {
+        match (part.filename, part.name) {
+            (Some(filename), _) => files.push((filename, part.data)),
+            (None, Some(ref field)) if field == "name" => {
+                name =
+                    Some(String::from_utf8(part.data).map_err(|_| {
+                        DumpError::Invalid("The name is not valid UTF-8.".to_string())
+                    })?)
+            }
+            _ => {}
+        }
+    }
+
+    // file names without their extension name the database and the tables
+    let stem = |filename: &str| match filename.rfind('.') {
+        Some(i) if i > 0 => filename[..i].to_string(),
+        _ => filename.to_string(),
+    };
+    let is_csv = |filename: &str| filename.to_lowercase().ends_with(".csv");
+    let name = match (name, files.first()) {
+        (_, None) => return Err(DumpError::Invalid("No file has been uploaded.".to_string())),
+        (Some(name), _) => name,
+        (None, Some((filename, _))) => stem(filename),
+    };
+    let content = if files.iter().all(|(filename, _)| is_csv(filename)) {
+        dump::from_csv_files(
+            &files
+                .into_iter()
+                .map(|(filename, data)| (stem(&filename), data))
+                .collect::<Vec<_>>(),
+        )?
+    } else if files.len() == 1 {
+        dump::from_sqlite_file(&files[0].1)?
+    } else {
+        return Err(DumpError::Invalid(
+            "Upload either one SQLite file or only CSV files.".to_string(),
+        ));
+    };
+
+    Ok(models::Database {
+        id: String::new(),
+        name,
+        content,
+    })
+}
+
+/// Returns the database as SQL statements or as SQLite file
+pub fn export_database(
+    req: HttpRequest,
+    id: web::Path<Uuid>,
+    query: web::Query<ExportQuery>,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    match (|| -> Result<HttpResponse, DumpError> {
+        let database = schema::databases::table
+            .find(id.to_string())
+            .select(models::QueryableDatabase::COLUMNS)
+            .get_result::<models::QueryableDatabase>(conn)?;
+        let format = query.format.as_ref().unwrap_or(&ExportFormat::Sql);
+        let (extension, content_type) = match format {
+            ExportFormat::Sql => ("sql", "application/sql"),
+            ExportFormat::Sqlite => ("sqlite", "application/vnd.sqlite3"),
+        };
+        let mut response = HttpResponse::Ok();
+        response.content_type(content_type).header(
+            header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"{}.{}\"",
+                database
+                    .name
+                    .replace(|c: char| c == '"' || c.is_control(), ""),
+                extension
+            ),
+        );
+        // plain dumps are streamed straight from the blob
+        Ok(match format {
+            ExportFormat::Sql => response.streaming(streaming::ReadStream::new(blobs::open(
+                conn,
+                &database.content_hash,
+            )?)),
+            ExportFormat::Sqlite => response.body(dump::to_sqlite_file(&blobs::load(
+                conn,
+                &database.content_hash,
+            )?)?),
+        })
+    })() {
+        Ok(response) => Box::new(Ok(response).into_future()),
+        Err(e) => Box::new(Ok(e.into_response("export database")).into_future()),
+    }
+}
+
+/// Describes the tables and views of the database, for the point and click query builder
+pub fn get_schema(
+    req: HttpRequest,
+    id: web::Path<Uuid>,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    match load_schema(conn, &id.to_string()) {
+        Ok(result) => Box::new(Ok(HttpResponse::Ok().json(result)).into_future()),
+        Err(e) => Box::new(Ok(e.into_response("get database schema")).into_future()),
+    }
+}
+
+/// Loading the dump is expensive, so the schema is worked out once for each content
+fn load_schema(conn: &SqliteConnection, id: &str) -> Result<models::DatabaseSchema, DumpError> {
+    let hash = schema::databases::table
+        .find(id)
+        .select(schema::databases::content_hash)
+        .get_result::<String>(conn)?;
+    if let Some(json) = blobs::load_schema(conn, &hash)? {
+        if let Ok(result) = serde_json::from_str(&json) {
+            return Ok(result);
+        }
+    }
+    let result = dump::schema(&blobs::load(conn, &hash)?)?;
+    if let Ok(json) = serde_json::to_string(&result) {
+        blobs::store_schema(conn, &hash, &json)?;
+    }
+    Ok(result)
+}
+
+/// Draws the tables and their foreign keys as SVG, Graphviz DOT or Mermaid ER diagram
+pub fn get_diagram(
+    req: HttpRequest,
+    id: web::Path<Uuid>,
+    query: web::Query<DiagramQuery>,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    match load_schema(conn, &id.to_string()) {
+        Ok(result) => {
+            let (content_type, body) = match query.format.as_ref().unwrap_or(&DiagramFormat::Svg) {
+                DiagramFormat::Svg => ("image/svg+xml", diagram::to_svg(&result)),
+                DiagramFormat::Dot => ("text/vnd.graphviz", diagram::to_dot(&result)),
+                DiagramFormat::Mermaid => {
+                    ("text/plain; charset=utf-8", diagram::to_mermaid(&result))
+                }
+            };
+            Box::new(Ok(HttpResponse::Ok().content_type(content_type).body(body)).into_future())
         }
+        Err(e) => Box::new(Ok(e.into_response("get database diagram")).into_future()),
     }
 }
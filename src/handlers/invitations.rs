@@ -0,0 +1,125 @@
+use crate::admins::is_admin;
+use crate::invitations;
+use crate::{models, schema};
+use actix_web::{web, Error, HttpRequest, HttpResponse, Scope};
+use actix_web_jwt_middleware::Subject;
+use diesel::{
+    r2d2::{self, ConnectionManager},
+    QueryDsl, RunQueryDsl, SqliteConnection,
+};
+use futures::future::{Future, IntoFuture};
+
+pub fn get_scope() -> Scope {
+    web::scope("/invitations")
+        .service(
+            web::resource("")
+                .route(web::get().to_async(get_invitations))
+                .route(web::post().to_async(create_invitation)),
+        )
+        .service(web::resource("/{code}").route(web::delete().to_async(delete_invitation)))
+}
+
+enum InvitationError {
+    Diesel(diesel::result::Error),
+    NoAdmin,
+    InvalidUses,
+}
+
+impl From<diesel::result::Error> for InvitationError {
+    fn from(val: diesel::result::Error) -> InvitationError {
+        InvitationError::Diesel(val)
+    }
+}
+
+impl InvitationError {
+    fn into_response(self, action: &str) -> HttpResponse {
+        match self {
+            InvitationError::Diesel(diesel::result::Error::NotFound) => {
+                HttpResponse::NotFound().finish()
+            }
+            InvitationError::Diesel(e) => {
+                log::error!("Couldn't {}: {}", action, e);
+                HttpResponse::InternalServerError().finish()
+            }
+            InvitationError::NoAdmin => HttpResponse::Forbidden().finish(),
+            InvitationError::InvalidUses => {
+                HttpResponse::BadRequest().body("An invitation has to be usable at least once.")
+            }
+        }
+    }
+}
+
+fn check_admin(conn: &SqliteConnection, user_id: &str) -> Result<(), InvitationError> {
+    if is_admin(conn, user_id)? {
+        Ok(())
+    } else {
+        Err(InvitationError::NoAdmin)
+    }
+}
+
+fn get_invitations(
+    req: HttpRequest,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    match (|| -> Result<Vec<models::Invitation>, InvitationError> {
+        check_admin(conn, &sub)?;
+        Ok(schema::invitations::table
+            .order(schema::invitations::created_at)
+            .load::<models::Invitation>(conn)?)
+    })() {
+        Ok(invitations) => Box::new(Ok(HttpResponse::Ok().json(invitations)).into_future()),
+        Err(e) => Box::new(Ok(e.into_response("get invitations")).into_future()),
+    }
+}
+
+/// Mints a new invitation code, which is returned with the invitation
+fn create_invitation(
+    req: HttpRequest,
+    json: web::Json<models::InvitationRequest>,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    match (|| -> Result<models::Invitation, InvitationError> {
+        check_admin(conn, &sub)?;
+        let request = json.into_inner();
+        if request.max_uses.unwrap_or(1) < 1 {
+            return Err(InvitationError::InvalidUses);
+        }
+        Ok(invitations::mint(conn, &sub, request)?)
+    })() {
+        Ok(invitation) => Box::new(Ok(HttpResponse::Ok().json(invitation)).into_future()),
+        Err(e) => Box::new(Ok(e.into_response("create invitation")).into_future()),
+    }
+}
+
+/// Withdraws an invitation code, e.g. one that was handed to the wrong people
+fn delete_invitation(
+    req: HttpRequest,
+    code: web::Path<String>,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    match (|| -> Result<(), InvitationError> {
+        check_admin(conn, &sub)?;
+        match diesel::delete(schema::invitations::table.find(code.as_str())).execute(conn)? {
+            0 => Err(diesel::result::Error::NotFound.into()),
+            _ => Ok(()),
+        }
+    })() {
+        Ok(()) => Box::new(Ok(HttpResponse::NoContent().finish()).into_future()),
+        Err(e) => Box::new(Ok(e.into_response("delete invitation")).into_future()),
+    }
+}
@@ -0,0 +1,9 @@
+use actix_web::{HttpRequest, HttpResponse};
+
+/// The public keys tokens are signed with, for verifying them in other services
+pub fn get_jwks(req: HttpRequest) -> HttpResponse {
+    let appdata: &crate::AppData = req.app_data().unwrap();
+    HttpResponse::Ok()
+        .content_type("application/jwk-set+json")
+        .json(appdata.jwt_keys.jwks())
+}
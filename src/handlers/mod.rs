@@ -1,7 +1,25 @@
 pub mod account;
+pub mod admin;
 pub mod alias;
+pub mod catalog;
+pub mod children;
 pub mod courses;
 pub mod databases;
+pub mod invitations;
+pub mod keys;
+pub mod quotas;
+pub mod revisions;
 pub mod subtasks;
+pub mod tags;
 pub mod tasks;
+pub mod trash;
 pub mod worksheets;
+
+use actix_web::http::Method;
+use actix_web_jwt_middleware::{Policy, Requirement};
+
+/// Policy of objects everyone knowing their id may read, e.g. the worksheets of a course
+/// shared with students, while changing them needs a token
+pub fn public_read() -> Policy {
+    Policy::required().method(Method::GET, Requirement::Anonymous)
+}
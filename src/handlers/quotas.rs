@@ -0,0 +1,139 @@
+use crate::admins::is_admin;
+use crate::quota;
+use crate::{models, schema};
+use actix_web::{web, Error, HttpRequest, HttpResponse, Scope};
+use actix_web_jwt_middleware::Subject;
+use diesel::{
+    r2d2::{self, ConnectionManager},
+    Connection, ExpressionMethods, QueryDsl, RunQueryDsl, SqliteConnection,
+};
+use futures::future::{Future, IntoFuture};
+
+pub fn get_scope() -> Scope {
+    web::scope("/quotas")
+        .service(web::resource("").route(web::get().to_async(get_own_quota)))
+        .service(
+            web::resource("/{username}")
+                .route(web::get().to_async(get_quota))
+                .route(web::put().to_async(set_quota)),
+        )
+}
+
+enum QuotaError {
+    Diesel(diesel::result::Error),
+    NoAdmin,
+}
+
+impl From<diesel::result::Error> for QuotaError {
+    fn from(val: diesel::result::Error) -> QuotaError {
+        QuotaError::Diesel(val)
+    }
+}
+
+impl QuotaError {
+    fn into_response(self, action: &str) -> HttpResponse {
+        match self {
+            QuotaError::Diesel(diesel::result::Error::NotFound) => {
+                HttpResponse::NotFound().finish()
+            }
+            QuotaError::Diesel(e) => {
+                log::error!("Couldn't {}: {}", action, e);
+                HttpResponse::InternalServerError().finish()
+            }
+            QuotaError::NoAdmin => HttpResponse::Forbidden().finish(),
+        }
+    }
+}
+
+pub fn quota_usage(
+    conn: &SqliteConnection,
+    settings: &crate::settings::Settings,
+    user_id: &str,
+) -> Result<models::QuotaUsage, diesel::result::Error> {
+    Ok(models::QuotaUsage {
+        usage: quota::usage(conn, user_id)?,
+        quota: quota::limits(conn, settings, user_id)?,
+    })
+}
+
+/// Finds the user with the given name, if the requesting user is an admin
+fn user_for_admin(
+    conn: &SqliteConnection,
+    admin_id: &str,
+    username: &str,
+) -> Result<String, QuotaError> {
+    if !is_admin(conn, admin_id)? {
+        return Err(QuotaError::NoAdmin);
+    }
+    Ok(schema::users::table
+        .filter(schema::users::name.eq(username))
+        .select(schema::users::id)
+        .get_result::<String>(conn)?)
+}
+
+fn get_own_quota(
+    req: HttpRequest,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let appdata: &crate::AppData = req.app_data().unwrap();
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    match quota_usage(conn, &appdata.settings, &sub) {
+        Ok(result) => Box::new(Ok(HttpResponse::Ok().json(result)).into_future()),
+        Err(e) => Box::new(Ok(QuotaError::from(e).into_response("get quota")).into_future()),
+    }
+}
+
+fn get_quota(
+    req: HttpRequest,
+    username: web::Path<String>,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let appdata: &crate::AppData = req.app_data().unwrap();
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    match (|| -> Result<models::QuotaUsage, QuotaError> {
+        let user_id = user_for_admin(conn, &sub, &username)?;
+        Ok(quota_usage(conn, &appdata.settings, &user_id)?)
+    })() {
+        Ok(result) => Box::new(Ok(HttpResponse::Ok().json(result)).into_future()),
+        Err(e) => Box::new(Ok(e.into_response("get quota")).into_future()),
+    }
+}
+
+/// Sets the limits of a user, limits left out fall back to the configured defaults
+fn set_quota(
+    req: HttpRequest,
+    username: web::Path<String>,
+    json: web::Json<models::QuotaLimits>,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let appdata: &crate::AppData = req.app_data().unwrap();
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    match conn.transaction::<models::QuotaUsage, QuotaError, _>(|| {
+        let user_id = user_for_admin(conn, &sub, &username)?;
+        let limits = json.into_inner();
+        diesel::replace_into(schema::quotas::table)
+            .values((
+                schema::quotas::user_id.eq(&user_id),
+                schema::quotas::database_bytes.eq(limits.database_bytes),
+                schema::quotas::objects.eq(limits.objects),
+                schema::quotas::aliases.eq(limits.aliases),
+            ))
+            .execute(conn)?;
+        Ok(quota_usage(conn, &appdata.settings, &user_id)?)
+    }) {
+        Ok(result) => Box::new(Ok(HttpResponse::Ok().json(result)).into_future()),
+        Err(e) => Box::new(Ok(e.into_response("set quota")).into_future()),
+    }
+}
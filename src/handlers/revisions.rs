@@ -0,0 +1,175 @@
+use crate::integrity::IntegrityError;
+use crate::middlewares::ownership::has_access;
+use crate::models;
+use crate::revisions::{self, RevisionStore};
+use actix_web::{web, Error, HttpRequest, HttpResponse, Scope};
+use actix_web_jwt_middleware::Subject;
+use diesel::{
+    r2d2::{self, ConnectionManager},
+    Connection, SqliteConnection,
+};
+use futures::future::{Future, IntoFuture};
+use serde::Deserialize;
+use uuid::Uuid;
+
+/// Revision endpoints, to be nested into the scope of the object type
+pub fn get_scope<S: RevisionStore + 'static>() -> Scope {
+    web::scope("/{id}/revisions")
+        .service(web::resource("").route(web::get().to_async(get_revisions::<S>)))
+        .service(web::resource("/diff").route(web::get().to_async(diff_revisions::<S>)))
+        .service(web::resource("/{revision}").route(web::get().to_async(get_revision::<S>)))
+        .service(
+            web::resource("/{revision}/restore").route(web::post().to_async(restore_revision::<S>)),
+        )
+}
+
+/// Query string for comparing two revisions, e.g. `?from=1&to=3`
+#[derive(Debug, Deserialize)]
+pub struct DiffQuery {
+    from: i32,
+    to: i32,
+}
+
+enum RevisionError {
+    Diesel(diesel::result::Error),
+    Json(serde_json::Error),
+    Integrity(IntegrityError),
+    NoAccess,
+}
+
+impl From<diesel::result::Error> for RevisionError {
+    fn from(val: diesel::result::Error) -> RevisionError {
+        RevisionError::Diesel(val)
+    }
+}
+
+impl From<IntegrityError> for RevisionError {
+    fn from(val: IntegrityError) -> RevisionError {
+        RevisionError::Integrity(val)
+    }
+}
+
+impl From<serde_json::Error> for RevisionError {
+    fn from(val: serde_json::Error) -> RevisionError {
+        RevisionError::Json(val)
+    }
+}
+
+impl RevisionError {
+    fn into_response(self, action: &str) -> HttpResponse {
+        match self {
+            RevisionError::Diesel(diesel::result::Error::NotFound) => {
+                HttpResponse::NotFound().finish()
+            }
+            RevisionError::Diesel(e) => {
+                log::error!("Couldn't {}: {}", action, e);
+                HttpResponse::InternalServerError().finish()
+            }
+            RevisionError::Json(e) => {
+                log::error!("Couldn't {}, stored revision is invalid: {}", action, e);
+                HttpResponse::InternalServerError().finish()
+            }
+            RevisionError::Integrity(e) => e.into_response(action),
+            RevisionError::NoAccess => HttpResponse::Forbidden().finish(),
+        }
+    }
+}
+
+fn check_access(conn: &SqliteConnection, user_id: &str, id: &str) -> Result<(), RevisionError> {
+    if has_access(conn, user_id, id)? {
+        Ok(())
+    } else {
+        Err(RevisionError::NoAccess)
+    }
+}
+
+fn get_revisions<S: RevisionStore>(
+    req: HttpRequest,
+    id: web::Path<Uuid>,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    let id = id.into_inner().to_string();
+    match (|| -> Result<Vec<models::Revision>, RevisionError> {
+        check_access(conn, &sub, &id)?;
+        let mut result = Vec::new();
+        for revision in S::list(conn, &id)? {
+            result.push(revision.into_revision()?);
+        }
+        Ok(result)
+    })() {
+        Ok(result) => Box::new(Ok(HttpResponse::Ok().json(result)).into_future()),
+        Err(e) => Box::new(Ok(e.into_response("get revisions")).into_future()),
+    }
+}
+
+fn get_revision<S: RevisionStore>(
+    req: HttpRequest,
+    path: web::Path<(Uuid, i32)>,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    let (id, revision) = path.into_inner();
+    let id = id.to_string();
+    match (|| -> Result<models::Revision, RevisionError> {
+        check_access(conn, &sub, &id)?;
+        Ok(S::get(conn, &id, revision)?.into_revision()?)
+    })() {
+        Ok(result) => Box::new(Ok(HttpResponse::Ok().json(result)).into_future()),
+        Err(e) => Box::new(Ok(e.into_response("get revision")).into_future()),
+    }
+}
+
+fn diff_revisions<S: RevisionStore>(
+    req: HttpRequest,
+    id: web::Path<Uuid>,
+    query: web::Query<DiffQuery>,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    let id = id.into_inner().to_string();
+    match (|| -> Result<Vec<models::RevisionChange>, RevisionError> {
+        check_access(conn, &sub, &id)?;
+        let from = S::get(conn, &id, query.from)?.into_revision()?;
+        let to = S::get(conn, &id, query.to)?.into_revision()?;
+        Ok(revisions::diff(&from.content, &to.content))
+    })() {
+        Ok(result) => Box::new(Ok(HttpResponse::Ok().json(result)).into_future()),
+        Err(e) => Box::new(Ok(e.into_response("diff revisions")).into_future()),
+    }
+}
+
+fn restore_revision<S: RevisionStore>(
+    req: HttpRequest,
+    path: web::Path<(Uuid, i32)>,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    let (id, revision) = path.into_inner();
+    let id = id.to_string();
+    match conn.transaction::<i32, RevisionError, _>(|| {
+        check_access(conn, &sub, &id)?;
+        Ok(revisions::restore::<S>(conn, &id, &sub, revision)?)
+    }) {
+        Ok(new_revision) => {
+            Box::new(Ok(HttpResponse::Ok().body(new_revision.to_string())).into_future())
+        }
+        Err(e) => Box::new(Ok(e.into_response("restore revision")).into_future()),
+    }
+}
@@ -1,7 +1,15 @@
-use crate::models;
+use crate::handlers::{public_read, revisions, tags::TagFilter};
+use crate::integrity::{self, IntegrityError};
+use crate::middlewares::conditional;
+use crate::models::{self, ObjectType};
+use crate::patch::{self, PatchError};
+use crate::quota;
+use crate::revisions::SubtaskRevisions;
 use crate::schema;
 use crate::solution_compare::compare_solutions;
+use crate::trash;
 use actix_web::{web, Error, HttpRequest, HttpResponse, Scope};
+use actix_web_jwt_middleware::{Policy, Subject};
 
 use futures::future::{Future, IntoFuture};
 use uuid::Uuid;
@@ -15,32 +23,59 @@ pub fn get_scope() -> Scope {
     web::scope("/subtasks")
         .service(
             web::resource("")
+                .wrap(Policy::required())
                 .route(web::get().to_async(get_subtasks))
                 .route(web::post().to_async(create_subtask)),
         )
         .service(
             web::resource("/{id}")
+                .wrap(public_read())
                 .route(web::get().to_async(get_subtask))
                 .route(web::put().to_async(update_subtask))
+                .route(web::patch().to_async(patch_subtask))
                 .route(web::delete().to_async(delete_subtask)),
         )
-        .service(web::resource("/{id}/verify").route(web::post().to_async(verify_subtask_solution)))
+        .service(
+            web::resource("/{id}/verify")
+                .wrap(Policy::anonymous())
+                .route(web::post().to_async(verify_subtask_solution)),
+        )
+        .service(revisions::get_scope::<SubtaskRevisions>().wrap(Policy::required()))
+}
+
+pub fn load_subtask(
+    conn: &SqliteConnection,
+    id: &str,
+) -> Result<models::Subtask, diesel::result::Error> {
+    schema::subtasks::table
+        .find(id)
+        .select(models::Subtask::COLUMNS)
+        .get_result::<models::Subtask>(conn)
 }
 
-fn get_subtasks(req: HttpRequest) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+pub fn save_subtask(
+    conn: &SqliteConnection,
+    id: &str,
+    mut subtask: models::Subtask,
+) -> Result<(), diesel::result::Error> {
+    subtask.id = id.to_string();
+    diesel::update(schema::subtasks::table.find(id))
+        .set(subtask)
+        .execute(conn)?;
+    Ok(())
+}
+
+fn get_subtasks(
+    req: HttpRequest,
+    filter: web::Query<TagFilter>,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
     let extensions = req.extensions();
     let conn = extensions
         .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
         .unwrap();
-    let sub = extensions
-        .get::<actix_web_jwt_middleware::AuthenticationData>()
-        .unwrap()
-        .claims
-        .sub
-        .clone()
-        .unwrap();
 
-    match schema::subtasks::table
+    let mut query = schema::subtasks::table
         .inner_join(
             schema::access::table
                 .on(schema::subtasks::columns::id.eq(schema::access::columns::object_id)),
@@ -53,8 +88,19 @@ fn get_subtasks(req: HttpRequest) -> Box<dyn Future<Item = HttpResponse, Error =
             schema::subtasks::is_solution_verifiable,
             schema::subtasks::content,
         ))
-        .load::<models::Subtask>(&*conn)
-    {
+        .into_boxed();
+    // only keep subtasks carrying every requested tag
+    for tag_id in filter.tag_ids() {
+        query = query.filter(
+            schema::subtasks::columns::id.eq_any(
+                schema::subtasks_in_tags::table
+                    .filter(schema::subtasks_in_tags::columns::tag_id.eq(tag_id))
+                    .select(schema::subtasks_in_tags::columns::subtask_id),
+            ),
+        );
+    }
+
+    match query.load::<models::Subtask>(&*conn) {
         Ok(result) => Box::new(Ok(HttpResponse::Ok().json(result)).into_future()),
         Err(e) => {
             log::error!("Couldn't get subtasks: {}", e);
@@ -65,45 +111,41 @@ fn get_subtasks(req: HttpRequest) -> Box<dyn Future<Item = HttpResponse, Error =
 fn create_subtask(
     req: HttpRequest,
     json: web::Json<models::Subtask>,
+    Subject(sub): Subject,
 ) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let appdata: &crate::AppData = req.app_data().unwrap();
     let extensions = req.extensions();
     let conn = extensions
         .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
         .unwrap();
-    let sub = extensions
-        .get::<actix_web_jwt_middleware::AuthenticationData>()
-        .unwrap()
-        .claims
-        .sub
-        .clone()
-        .unwrap();
 
-    match conn.transaction::<Uuid, diesel::result::Error, _>(|| {
-        // create subtask object
-        let mut new_subtask = json.into_inner();
-        let id = Uuid::new_v4();
-        new_subtask.id = id.to_string();
-
-        // insert access for user
-        diesel::insert_into(schema::access::table)
-            .values(models::Access {
-                user_id: sub,
-                object_id: id.to_string(),
-            })
-            .execute(&*conn)?;
-
-        // insert subtask object
-        diesel::insert_into(schema::subtasks::table)
-            .values(new_subtask)
-            .execute(&*conn)?;
-
-        Ok(id)
+    match conn.transaction::<Uuid, IntegrityError, _>(|| {
+        quota::enforce(conn, &appdata.settings, &sub, || {
+            // create subtask object
+            let mut new_subtask = json.into_inner();
+            let id = Uuid::new_v4();
+            new_subtask.id = id.to_string();
+
+            // insert access for user
+            diesel::insert_into(schema::access::table)
+                .values(models::Access {
+                    user_id: sub.clone(),
+                    object_id: id.to_string(),
+                })
+                .execute(&*conn)?;
+
+            // insert subtask object
+            diesel::insert_into(schema::subtasks::table)
+                .values(new_subtask)
+                .execute(&*conn)?;
+
+            crate::revisions::record::<SubtaskRevisions>(conn, &id.to_string(), &sub, None)?;
+
+            Ok(id)
+        })
     }) {
         Ok(result) => Box::new(Ok(HttpResponse::Ok().body(result.to_string())).into_future()),
-        Err(e) => {
-            log::error!("Couldn't create subtask: {}", e);
-            Box::new(Ok(HttpResponse::InternalServerError().finish()).into_future())
-        }
+        Err(e) => Box::new(Ok(e.into_response("create subtask")).into_future()),
     }
 }
 fn get_subtask(
@@ -115,10 +157,7 @@ fn get_subtask(
         .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
         .unwrap();
 
-    match schema::subtasks::table
-        .find(format!("{}", id))
-        .get_result::<models::Subtask>(&*conn)
-    {
+    match load_subtask(conn, &id.to_string()) {
         Ok(result) => Box::new(Ok(HttpResponse::Ok().json(result)).into_future()),
         Err(e) => match e {
             diesel::result::Error::NotFound => {
@@ -135,57 +174,78 @@ fn update_subtask(
     req: HttpRequest,
     id: web::Path<Uuid>,
     json: web::Json<models::Subtask>,
+    Subject(sub): Subject,
 ) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
     let extensions = req.extensions();
     let conn = extensions
         .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
         .unwrap();
 
-    match diesel::update(schema::subtasks::table.find(id.into_inner().to_string()))
-        .set(json.into_inner())
-        .execute(&*conn)
-    {
+    let id = id.into_inner().to_string();
+    match conn.immediate_transaction::<i32, IntegrityError, _>(|| {
+        conditional::check_if_match::<IntegrityError>(&req, conn, "subtasks", &id)?;
+        Ok(crate::revisions::update::<SubtaskRevisions>(
+            conn,
+            &id,
+            &sub,
+            json.into_inner(),
+            None,
+        )?)
+    }) {
         Ok(_) => Box::new(Ok(HttpResponse::Ok().finish()).into_future()),
-        Err(e) => {
-            log::error!("Couldn't update subtask: {}", e);
-            Box::new(Ok(HttpResponse::InternalServerError().finish()).into_future())
-        }
+        Err(e) => Box::new(Ok(e.into_response("update subtask")).into_future()),
     }
 }
-fn delete_subtask(
+
+fn patch_subtask(
     req: HttpRequest,
     id: web::Path<Uuid>,
+    json: web::Json<json_patch::Patch>,
+    Subject(sub): Subject,
 ) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
     let extensions = req.extensions();
     let conn = extensions
         .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
         .unwrap();
 
-    let subtask_id = id.into_inner();
+    let id = id.into_inner().to_string();
+    match conn.immediate_transaction::<i32, PatchError, _>(|| {
+        conditional::check_if_match::<PatchError>(&req, conn, "subtasks", &id)?;
+        let subtask = patch::apply(&load_subtask(conn, &id)?, &json)?;
+        Ok(crate::revisions::update::<SubtaskRevisions>(
+            conn, &id, &sub, subtask, None,
+        )?)
+    }) {
+        Ok(_) => Box::new(Ok(HttpResponse::Ok().finish()).into_future()),
+        Err(e) => Box::new(Ok(e.into_response("patch subtask")).into_future()),
+    }
+}
 
-    match conn.transaction::<(), diesel::result::Error, _>(|| {
-        // delete subtask
-        diesel::delete(schema::subtasks::table.find(subtask_id.to_string())).execute(&*conn)?;
+fn delete_subtask(
+    req: HttpRequest,
+    id: web::Path<Uuid>,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
 
-        // delete task associations
-        diesel::delete(
-            schema::subtasks_in_tasks::table
-                .filter(schema::subtasks_in_tasks::subtask_id.eq(subtask_id.to_string())),
-        )
-        .execute(&*conn)?;
+    let uuid = id.into_inner().to_string();
 
-        // delete access
-        diesel::delete(
-            schema::access::table.filter(schema::access::object_id.eq(subtask_id.to_string())),
-        )
-        .execute(&*conn)?;
-        Ok(())
+    // the subtask is kept in the trash, so it can be restored later
+    match conn.immediate_transaction::<(), IntegrityError, _>(|| {
+        conditional::check_if_match::<IntegrityError>(&req, conn, "subtasks", &uuid)?;
+        integrity::check_unreferenced(conn, &uuid, ObjectType::SUBTASK)?;
+        Ok(trash::move_to_trash(
+            conn,
+            &uuid,
+            ObjectType::SUBTASK,
+            &sub,
+        )?)
     }) {
         Ok(_) => Box::new(Ok(HttpResponse::Ok().finish()).into_future()),
-        Err(e) => {
-            log::error!("Couldn't delete subtask: {}", e);
-            Box::new(Ok(HttpResponse::InternalServerError().finish()).into_future())
-        }
+        Err(e) => Box::new(Ok(e.into_response("delete subtask")).into_future()),
     }
 }
 fn verify_subtask_solution(
@@ -206,6 +266,7 @@ fn verify_subtask_solution(
     // get teacher solution
     match schema::subtasks::table
         .find(format!("{}", subtask_id))
+        .select(models::Subtask::COLUMNS)
         .get_result::<models::Subtask>(&*conn)
     {
         Ok(subtask) => {
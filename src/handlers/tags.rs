@@ -0,0 +1,279 @@
+use crate::integrity::{IntegrityError, InvalidReferences};
+use crate::models::{self, ObjectType};
+use crate::quota;
+use crate::schema;
+use actix_web::{web, Error, HttpRequest, HttpResponse, Scope};
+use actix_web_jwt_middleware::Subject;
+use diesel::{
+    prelude::*,
+    r2d2::{self, ConnectionManager},
+    SqliteConnection,
+};
+use futures::future::{Future, IntoFuture};
+use serde::Deserialize;
+use uuid::Uuid;
+
+pub fn get_scope() -> Scope {
+    web::scope("/tags")
+        .service(
+            web::resource("")
+                .route(web::get().to_async(get_tags))
+                .route(web::post().to_async(create_tag)),
+        )
+        .service(
+            web::resource("/{id}")
+                .route(web::get().to_async(get_tag))
+                .route(web::put().to_async(update_tag))
+                .route(web::delete().to_async(delete_tag)),
+        )
+}
+
+/// Query string for filtering list endpoints by tags, e.g. `?tags=<id>,<id>`.
+/// Only objects carrying all of the given tags are returned.
+#[derive(Debug, Deserialize)]
+pub struct TagFilter {
+    tags: Option<String>,
+}
+
+impl TagFilter {
+    pub fn tag_ids(&self) -> Vec<String> {
+        match &self.tags {
+            Some(tags) => tags
+                .split(',')
+                .map(str::trim)
+                .filter(|tag| !tag.is_empty())
+                .map(str::to_string)
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+fn load_tag(
+    conn: &SqliteConnection,
+    tag: models::QueryableTag,
+) -> Result<models::Tag, diesel::result::Error> {
+    let subtasks = schema::subtasks_in_tags::table
+        .filter(schema::subtasks_in_tags::columns::tag_id.eq(&tag.id))
+        .select(schema::subtasks_in_tags::columns::subtask_id)
+        .load::<String>(conn)?;
+    let databases = schema::databases_in_tags::table
+        .filter(schema::databases_in_tags::columns::tag_id.eq(&tag.id))
+        .select(schema::databases_in_tags::columns::database_id)
+        .load::<String>(conn)?;
+    Ok(models::Tag {
+        id: tag.id,
+        name: tag.name,
+        category: tag.category,
+        subtasks,
+        databases,
+    })
+}
+
+/// Links the tag to its subtasks and databases, which have to exist and be accessible
+fn insert_tagged_objects(
+    conn: &SqliteConnection,
+    user_id: &str,
+    tag_id: &str,
+    tag: &models::Tag,
+) -> Result<(), IntegrityError> {
+    InvalidReferences::default()
+        .check(conn, user_id, ObjectType::SUBTASK, &tag.subtasks)?
+        .check(conn, user_id, ObjectType::DATABASE, &tag.databases)?
+        .into_result()?;
+    for subtask_id in tag.subtasks.iter() {
+        diesel::insert_into(schema::subtasks_in_tags::table)
+            .values(models::SubtasksInTag {
+                subtask_id: subtask_id.to_string(),
+                tag_id: tag_id.to_string(),
+            })
+            .execute(conn)?;
+    }
+    for database_id in tag.databases.iter() {
+        diesel::insert_into(schema::databases_in_tags::table)
+            .values(models::DatabasesInTag {
+                database_id: database_id.to_string(),
+                tag_id: tag_id.to_string(),
+            })
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+fn get_tags(
+    req: HttpRequest,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    match conn.transaction::<Vec<models::Tag>, diesel::result::Error, _>(|| {
+        let query_tags = schema::tags::table
+            .inner_join(
+                schema::access::table
+                    .on(schema::tags::columns::id.eq(schema::access::columns::object_id)),
+            )
+            .filter(schema::access::columns::user_id.eq(sub))
+            .select((
+                schema::tags::columns::id,
+                schema::tags::columns::name,
+                schema::tags::columns::category,
+            ))
+            .order(schema::tags::columns::name)
+            .load::<models::QueryableTag>(&*conn)?;
+        query_tags
+            .into_iter()
+            .map(|tag| load_tag(conn, tag))
+            .collect()
+    }) {
+        Ok(tags) => Box::new(Ok(HttpResponse::Ok().json(tags)).into_future()),
+        Err(e) => {
+            log::error!("Couldn't get tags: {}", e);
+            Box::new(Ok(HttpResponse::InternalServerError().finish()).into_future())
+        }
+    }
+}
+
+fn create_tag(
+    req: HttpRequest,
+    json: web::Json<models::Tag>,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let appdata: &crate::AppData = req.app_data().unwrap();
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    match conn.transaction::<Uuid, IntegrityError, _>(|| {
+        quota::enforce(conn, &appdata.settings, &sub, || {
+            // create tag object
+            let tag = json.into_inner();
+            let tag_id = Uuid::new_v4();
+            let mut new_tag = models::QueryableTag::from_tag(tag.clone());
+            new_tag.id = tag_id.to_string();
+
+            // insert access for user
+            diesel::insert_into(schema::access::table)
+                .values(models::Access {
+                    user_id: sub.clone(),
+                    object_id: tag_id.to_string(),
+                })
+                .execute(&*conn)?;
+
+            // insert tag object
+            diesel::insert_into(schema::tags::table)
+                .values(new_tag)
+                .execute(&*conn)?;
+
+            // set subtasks and databases carrying this tag
+            insert_tagged_objects(conn, &sub, &tag_id.to_string(), &tag)?;
+
+            Ok(tag_id)
+        })
+    }) {
+        Ok(tag_id) => Box::new(Ok(HttpResponse::Ok().body(tag_id.to_string())).into_future()),
+        Err(e) => Box::new(Ok(e.into_response("create tag")).into_future()),
+    }
+}
+
+fn get_tag(
+    req: HttpRequest,
+    id: web::Path<Uuid>,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    match conn.transaction::<models::Tag, diesel::result::Error, _>(|| {
+        let tag = schema::tags::table
+            .find(format!("{}", id))
+            .get_result::<models::QueryableTag>(&*conn)?;
+        load_tag(conn, tag)
+    }) {
+        Ok(tag) => Box::new(Ok(HttpResponse::Ok().json(tag)).into_future()),
+        Err(e) => match e {
+            diesel::result::Error::NotFound => {
+                Box::new(Ok(HttpResponse::NotFound().finish()).into_future())
+            }
+            e => {
+                log::error!("Couldn't get tag: {}", e);
+                Box::new(Ok(HttpResponse::InternalServerError().finish()).into_future())
+            }
+        },
+    }
+}
+
+fn update_tag(
+    req: HttpRequest,
+    id: web::Path<Uuid>,
+    json: web::Json<models::Tag>,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    let tag = json.into_inner();
+    let id = format!("{}", id.into_inner());
+    match conn.transaction::<(), IntegrityError, _>(|| {
+        // update tag
+        let mut queryable_tag = models::QueryableTag::from_tag(tag.clone());
+        queryable_tag.id = id.clone();
+        diesel::update(schema::tags::table.find(&id))
+            .set(queryable_tag)
+            .execute(&*conn)?;
+
+        // update which objects carry the tag, first delete old ones
+        diesel::delete(
+            schema::subtasks_in_tags::table.filter(schema::subtasks_in_tags::tag_id.eq(&id)),
+        )
+        .execute(&*conn)?;
+        diesel::delete(
+            schema::databases_in_tags::table.filter(schema::databases_in_tags::tag_id.eq(&id)),
+        )
+        .execute(&*conn)?;
+        // then insert new ones
+        insert_tagged_objects(conn, &sub, &id, &tag)
+    }) {
+        Ok(_) => Box::new(Ok(HttpResponse::Ok().finish()).into_future()),
+        Err(e) => Box::new(Ok(e.into_response("update tag")).into_future()),
+    }
+}
+
+fn delete_tag(
+    req: HttpRequest,
+    id: web::Path<Uuid>,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    let uuid = id.into_inner().to_string();
+
+    match conn.transaction::<(), diesel::result::Error, _>(|| {
+        diesel::delete(schema::access::table.filter(schema::access::object_id.eq(&uuid)))
+            .execute(&*conn)?;
+        diesel::delete(
+            schema::subtasks_in_tags::table.filter(schema::subtasks_in_tags::tag_id.eq(&uuid)),
+        )
+        .execute(&*conn)?;
+        diesel::delete(
+            schema::databases_in_tags::table.filter(schema::databases_in_tags::tag_id.eq(&uuid)),
+        )
+        .execute(&*conn)?;
+        diesel::delete(schema::tags::table.find(&uuid)).execute(&*conn)?;
+        Ok(())
+    }) {
+        Ok(_) => Box::new(Ok(HttpResponse::Ok().finish()).into_future()),
+        Err(e) => {
+            log::error!("Couldn't delete tag: {}", e);
+            Box::new(Ok(HttpResponse::InternalServerError().finish()).into_future())
+        }
+    }
+}
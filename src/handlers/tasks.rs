@@ -1,6 +1,15 @@
-use crate::models;
+use crate::handlers::{children, public_read, revisions};
+use crate::integrity::{self, IntegrityError, InvalidReferences};
+use crate::middlewares::conditional;
+use crate::models::{self, ObjectType};
+use crate::ordering::TaskSubtasks;
+use crate::patch::{self, PatchError};
+use crate::quota;
+use crate::revisions::TaskRevisions;
 use crate::schema;
+use crate::trash;
 use actix_web::{web, Error, HttpRequest, HttpResponse, Scope};
+use actix_web_jwt_middleware::{Policy, Subject};
 
 use futures::future::{Future, IntoFuture};
 use uuid::Uuid;
@@ -13,29 +22,104 @@ pub fn get_scope() -> Scope {
     web::scope("/tasks")
         .service(
             web::resource("")
+                .wrap(Policy::required())
                 .route(web::get().to_async(get_tasks))
                 .route(web::post().to_async(create_task)),
         )
         .service(
             web::resource("/{id}")
+                .wrap(public_read())
                 .route(web::get().to_async(get_task))
                 .route(web::put().to_async(update_task))
+                .route(web::patch().to_async(patch_task))
                 .route(web::delete().to_async(delete_task)),
         )
+        .service(revisions::get_scope::<TaskRevisions>().wrap(Policy::required()))
+        .service(children::get_scope::<TaskSubtasks>("/{id}/subtasks").wrap(Policy::required()))
 }
 
-fn get_tasks(req: HttpRequest) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+pub fn load_task(conn: &SqliteConnection, id: &str) -> Result<models::Task, diesel::result::Error> {
+    let task = schema::tasks::table
+        .find(id)
+        .select(models::QueryableTask::COLUMNS)
+        .get_result::<models::QueryableTask>(conn)?;
+
+    let subtasks = schema::subtasks_in_tasks::table
+        .filter(schema::subtasks_in_tasks::columns::task_id.eq(id))
+        .select(schema::subtasks_in_tasks::columns::subtask_id)
+        .order(schema::subtasks_in_tasks::position)
+        .load::<String>(conn)?;
+
+    Ok(models::Task {
+        id: task.id,
+        database_id: task.database_id,
+        subtasks,
+    })
+}
+
+pub fn save_task(
+    conn: &SqliteConnection,
+    id: &str,
+    mut task: models::Task,
+) -> Result<(), diesel::result::Error> {
+    task.id = id.to_string();
+
+    // update tasks
+    diesel::update(schema::tasks::table.find(id))
+        .set(models::QueryableTask::from_task(task.clone()))
+        .execute(conn)?;
+
+    // update which subtasks belong to this task
+    diesel::delete(
+        schema::subtasks_in_tasks::table.filter(schema::subtasks_in_tasks::task_id.eq(id)),
+    )
+    .execute(conn)?;
+    let mut pos = -1;
+    let subtasks_in_task: Vec<models::SubtasksInTask> = task
+        .subtasks
+        .iter()
+        .map(|subtask_id| {
+            pos += 1;
+            models::SubtasksInTask {
+                subtask_id: subtask_id.to_string(),
+                task_id: id.to_string(),
+                position: pos,
+            }
+        })
+        .collect();
+    for subtask in subtasks_in_task {
+        diesel::insert_into(schema::subtasks_in_tasks::table)
+            .values(subtask)
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+/// Fails if the subtasks or the database of a task are missing or not accessible
+pub fn check_references(
+    conn: &SqliteConnection,
+    user_id: &str,
+    task: &models::Task,
+) -> Result<(), IntegrityError> {
+    InvalidReferences::default()
+        .check(conn, user_id, ObjectType::SUBTASK, &task.subtasks)?
+        .check(
+            conn,
+            user_id,
+            ObjectType::DATABASE,
+            std::slice::from_ref(&task.database_id),
+        )?
+        .into_result()
+}
+
+fn get_tasks(
+    req: HttpRequest,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
     let extensions = req.extensions();
     let conn = extensions
         .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
         .unwrap();
-    let sub = extensions
-        .get::<actix_web_jwt_middleware::AuthenticationData>()
-        .unwrap()
-        .claims
-        .sub
-        .clone()
-        .unwrap();
 
     match schema::tasks::table
         .inner_join(
@@ -75,59 +159,56 @@ fn get_tasks(req: HttpRequest) -> Box<dyn Future<Item = HttpResponse, Error = Er
 fn create_task(
     req: HttpRequest,
     json: web::Json<models::Task>,
+    Subject(sub): Subject,
 ) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let appdata: &crate::AppData = req.app_data().unwrap();
     let extensions = req.extensions();
     let conn = extensions
         .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
         .unwrap();
-    let sub = extensions
-        .get::<actix_web_jwt_middleware::AuthenticationData>()
-        .unwrap()
-        .claims
-        .sub
-        .clone()
-        .unwrap();
-
-    match conn.transaction::<Uuid, diesel::result::Error, _>(|| {
-        // create task object
-        let task = json.into_inner();
-        let task_id = Uuid::new_v4();
-        let new_task = models::QueryableTask {
-            id: task_id.to_string(),
-            database_id: task.database_id,
-        };
 
-        // insert access for user
-        diesel::insert_into(schema::access::table)
-            .values(models::Access {
-                user_id: sub,
-                object_id: task_id.to_string(),
-            })
-            .execute(&*conn)?;
+    match conn.transaction::<Uuid, IntegrityError, _>(|| {
+        quota::enforce(conn, &appdata.settings, &sub, || {
+            // create task object
+            let task = json.into_inner();
+            check_references(conn, &sub, &task)?;
+            let task_id = Uuid::new_v4();
+            let new_task = models::QueryableTask {
+                id: task_id.to_string(),
+                database_id: task.database_id,
+            };
 
-        // set subtasks belonging to task
-        for (position, subtask_id) in task.subtasks.iter().enumerate() {
-            diesel::insert_into(schema::subtasks_in_tasks::table)
-                .values(models::SubtasksInTask {
-                    subtask_id: subtask_id.to_string(),
-                    task_id: task_id.to_string(),
-                    position: position as i32,
+            // insert access for user
+            diesel::insert_into(schema::access::table)
+                .values(models::Access {
+                    user_id: sub.clone(),
+                    object_id: task_id.to_string(),
                 })
                 .execute(&*conn)?;
-        }
 
-        // insert task object
-        diesel::insert_into(schema::tasks::table)
-            .values(new_task)
-            .execute(&*conn)?;
+            // insert task object
+            diesel::insert_into(schema::tasks::table)
+                .values(new_task)
+                .execute(&*conn)?;
+
+            // set subtasks belonging to task
+            for (position, subtask_id) in task.subtasks.iter().enumerate() {
+                diesel::insert_into(schema::subtasks_in_tasks::table)
+                    .values(models::SubtasksInTask {
+                        subtask_id: subtask_id.to_string(),
+                        task_id: task_id.to_string(),
+                        position: position as i32,
+                    })
+                    .execute(&*conn)?;
+            }
+
+            crate::revisions::record::<TaskRevisions>(conn, &task_id.to_string(), &sub, None)?;
 
-        Ok(task_id)
+            Ok(task_id)
+        })
     }) {
         Ok(id) => Box::new(Ok(HttpResponse::Ok().body(id.to_string())).into_future()),
-        Err(e) => {
-            log::error!("Couldn't create task: {}", e);
-            Box::new(Ok(HttpResponse::InternalServerError().finish()).into_future())
-        }
+        Err(e) => Box::new(Ok(e.into_response("create task")).into_future()),
     }
 }
 
@@ -140,26 +221,8 @@ fn get_task(
         .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
         .unwrap();
 
-    match schema::tasks::table
-        .find(format!("{}", id))
-        .get_result::<models::QueryableTask>(&*conn)
-    {
-        Ok(task) => {
-            let subtasks_query = schema::subtasks_in_tasks::table
-                .filter(schema::subtasks_in_tasks::columns::task_id.eq(format!("{}", id)))
-                .select(schema::subtasks_in_tasks::columns::subtask_id)
-                .order(schema::subtasks_in_tasks::position)
-                .load::<String>(&*conn);
-
-            Box::new(
-                Ok(HttpResponse::Ok().json(models::Task {
-                    id: task.id,
-                    database_id: task.database_id,
-                    subtasks: subtasks_query.unwrap(),
-                }))
-                .into_future(),
-            )
-        }
+    match load_task(conn, &id.to_string()) {
+        Ok(task) => Box::new(Ok(HttpResponse::Ok().json(task)).into_future()),
         Err(e) => match e {
             diesel::result::Error::NotFound => {
                 Box::new(Ok(HttpResponse::NotFound().finish()).into_future())
@@ -176,88 +239,71 @@ fn update_task(
     req: HttpRequest,
     id: web::Path<Uuid>,
     json: web::Json<models::Task>,
+    Subject(sub): Subject,
 ) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
     let extensions = req.extensions();
     let conn = extensions
         .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
         .unwrap();
 
-    match conn.transaction::<(), diesel::result::Error, _>(|| {
+    let id = id.into_inner().to_string();
+    match conn.immediate_transaction::<i32, IntegrityError, _>(|| {
+        conditional::check_if_match::<IntegrityError>(&req, conn, "tasks", &id)?;
         let task = json.into_inner();
+        check_references(conn, &sub, &task)?;
+        Ok(crate::revisions::update::<TaskRevisions>(
+            conn, &id, &sub, task, None,
+        )?)
+    }) {
+        Ok(_) => Box::new(Ok(HttpResponse::Ok().finish()).into_future()),
+        Err(e) => Box::new(Ok(e.into_response("update task")).into_future()),
+    }
+}
 
-        // update tasks
-        diesel::update(schema::tasks::table.find(format!("{}", id)))
-            .set(models::QueryableTask::from_task(task.clone()))
-            .execute(&*conn)?;
+fn patch_task(
+    req: HttpRequest,
+    id: web::Path<Uuid>,
+    json: web::Json<json_patch::Patch>,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
 
-        // update which subtasks belong to this task
-        diesel::delete(
-            schema::subtasks_in_tasks::table
-                .filter(schema::subtasks_in_tasks::task_id.eq(task.id.clone())),
-        )
-        .execute(&*conn)?;
-        let mut pos = -1;
-        let task_id = task.id.clone();
-        let subtasks_in_task: Vec<models::SubtasksInTask> = task
-            .subtasks
-            .iter()
-            .map(|subtask_id| {
-                pos += 1;
-                models::SubtasksInTask {
-                    subtask_id: subtask_id.to_string(),
-                    task_id: task_id.clone(),
-                    position: pos,
-                }
-            })
-            .collect();
-        for subtask in subtasks_in_task {
-            diesel::insert_into(schema::subtasks_in_tasks::table)
-                .values(subtask)
-                .execute(&*conn)?;
-        }
-        Ok(())
+    let id = id.into_inner().to_string();
+    match conn.immediate_transaction::<i32, PatchError, _>(|| {
+        conditional::check_if_match::<PatchError>(&req, conn, "tasks", &id)?;
+        let task = patch::apply(&load_task(conn, &id)?, &json)?;
+        check_references(conn, &sub, &task)?;
+        Ok(crate::revisions::update::<TaskRevisions>(
+            conn, &id, &sub, task, None,
+        )?)
     }) {
         Ok(_) => Box::new(Ok(HttpResponse::Ok().finish()).into_future()),
-        Err(e) => {
-            log::error!("Couldn't update task: {}", e);
-            Box::new(Ok(HttpResponse::InternalServerError().finish()).into_future())
-        }
+        Err(e) => Box::new(Ok(e.into_response("patch task")).into_future()),
     }
 }
 
 fn delete_task(
     req: HttpRequest,
     id: web::Path<Uuid>,
+    Subject(sub): Subject,
 ) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
     let extensions = req.extensions();
     let conn = extensions
         .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
         .unwrap();
 
-    let uuid = id.into_inner();
+    let uuid = id.into_inner().to_string();
 
-    match conn.transaction::<(), diesel::result::Error, _>(|| {
-        diesel::delete(
-            schema::subtasks_in_tasks::table
-                .filter(schema::subtasks_in_tasks::task_id.eq(uuid.to_string())),
-        )
-        .execute(&*conn)?;
-        diesel::delete(
-            schema::tasks_in_worksheets::table
-                .filter(schema::tasks_in_worksheets::task_id.eq(uuid.to_string())),
-        )
-        .execute(&*conn)?;
-        diesel::delete(
-            schema::access::table.filter(schema::access::object_id.eq(uuid.to_string())),
-        )
-        .execute(&*conn)?;
-        diesel::delete(schema::tasks::table.find(format!("{}", uuid))).execute(&*conn)?;
-        Ok(())
+    // the task is kept in the trash, so it can be restored later
+    match conn.immediate_transaction::<(), IntegrityError, _>(|| {
+        conditional::check_if_match::<IntegrityError>(&req, conn, "tasks", &uuid)?;
+        integrity::check_unreferenced(conn, &uuid, ObjectType::TASK)?;
+        Ok(trash::move_to_trash(conn, &uuid, ObjectType::TASK, &sub)?)
     }) {
         Ok(_) => Box::new(Ok(HttpResponse::Ok().finish()).into_future()),
-        Err(e) => {
-            log::error!("Couldn't delete task: {}", e);
-            Box::new(Ok(HttpResponse::InternalServerError().finish()).into_future())
-        }
+        Err(e) => Box::new(Ok(e.into_response("delete task")).into_future()),
     }
 }
@@ -0,0 +1,171 @@
+use crate::middlewares::ownership::has_access;
+use crate::models::{self, ObjectType};
+use crate::quota::{self, Exceeded};
+use crate::schema;
+use crate::trash;
+use actix_web::{web, Error, HttpRequest, HttpResponse, Scope};
+use actix_web_jwt_middleware::Subject;
+use diesel::{
+    prelude::*,
+    r2d2::{self, ConnectionManager},
+    SqliteConnection,
+};
+use futures::future::{Future, IntoFuture};
+use serde::Deserialize;
+use uuid::Uuid;
+
+pub fn get_scope() -> Scope {
+    web::scope("/trash")
+        .service(web::resource("").route(web::get().to_async(get_trash)))
+        .service(web::resource("/{id}").route(web::delete().to_async(purge_object)))
+        .service(web::resource("/{id}/restore").route(web::post().to_async(restore_object)))
+}
+
+/// Query string for listing the trash, e.g. `?type=TASK`
+#[derive(Debug, Deserialize)]
+pub struct TrashQuery {
+    #[serde(rename = "type")]
+    object_type: Option<ObjectType>,
+}
+
+enum TrashError {
+    Diesel(diesel::result::Error),
+    NoAccess,
+    Conflict,
+    /// with a message naming the missing object
+    MissingReference(String),
+    Quota(Exceeded),
+}
+
+impl From<Exceeded> for TrashError {
+    fn from(val: Exceeded) -> TrashError {
+        TrashError::Quota(val)
+    }
+}
+
+impl From<diesel::result::Error> for TrashError {
+    fn from(val: diesel::result::Error) -> TrashError {
+        match val {
+            diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                _,
+            ) => TrashError::Conflict,
+            diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::ForeignKeyViolation,
+                _,
+            ) => TrashError::MissingReference(
+                "An object this one refers to doesn't exist anymore.".to_owned(),
+            ),
+            val => TrashError::Diesel(val),
+        }
+    }
+}
+
+impl From<trash::RestoreError> for TrashError {
+    fn from(val: trash::RestoreError) -> TrashError {
+        match val {
+            trash::RestoreError::Diesel(e) => e.into(),
+            trash::RestoreError::MissingReference(message) => TrashError::MissingReference(message),
+        }
+    }
+}
+
+impl TrashError {
+    fn into_response(self, action: &str) -> HttpResponse {
+        match self {
+            TrashError::Diesel(diesel::result::Error::NotFound) => {
+                HttpResponse::NotFound().finish()
+            }
+            TrashError::Diesel(e) => {
+                log::error!("Couldn't {}: {}", action, e);
+                HttpResponse::InternalServerError().finish()
+            }
+            TrashError::NoAccess => HttpResponse::Forbidden().finish(),
+            TrashError::Conflict => HttpResponse::Conflict()
+                .body("An object with the same name has been created in the meantime."),
+            TrashError::MissingReference(message) => HttpResponse::Conflict().body(message),
+            TrashError::Quota(exceeded) => exceeded.into_response(),
+        }
+    }
+}
+
+fn get_trash(
+    req: HttpRequest,
+    query: web::Query<TrashQuery>,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    let mut entries = schema::trash::table
+        .inner_join(
+            schema::access::table
+                .on(schema::trash::columns::object_id.eq(schema::access::columns::object_id)),
+        )
+        .filter(schema::access::columns::user_id.eq(sub))
+        .select((
+            schema::trash::columns::object_id,
+            schema::trash::columns::object_type,
+            schema::trash::columns::name,
+            schema::trash::columns::deleted_by,
+            schema::trash::columns::deleted_at,
+            schema::trash::columns::content,
+            schema::trash::columns::content_hash,
+        ))
+        .order(schema::trash::columns::deleted_at.desc())
+        .into_boxed();
+    if let Some(object_type) = query.object_type {
+        entries = entries.filter(schema::trash::columns::object_type.eq(object_type));
+    }
+
+    match entries.load::<models::TrashEntry>(&*conn) {
+        Ok(result) => Box::new(Ok(HttpResponse::Ok().json(result)).into_future()),
+        Err(e) => {
+            log::error!("Couldn't get trash: {}", e);
+            Box::new(Ok(HttpResponse::InternalServerError().finish()).into_future())
+        }
+    }
+}
+
+fn restore_object(
+    req: HttpRequest,
+    id: web::Path<Uuid>,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let appdata: &crate::AppData = req.app_data().unwrap();
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    let uuid = id.into_inner().to_string();
+    match conn.transaction::<(), TrashError, _>(|| {
+        quota::enforce(conn, &appdata.settings, &sub, || {
+            if !has_access(conn, &sub, &uuid)? {
+                return Err(TrashError::NoAccess);
+            }
+            Ok(trash::restore(conn, &uuid)?)
+        })
+    }) {
+        Ok(_) => Box::new(Ok(HttpResponse::Ok().finish()).into_future()),
+        Err(e) => Box::new(Ok(e.into_response("restore object")).into_future()),
+    }
+}
+
+fn purge_object(
+    req: HttpRequest,
+    id: web::Path<Uuid>,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
+
+    let uuid = id.into_inner().to_string();
+    match conn.transaction::<(), TrashError, _>(|| Ok(trash::purge(conn, &uuid)?)) {
+        Ok(_) => Box::new(Ok(HttpResponse::Ok().finish()).into_future()),
+        Err(e) => Box::new(Ok(e.into_response("purge object")).into_future()),
+    }
+}
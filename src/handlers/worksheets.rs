@@ -1,7 +1,16 @@
+use crate::handlers::{children, public_read, revisions};
+use crate::integrity::{self, IntegrityError, InvalidReferences};
+use crate::middlewares::conditional;
 use crate::models;
-use crate::models::TasksInWorksheet;
+use crate::models::{ObjectType, TasksInWorksheet};
+use crate::ordering::WorksheetTasks;
+use crate::patch::{self, PatchError};
+use crate::quota;
+use crate::revisions::WorksheetRevisions;
 use crate::schema;
+use crate::trash;
 use actix_web::{web, Error, HttpRequest, HttpResponse, Scope};
+use actix_web_jwt_middleware::{Policy, Subject};
 use diesel::{
     prelude::*,
     r2d2::{self, ConnectionManager},
@@ -13,29 +22,106 @@ pub fn get_scope() -> Scope {
     web::scope("/worksheets")
         .service(
             web::resource("")
+                .wrap(Policy::required())
                 .route(web::get().to_async(get_worksheets))
                 .route(web::post().to_async(create_worksheet)),
         )
         .service(
             web::resource("/{id}")
+                .wrap(public_read())
                 .route(web::get().to_async(get_worksheet))
                 .route(web::put().to_async(update_worksheet))
+                .route(web::patch().to_async(patch_worksheet))
                 .route(web::delete().to_async(delete_worksheet)),
         )
+        .service(revisions::get_scope::<WorksheetRevisions>().wrap(Policy::required()))
+        .service(children::get_scope::<WorksheetTasks>("/{id}/tasks").wrap(Policy::required()))
 }
 
-fn get_worksheets(req: HttpRequest) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+pub fn load_worksheet(
+    conn: &SqliteConnection,
+    id: &str,
+) -> Result<models::Worksheet, diesel::result::Error> {
+    let worksheet = schema::worksheets::table
+        .find(id)
+        .select(models::QueryableWorksheet::COLUMNS)
+        .get_result::<models::QueryableWorksheet>(conn)?;
+
+    let tasks = schema::tasks_in_worksheets::table
+        .filter(schema::tasks_in_worksheets::columns::worksheet_id.eq(id))
+        .select(schema::tasks_in_worksheets::columns::task_id)
+        .order(schema::tasks_in_worksheets::position)
+        .load::<String>(conn)?;
+
+    Ok(models::Worksheet {
+        id: worksheet.id,
+        name: worksheet.name,
+        is_online: worksheet.is_online,
+        is_solution_online: worksheet.is_solution_online,
+        tasks,
+    })
+}
+
+pub fn save_worksheet(
+    conn: &SqliteConnection,
+    id: &str,
+    mut worksheet: models::Worksheet,
+) -> Result<(), diesel::result::Error> {
+    worksheet.id = id.to_string();
+
+    // update worksheet
+    diesel::update(schema::worksheets::table.find(id))
+        .set(models::QueryableWorksheet::from_worksheet(
+            worksheet.clone(),
+        ))
+        .execute(conn)?;
+
+    // update which tasks belong to worksheet
+    diesel::delete(
+        schema::tasks_in_worksheets::table.filter(schema::tasks_in_worksheets::worksheet_id.eq(id)),
+    )
+    .execute(conn)?;
+    let mut pos = -1;
+    let tasks_in_worksheet: Vec<TasksInWorksheet> = worksheet
+        .tasks
+        .iter()
+        .map(|task_id| {
+            pos += 1;
+            models::TasksInWorksheet {
+                task_id: task_id.to_string(),
+                worksheet_id: id.to_string(),
+                position: pos,
+            }
+        })
+        .collect();
+
+    for task in tasks_in_worksheet {
+        diesel::insert_into(schema::tasks_in_worksheets::table)
+            .values(task)
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+/// Fails if tasks of a worksheet are missing or not accessible
+pub fn check_references(
+    conn: &SqliteConnection,
+    user_id: &str,
+    worksheet: &models::Worksheet,
+) -> Result<(), IntegrityError> {
+    InvalidReferences::default()
+        .check(conn, user_id, ObjectType::TASK, &worksheet.tasks)?
+        .into_result()
+}
+
+fn get_worksheets(
+    req: HttpRequest,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
     let extensions = req.extensions();
     let conn = extensions
         .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
         .unwrap();
-    let sub = extensions
-        .get::<actix_web_jwt_middleware::AuthenticationData>()
-        .unwrap()
-        .claims
-        .sub
-        .clone()
-        .unwrap();
 
     match conn.transaction::<Vec<models::Worksheet>, diesel::result::Error, _>(|| {
         let mut worksheets: Vec<models::Worksheet> = Vec::new();
@@ -79,61 +165,63 @@ fn get_worksheets(req: HttpRequest) -> Box<dyn Future<Item = HttpResponse, Error
 fn create_worksheet(
     req: HttpRequest,
     json: web::Json<models::Worksheet>,
+    Subject(sub): Subject,
 ) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let appdata: &crate::AppData = req.app_data().unwrap();
     let extensions = req.extensions();
     let conn = extensions
         .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
         .unwrap();
-    let sub = extensions
-        .get::<actix_web_jwt_middleware::AuthenticationData>()
-        .unwrap()
-        .claims
-        .sub
-        .clone()
-        .unwrap();
-
-    match conn.transaction::<Uuid, diesel::result::Error, _>(|| {
-        // create worksheet object
-        let worksheet = json.into_inner();
-        let worksheet_id = Uuid::new_v4();
-        let new_worksheet = models::QueryableWorksheet {
-            id: worksheet_id.to_string(),
-            name: worksheet.name,
-            is_online: worksheet.is_online,
-            is_solution_online: worksheet.is_solution_online,
-        };
 
-        // insert access for user
-        diesel::insert_into(schema::access::table)
-            .values(models::Access {
-                user_id: sub,
-                object_id: worksheet_id.to_string(),
-            })
-            .execute(&*conn)?;
+    match conn.transaction::<Uuid, IntegrityError, _>(|| {
+        quota::enforce(conn, &appdata.settings, &sub, || {
+            // create worksheet object
+            let worksheet = json.into_inner();
+            check_references(conn, &sub, &worksheet)?;
+            let worksheet_id = Uuid::new_v4();
+            let new_worksheet = models::QueryableWorksheet {
+                id: worksheet_id.to_string(),
+                name: worksheet.name,
+                is_online: worksheet.is_online,
+                is_solution_online: worksheet.is_solution_online,
+            };
 
-        // set tasks belonging to worksheet
-        for (position, task_id) in worksheet.tasks.iter().enumerate() {
-            diesel::insert_into(schema::tasks_in_worksheets::table)
-                .values(models::TasksInWorksheet {
-                    task_id: task_id.to_string(),
-                    worksheet_id: worksheet_id.to_string(),
-                    position: position as i32,
+            // insert access for user
+            diesel::insert_into(schema::access::table)
+                .values(models::Access {
+                    user_id: sub.clone(),
+                    object_id: worksheet_id.to_string(),
                 })
                 .execute(&*conn)?;
-        }
 
-        // insert worksheet object
-        diesel::insert_into(schema::worksheets::table)
-            .values(new_worksheet)
-            .execute(&*conn)?;
+            // insert worksheet object
+            diesel::insert_into(schema::worksheets::table)
+                .values(new_worksheet)
+                .execute(&*conn)?;
+
+            // set tasks belonging to worksheet
+            for (position, task_id) in worksheet.tasks.iter().enumerate() {
+                diesel::insert_into(schema::tasks_in_worksheets::table)
+                    .values(models::TasksInWorksheet {
+                        task_id: task_id.to_string(),
+                        worksheet_id: worksheet_id.to_string(),
+                        position: position as i32,
+                    })
+                    .execute(&*conn)?;
+            }
 
-        Ok(worksheet_id)
+            crate::revisions::record::<WorksheetRevisions>(
+                conn,
+                &worksheet_id.to_string(),
+                &sub,
+                None,
+            )?;
+
+            Ok(worksheet_id)
+        })
     }) {
         Ok(id) => Box::new(Ok(HttpResponse::Ok().body(id.to_string())).into_future()),
-        Err(e) => {
-            log::error!("Couldn't create worksheet: {}", e);
-            Box::new(Ok(HttpResponse::InternalServerError().finish()).into_future())
-        }
+        Err(e) => Box::new(Ok(e.into_response("create worksheet")).into_future()),
     }
 }
 
@@ -146,25 +234,7 @@ fn get_worksheet(
         .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
         .unwrap();
 
-    match conn.transaction::<models::Worksheet, diesel::result::Error, _>(|| {
-        let worksheet = schema::worksheets::table
-            .find(format!("{}", id))
-            .get_result::<models::QueryableWorksheet>(&*conn)?;
-
-        let tasks_query = schema::tasks_in_worksheets::table
-            .filter(schema::tasks_in_worksheets::columns::worksheet_id.eq(format!("{}", id)))
-            .select(schema::tasks_in_worksheets::columns::task_id)
-            .order(schema::tasks_in_worksheets::position)
-            .load::<String>(&*conn);
-
-        Ok(models::Worksheet {
-            id: worksheet.id,
-            name: worksheet.name,
-            is_online: worksheet.is_online,
-            is_solution_online: worksheet.is_solution_online,
-            tasks: tasks_query.unwrap(),
-        })
-    }) {
+    match load_worksheet(conn, &id.to_string()) {
         Ok(sheet) => Box::new(Ok(HttpResponse::Ok().json(sheet)).into_future()),
         Err(e) => match e {
             diesel::result::Error::NotFound => {
@@ -182,81 +252,76 @@ fn update_worksheet(
     req: HttpRequest,
     id: web::Path<Uuid>,
     json: web::Json<models::Worksheet>,
+    Subject(sub): Subject,
 ) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
     let extensions = req.extensions();
     let conn = extensions
         .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
         .unwrap();
 
-    match conn.transaction::<(), diesel::result::Error, _>(|| {
+    let id = id.into_inner().to_string();
+    match conn.immediate_transaction::<i32, IntegrityError, _>(|| {
+        conditional::check_if_match::<IntegrityError>(&req, conn, "worksheets", &id)?;
         let worksheet = json.into_inner();
+        check_references(conn, &sub, &worksheet)?;
+        Ok(crate::revisions::update::<WorksheetRevisions>(
+            conn, &id, &sub, worksheet, None,
+        )?)
+    }) {
+        Ok(_) => Box::new(Ok(HttpResponse::Ok().finish()).into_future()),
+        Err(e) => Box::new(Ok(e.into_response("update worksheet")).into_future()),
+    }
+}
 
-        // update worksheet
-        diesel::update(schema::worksheets::table.find(format!("{}", id)))
-            .set(models::QueryableWorksheet::from_worksheet(
-                worksheet.clone(),
-            ))
-            .execute(&*conn)?;
-
-        // update which tasks belong to worksheet
-        diesel::delete(
-            schema::tasks_in_worksheets::table
-                .filter(schema::tasks_in_worksheets::worksheet_id.eq(worksheet.id.clone())),
-        )
-        .execute(&*conn)?;
-        let mut pos = -1;
-        let worksheet_id = worksheet.id.clone();
-        let tasks_in_worksheet: Vec<TasksInWorksheet> = worksheet
-            .tasks
-            .iter()
-            .map(|task_id| {
-                pos += 1;
-                models::TasksInWorksheet {
-                    task_id: task_id.to_string(),
-                    worksheet_id: worksheet_id.clone(),
-                    position: pos,
-                }
-            })
-            .collect();
+fn patch_worksheet(
+    req: HttpRequest,
+    id: web::Path<Uuid>,
+    json: web::Json<json_patch::Patch>,
+    Subject(sub): Subject,
+) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
+    let extensions = req.extensions();
+    let conn = extensions
+        .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+        .unwrap();
 
-        for task in tasks_in_worksheet {
-            diesel::insert_into(schema::tasks_in_worksheets::table)
-                .values(task)
-                .execute(&*conn)?;
-        }
-        Ok(())
+    let id = id.into_inner().to_string();
+    match conn.immediate_transaction::<i32, PatchError, _>(|| {
+        conditional::check_if_match::<PatchError>(&req, conn, "worksheets", &id)?;
+        let worksheet = patch::apply(&load_worksheet(conn, &id)?, &json)?;
+        check_references(conn, &sub, &worksheet)?;
+        Ok(crate::revisions::update::<WorksheetRevisions>(
+            conn, &id, &sub, worksheet, None,
+        )?)
     }) {
         Ok(_) => Box::new(Ok(HttpResponse::Ok().finish()).into_future()),
-        Err(e) => {
-            log::error!("Couldn't update worksheet: {}", e);
-            Box::new(Ok(HttpResponse::InternalServerError().finish()).into_future())
-        }
+        Err(e) => Box::new(Ok(e.into_response("patch worksheet")).into_future()),
     }
 }
 
 fn delete_worksheet(
     req: HttpRequest,
     id: web::Path<Uuid>,
+    Subject(sub): Subject,
 ) -> Box<dyn Future<Item = HttpResponse, Error = Error>> {
     let extensions = req.extensions();
     let conn = extensions
         .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
         .unwrap();
 
-    match conn.transaction::<(), diesel::result::Error, _>(|| {
-        let uuid = id.into_inner();
-        diesel::delete(
-            schema::tasks_in_worksheets::table
-                .filter(schema::tasks_in_worksheets::worksheet_id.eq(uuid.to_string())),
-        )
-        .execute(&*conn)?;
-        diesel::delete(schema::worksheets::table.find(format!("{}", uuid))).execute(&*conn)?;
-        Ok(())
+    let uuid = id.into_inner().to_string();
+
+    // the worksheet is kept in the trash, so it can be restored later
+    match conn.immediate_transaction::<(), IntegrityError, _>(|| {
+        conditional::check_if_match::<IntegrityError>(&req, conn, "worksheets", &uuid)?;
+        integrity::check_unreferenced(conn, &uuid, ObjectType::WORKSHEET)?;
+        Ok(trash::move_to_trash(
+            conn,
+            &uuid,
+            ObjectType::WORKSHEET,
+            &sub,
+        )?)
     }) {
         Ok(_) => Box::new(Ok(HttpResponse::Ok().finish()).into_future()),
-        Err(e) => {
-            log::error!("Couldn't delete worksheet: {}", e);
-            Box::new(Ok(HttpResponse::InternalServerError().finish()).into_future())
-        }
+        Err(e) => Box::new(Ok(e.into_response("delete worksheet")).into_future()),
     }
 }
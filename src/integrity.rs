@@ -0,0 +1,563 @@
+use crate::database::DatabaseConnectionConfig;
+use crate::middlewares::conditional::PreconditionFailed;
+use crate::models::ObjectType;
+use crate::quota::Exceeded;
+use crate::schema;
+use crate::trash;
+use actix_web::HttpResponse;
+use diesel::{dsl::not, prelude::*, SqliteConnection};
+use serde::Serialize;
+
+/// An object that refers to another one, e.g. a task using a database
+#[derive(Debug, Serialize)]
+pub struct Reference {
+    pub object_id: String,
+    pub object_type: ObjectType,
+}
+
+pub enum IntegrityError {
+    Diesel(diesel::result::Error),
+    Referenced(Vec<Reference>),
+    InvalidReferences(InvalidReferences),
+    Quota(Exceeded),
+    PreconditionFailed,
+}
+
+impl From<Exceeded> for IntegrityError {
+    fn from(val: Exceeded) -> IntegrityError {
+        IntegrityError::Quota(val)
+    }
+}
+
+impl From<PreconditionFailed> for IntegrityError {
+    fn from(_: PreconditionFailed) -> IntegrityError {
+        IntegrityError::PreconditionFailed
+    }
+}
+
+impl From<diesel::result::Error> for IntegrityError {
+    fn from(val: diesel::result::Error) -> IntegrityError {
+        IntegrityError::Diesel(val)
+    }
+}
+
+impl IntegrityError {
+    pub fn into_response(self, action: &str) -> HttpResponse {
+        match self {
+            IntegrityError::Diesel(diesel::result::Error::NotFound) => {
+                HttpResponse::NotFound().finish()
+            }
+            IntegrityError::Diesel(diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::ForeignKeyViolation,
+                _,
+            )) => HttpResponse::Conflict().finish(),
+            IntegrityError::Diesel(e) => {
+                log::error!("Couldn't {}: {}", action, e);
+                HttpResponse::InternalServerError().finish()
+            }
+            IntegrityError::Referenced(references) => HttpResponse::Conflict().json(references),
+            IntegrityError::InvalidReferences(invalid) => {
+                HttpResponse::UnprocessableEntity().json(invalid)
+            }
+            IntegrityError::Quota(exceeded) => exceeded.into_response(),
+            IntegrityError::PreconditionFailed => HttpResponse::PreconditionFailed().finish(),
+        }
+    }
+}
+
+/// Fails with the list of referencing objects if removing the object would leave them
+/// pointing at nothing. Links between objects don't count, the trash restores those.
+pub fn check_unreferenced(
+    conn: &SqliteConnection,
+    id: &str,
+    object_type: ObjectType,
+) -> Result<(), IntegrityError> {
+    let references = match object_type {
+        ObjectType::DATABASE => schema::tasks::table
+            .filter(schema::tasks::database_id.eq(id))
+            .select(schema::tasks::id)
+            .load::<String>(conn)?
+            .into_iter()
+            .map(|object_id| Reference {
+                object_id,
+                object_type: ObjectType::TASK,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    if references.is_empty() {
+        Ok(())
+    } else {
+        Err(IntegrityError::Referenced(references))
+    }
+}
+
+/// Keeps those of the given ids which still exist, in their original order
+pub fn existing(
+    conn: &SqliteConnection,
+    object_type: ObjectType,
+    ids: &[String],
+) -> Result<Vec<String>, diesel::result::Error> {
+    let found = match object_type {
+        ObjectType::COURSE => schema::courses::table
+            .filter(schema::courses::id.eq_any(ids))
+            .select(schema::courses::id)
+            .load::<String>(conn)?,
+        ObjectType::WORKSHEET => schema::worksheets::table
+            .filter(schema::worksheets::id.eq_any(ids))
+            .select(schema::worksheets::id)
+            .load::<String>(conn)?,
+        ObjectType::TASK => schema::tasks::table
+            .filter(schema::tasks::id.eq_any(ids))
+            .select(schema::tasks::id)
+            .load::<String>(conn)?,
+        ObjectType::SUBTASK => schema::subtasks::table
+            .filter(schema::subtasks::id.eq_any(ids))
+            .select(schema::subtasks::id)
+            .load::<String>(conn)?,
+        ObjectType::DATABASE => schema::databases::table
+            .filter(schema::databases::id.eq_any(ids))
+            .select(schema::databases::id)
+            .load::<String>(conn)?,
+    };
+    Ok(ids
+        .iter()
+        .filter(|id| found.contains(id))
+        .cloned()
+        .collect())
+}
+
+/// Ids from a request which don't exist, or which the user has no access to
+#[derive(Debug, Default, Serialize)]
+pub struct InvalidReferences {
+    pub missing: Vec<String>,
+    pub inaccessible: Vec<String>,
+}
+
+impl InvalidReferences {
+    /// Records which of the ids don't belong to an existing object of the given type,
+    /// or to one the user has no access to
+    pub fn check(
+        mut self,
+        conn: &SqliteConnection,
+        user_id: &str,
+        object_type: ObjectType,
+        ids: &[String],
+    ) -> Result<Self, diesel::result::Error> {
+        let found = existing(conn, object_type, ids)?;
+        let accessible = schema::access::table
+            .filter(schema::access::user_id.eq(user_id))
+            .filter(schema::access::object_id.eq_any(&found))
+            .select(schema::access::object_id)
+            .load::<String>(conn)?;
+        for id in ids {
+            if !found.contains(id) {
+                self.missing.push(id.clone());
+            } else if !accessible.contains(id) {
+                self.inaccessible.push(id.clone());
+            }
+        }
+        Ok(self)
+    }
+
+    pub fn into_result(self) -> Result<(), IntegrityError> {
+        if self.missing.is_empty() && self.inaccessible.is_empty() {
+            Ok(())
+        } else {
+            Err(IntegrityError::InvalidReferences(self))
+        }
+    }
+}
+
+/// What a garbage collection run removed, or would remove
+#[derive(Debug, Default)]
+pub struct GarbageReport {
+    pub links: usize,
+    pub objects: Vec<(ObjectType, String)>,
+    pub access: usize,
+    pub aliases: Vec<String>,
+    pub revisions: usize,
+    pub blobs: usize,
+    /// tasks whose database is missing, these are reported but kept
+    pub broken_tasks: Vec<String>,
+}
+
+/// Removes links to missing objects, objects nobody has access to and which aren't
+/// part of anything else, access rows, aliases and revisions of missing objects, and dumps
+/// no database or trash entry refers to.
+/// With `dry_run` everything is rolled back and only the report is returned.
+pub fn collect_garbage(
+    conn: &SqliteConnection,
+    dry_run: bool,
+) -> Result<GarbageReport, diesel::result::Error> {
+    let mut report = GarbageReport::default();
+    let result = conn.transaction::<(), diesel::result::Error, _>(|| {
+        report.links = remove_dangling_links(conn)?;
+        loop {
+            let orphans = find_orphans(conn)?;
+            if orphans.is_empty() {
+                break;
+            }
+            // removing an orphan can leave its children orphaned as well
+            for (object_type, id) in orphans.iter() {
+                trash::remove(conn, id, *object_type)?;
+            }
+            report.objects.extend(orphans);
+        }
+        report.access = remove_dangling_access(conn)?;
+        report.aliases = remove_dangling_aliases(conn)?;
+        report.revisions = remove_dangling_revisions(conn)?;
+        report.blobs = remove_unreferenced_blobs(conn)?;
+        report.broken_tasks = schema::tasks::table
+            .filter(not(schema::tasks::database_id
+                .eq_any(schema::databases::table.select(schema::databases::id))))
+            .select(schema::tasks::id)
+            .load::<String>(conn)?;
+        if dry_run {
+            Err(diesel::result::Error::RollbackTransaction)
+        } else {
+            Ok(())
+        }
+    });
+    match result {
+        Ok(_) | Err(diesel::result::Error::RollbackTransaction) => Ok(report),
+        Err(e) => Err(e),
+    }
+}
+
+fn remove_dangling_links(conn: &SqliteConnection) -> Result<usize, diesel::result::Error> {
+    let mut removed = diesel::delete(
+        schema::worksheets_in_courses::table.filter(
+            not(schema::worksheets_in_courses::worksheet_id
+                .eq_any(schema::worksheets::table.select(schema::worksheets::id)))
+            .or(not(schema::worksheets_in_courses::course_id
+                .eq_any(schema::courses::table.select(schema::courses::id)))),
+        ),
+    )
+    .execute(conn)?;
+    removed += diesel::delete(
+        schema::tasks_in_worksheets::table.filter(
+            not(schema::tasks_in_worksheets::task_id
+                .eq_any(schema::tasks::table.select(schema::tasks::id)))
+            .or(not(schema::tasks_in_worksheets::worksheet_id.eq_any(
+                schema::worksheets::table.select(schema::worksheets::id),
+            ))),
+        ),
+    )
+    .execute(conn)?;
+    removed += diesel::delete(
+        schema::subtasks_in_tasks::table.filter(
+            not(schema::subtasks_in_tasks::subtask_id
+                .eq_any(schema::subtasks::table.select(schema::subtasks::id)))
+            .or(not(schema::subtasks_in_tasks::task_id
+                .eq_any(schema::tasks::table.select(schema::tasks::id)))),
+        ),
+    )
+    .execute(conn)?;
+    removed += diesel::delete(
+        schema::subtasks_in_tags::table.filter(
+            not(schema::subtasks_in_tags::subtask_id
+                .eq_any(schema::subtasks::table.select(schema::subtasks::id)))
+            .or(not(schema::subtasks_in_tags::tag_id
+                .eq_any(schema::tags::table.select(schema::tags::id)))),
+        ),
+    )
+    .execute(conn)?;
+    removed += diesel::delete(
+        schema::databases_in_tags::table.filter(
+            not(schema::databases_in_tags::database_id
+                .eq_any(schema::databases::table.select(schema::databases::id)))
+            .or(not(schema::databases_in_tags::tag_id
+                .eq_any(schema::tags::table.select(schema::tags::id)))),
+        ),
+    )
+    .execute(conn)?;
+    Ok(removed)
+}
+
+/// Objects which nobody has access to and which can't be reached through a parent
+fn find_orphans(
+    conn: &SqliteConnection,
+) -> Result<Vec<(ObjectType, String)>, diesel::result::Error> {
+    let accessible = schema::access::table.select(schema::access::object_id);
+    let mut orphans = Vec::new();
+    let typed = |object_type: ObjectType, ids: Vec<String>| {
+        ids.into_iter().map(move |id| (object_type, id))
+    };
+
+    orphans.extend(typed(
+        ObjectType::COURSE,
+        schema::courses::table
+            .filter(not(schema::courses::id.eq_any(accessible)))
+            .select(schema::courses::id)
+            .load::<String>(conn)?,
+    ));
+    orphans.extend(typed(
+        ObjectType::WORKSHEET,
+        schema::worksheets::table
+            .filter(not(schema::worksheets::id.eq_any(accessible)))
+            .filter(not(schema::worksheets::id.eq_any(
+                schema::worksheets_in_courses::table
+                    .select(schema::worksheets_in_courses::worksheet_id),
+            )))
+            .select(schema::worksheets::id)
+            .load::<String>(conn)?,
+    ));
+    orphans.extend(typed(
+        ObjectType::TASK,
+        schema::tasks::table
+            .filter(not(schema::tasks::id.eq_any(accessible)))
+            .filter(not(schema::tasks::id.eq_any(
+                schema::tasks_in_worksheets::table.select(schema::tasks_in_worksheets::task_id),
+            )))
+            .select(schema::tasks::id)
+            .load::<String>(conn)?,
+    ));
+    orphans.extend(typed(
+        ObjectType::SUBTASK,
+        schema::subtasks::table
+            .filter(not(schema::subtasks::id.eq_any(accessible)))
+            .filter(not(schema::subtasks::id.eq_any(
+                schema::subtasks_in_tasks::table.select(schema::subtasks_in_tasks::subtask_id),
+            )))
+            .select(schema::subtasks::id)
+            .load::<String>(conn)?,
+    ));
+    orphans.extend(typed(
+        ObjectType::DATABASE,
+        schema::databases::table
+            .filter(not(schema::databases::id.eq_any(accessible)))
+            .filter(not(schema::databases::id.eq_any(
+                schema::tasks::table.select(schema::tasks::database_id),
+            )))
+            .select(schema::databases::id)
+            .load::<String>(conn)?,
+    ));
+    Ok(orphans)
+}
+
+/// Matches ids which belong to no object, neither an existing nor a trashed one
+macro_rules! known_object {
+    ($column:expr) => {
+        not($column.eq_any(schema::courses::table.select(schema::courses::id)))
+            .and(not($column.eq_any(
+                schema::worksheets::table.select(schema::worksheets::id),
+            )))
+            .and(not(
+                $column.eq_any(schema::tasks::table.select(schema::tasks::id))
+            ))
+            .and(not($column.eq_any(
+                schema::subtasks::table.select(schema::subtasks::id),
+            )))
+            .and(not($column.eq_any(
+                schema::databases::table.select(schema::databases::id),
+            )))
+            .and(not($column.eq_any(
+                schema::trash::table.select(schema::trash::object_id),
+            )))
+    };
+}
+
+fn remove_dangling_access(conn: &SqliteConnection) -> Result<usize, diesel::result::Error> {
+    diesel::delete(
+        schema::access::table
+            .filter(known_object!(schema::access::object_id))
+            .filter(not(
+                schema::access::object_id.eq_any(schema::tags::table.select(schema::tags::id))
+            ))
+            .filter(not(schema::access::object_id.eq_any(
+                schema::catalog_entries::table.select(schema::catalog_entries::id),
+            ))),
+    )
+    .execute(conn)
+}
+
+fn remove_dangling_aliases(conn: &SqliteConnection) -> Result<Vec<String>, diesel::result::Error> {
+    let aliases = schema::aliases::table
+        .filter(known_object!(schema::aliases::object_id))
+        .select(schema::aliases::alias)
+        .load::<String>(conn)?;
+    diesel::delete(schema::aliases::table.filter(schema::aliases::alias.eq_any(&aliases)))
+        .execute(conn)?;
+    Ok(aliases)
+}
+
+fn remove_dangling_revisions(conn: &SqliteConnection) -> Result<usize, diesel::result::Error> {
+    let mut removed = diesel::delete(
+        schema::subtask_revisions::table
+            .filter(known_object!(schema::subtask_revisions::subtask_id)),
+    )
+    .execute(conn)?;
+    removed += diesel::delete(
+        schema::task_revisions::table.filter(known_object!(schema::task_revisions::task_id)),
+    )
+    .execute(conn)?;
+    removed += diesel::delete(
+        schema::worksheet_revisions::table
+            .filter(known_object!(schema::worksheet_revisions::worksheet_id)),
+    )
+    .execute(conn)?;
+    Ok(removed)
+}
+
+/// Blobs are removed by the triggers once their count drops to zero, but a blob that
+/// was stored and never referred to keeps a count of zero
+fn remove_unreferenced_blobs(conn: &SqliteConnection) -> Result<usize, diesel::result::Error> {
+    diesel::delete(
+        schema::blobs::table.filter(
+            schema::blobs::ref_count
+                .le(0)
+                .and(not(schema::blobs::hash.eq_any(
+                    schema::databases::table.select(schema::databases::content_hash),
+                )))
+                .and(not(schema::blobs::hash.nullable().eq_any(
+                    schema::trash::table
+                        .filter(schema::trash::content_hash.is_not_null())
+                        .select(schema::trash::content_hash),
+                ))),
+        ),
+    )
+    .execute(conn)
+}
+
+/// Entry point of the `gc` command
+pub fn run_gc(db_connection: &DatabaseConnectionConfig, dry_run: bool) {
+    let conn = match db_connection.create_sqlite_connection_pool().get() {
+        Ok(conn) => conn,
+        Err(e) => {
+            log::error!("Couldn't connect to database: {}", e);
+            return;
+        }
+    };
+    let report = match collect_garbage(&conn, dry_run) {
+        Ok(report) => report,
+        Err(e) => {
+            log::error!("Couldn't collect garbage: {}", e);
+            return;
+        }
+    };
+
+    let verb = if dry_run { "Would remove" } else { "Removed" };
+    println!("{} {} dangling links", verb, report.links);
+    println!("{} {} orphaned objects", verb, report.objects.len());
+    for (object_type, id) in report.objects.iter() {
+        println!("    {:?} {}", object_type, id);
+    }
+    println!("{} {} access rows of missing objects", verb, report.access);
+    println!(
+        "{} {} aliases of missing objects",
+        verb,
+        report.aliases.len()
+    );
+    for alias in report.aliases.iter() {
+        println!("    {}", alias);
+    }
+    println!("{} {} revisions of missing objects", verb, report.revisions);
+    println!("{} {} unreferenced dumps", verb, report.blobs);
+    if !report.broken_tasks.is_empty() {
+        println!(
+            "{} tasks refer to a missing database and have to be fixed manually:",
+            report.broken_tasks.len()
+        );
+        for id in report.broken_tasks.iter() {
+            println!("    {}", id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{seed, test_connection};
+    use diesel::connection::SimpleConnection;
+
+    fn links(conn: &SqliteConnection) -> Vec<String> {
+        schema::worksheets_in_courses::table
+            .select(schema::worksheets_in_courses::worksheet_id)
+            .order(schema::worksheets_in_courses::worksheet_id)
+            .load::<String>(conn)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_existing() {
+        let conn = test_connection();
+        seed(&conn);
+        let ids = ["b", "missing", "a"]
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            existing(&conn, ObjectType::WORKSHEET, &ids).unwrap(),
+            vec!["b", "a"]
+        );
+
+        let invalid = InvalidReferences::default()
+            .check(&conn, "user", ObjectType::WORKSHEET, &ids)
+            .unwrap();
+        assert_eq!(invalid.missing, vec!["missing"]);
+        assert_eq!(invalid.inaccessible, vec!["b"]);
+    }
+
+    #[test]
+    fn test_collect_garbage() {
+        let conn = test_connection();
+        seed(&conn);
+        // links to missing objects are only possible without foreign keys, e.g. from before
+        // they were turned on
+        conn.batch_execute(
+            "PRAGMA foreign_keys = OFF;
+             INSERT INTO worksheets_in_courses (worksheet_id, course_id, position)
+                 VALUES ('missing', 'course', 2);
+             PRAGMA foreign_keys = ON;",
+        )
+        .unwrap();
+
+        let report = collect_garbage(&conn, true).unwrap();
+        assert_eq!(report.links, 1);
+        assert_eq!(
+            report
+                .objects
+                .iter()
+                .map(|(_, id)| id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["orphan"]
+        );
+        // a dry run changes nothing
+        assert_eq!(links(&conn), vec!["a", "b", "c", "d", "missing"]);
+
+        let report = collect_garbage(&conn, false).unwrap();
+        assert_eq!(report.links, 1);
+        assert_eq!(links(&conn), vec!["a", "b", "c", "d"]);
+        // reachable through the course, although nobody has access to it directly
+        assert_eq!(
+            existing(
+                &conn,
+                ObjectType::WORKSHEET,
+                &["b".to_string(), "orphan".to_string()]
+            )
+            .unwrap(),
+            vec!["b"]
+        );
+
+        let report = collect_garbage(&conn, false).unwrap();
+        assert_eq!(report.links, 0);
+        assert!(report.objects.is_empty());
+        assert_eq!(report.blobs, 0);
+    }
+
+    #[test]
+    fn test_collect_unreferenced_blobs() {
+        let conn = test_connection();
+        seed(&conn);
+        crate::blobs::store(&conn, "CREATE TABLE unused (a);").unwrap();
+
+        let report = collect_garbage(&conn, false).unwrap();
+        assert_eq!(report.blobs, 1);
+        let hashes = schema::blobs::table
+            .select(schema::blobs::hash)
+            .load::<String>(&conn)
+            .unwrap();
+        assert_eq!(hashes, vec!["hash"]);
+    }
+}
@@ -0,0 +1,51 @@
+use crate::models;
+use crate::schema;
+use chrono::Utc;
+use diesel::prelude::*;
+use rand::RngCore;
+
+/// Creates a random invitation code
+pub fn mint(
+    conn: &SqliteConnection,
+    created_by: &str,
+    request: models::InvitationRequest,
+) -> Result<models::Invitation, diesel::result::Error> {
+    let mut bytes = [0u8; 12];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let invitation = models::Invitation {
+        code: base64::encode_config(&bytes, base64::URL_SAFE_NO_PAD),
+        created_by: created_by.to_owned(),
+        created_at: Utc::now().naive_utc(),
+        expires_at: request.expires_at,
+        max_uses: request.max_uses.unwrap_or(1),
+        uses: 0,
+    };
+    diesel::insert_into(schema::invitations::table)
+        .values((
+            schema::invitations::code.eq(&invitation.code),
+            schema::invitations::created_by.eq(&invitation.created_by),
+            schema::invitations::created_at.eq(invitation.created_at),
+            schema::invitations::expires_at.eq(invitation.expires_at),
+            schema::invitations::max_uses.eq(invitation.max_uses),
+        ))
+        .execute(conn)?;
+    Ok(invitation)
+}
+
+/// Uses up one use of the code, returns false if it's unknown, expired or used up
+pub fn redeem(conn: &SqliteConnection, code: &str) -> Result<bool, diesel::result::Error> {
+    // a single statement, so concurrent registrations can't exceed the count
+    Ok(diesel::update(
+        schema::invitations::table
+            .find(code)
+            .filter(schema::invitations::uses.lt(schema::invitations::max_uses))
+            .filter(
+                schema::invitations::expires_at
+                    .is_null()
+                    .or(schema::invitations::expires_at.gt(Utc::now().naive_utc())),
+            ),
+    )
+    .set(schema::invitations::uses.eq(schema::invitations::uses + 1))
+    .execute(conn)?
+        > 0)
+}
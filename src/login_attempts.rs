@@ -0,0 +1,251 @@
+use actix_web::{http::header::RETRY_AFTER, HttpResponse, ResponseError};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Limits of failed logins, kept for each username and each client address. After the
+/// backoff limit every failure doubles the wait for the next attempt, starting at a second.
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct LoginThrottling {
+    pub(crate) user_backoff_after: u32,
+    pub(crate) user_lockout_after: u32,
+    /// Higher than for usernames, since many users can share an address
+    pub(crate) ip_backoff_after: u32,
+    pub(crate) ip_lockout_after: u32,
+    /// How long lockouts last, failures are forgotten after this long without another one
+    pub(crate) lockout_seconds: u64,
+}
+
+impl Default for LoginThrottling {
+    fn default() -> LoginThrottling {
+        LoginThrottling {
+            user_backoff_after: 3,
+            user_lockout_after: 10,
+            ip_backoff_after: 10,
+            ip_lockout_after: 50,
+            lockout_seconds: 900,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    User(String),
+    Ip(IpAddr),
+}
+
+impl Key {
+    fn ip(ip: IpAddr) -> Key {
+        match ip {
+            // clients usually get a whole /64, so its addresses count as one
+            IpAddr::V6(ip) => {
+                let mut segments = ip.segments();
+                segments[4..].iter_mut().for_each(|segment| *segment = 0);
+                Key::Ip(IpAddr::from(segments))
+            }
+            ip => Key::Ip(ip),
+        }
+    }
+}
+
+struct Failures {
+    count: u32,
+    last: Instant,
+}
+
+/// Failed logins of this process, shared by the workers
+pub struct LoginAttempts {
+    throttling: LoginThrottling,
+    failures: Mutex<HashMap<Key, Failures>>,
+}
+
+impl LoginAttempts {
+    pub fn new(throttling: LoginThrottling) -> Self {
+        LoginAttempts {
+            throttling,
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// How long to wait until the username may be tried again from the address, if at all
+    pub fn retry_after(&self, username: &str, ip: Option<IpAddr>) -> Option<Duration> {
+        self.retry_after_at(username, ip, Instant::now())
+    }
+
+    pub fn record_failure(&self, username: &str, ip: Option<IpAddr>) {
+        self.record_failure_at(username, ip, Instant::now())
+    }
+
+    /// Forgets the failures of the username, but not of the address, so logging into an own
+    /// account in between doesn't allow more guesses for others
+    pub fn record_success(&self, username: &str) {
+        self.failures
+            .lock()
+            .unwrap()
+            .remove(&Key::User(username.to_owned()));
+    }
+
+    fn keys(username: &str, ip: Option<IpAddr>) -> Vec<Key> {
+        let mut keys = vec![Key::User(username.to_owned())];
+        keys.extend(ip.map(Key::ip));
+        keys
+    }
+
+    fn lockout(&self) -> Duration {
+        Duration::from_secs(self.throttling.lockout_seconds)
+    }
+
+    fn blocked_until(&self, key: &Key, failures: &Failures) -> Option<Instant> {
+        let (backoff_after, lockout_after) = match key {
+            Key::User(_) => (
+                self.throttling.user_backoff_after,
+                self.throttling.user_lockout_after,
+            ),
+            Key::Ip(_) => (
+                self.throttling.ip_backoff_after,
+                self.throttling.ip_lockout_after,
+            ),
+        };
+        if failures.count >= lockout_after {
+            Some(failures.last + self.lockout())
+        } else if failures.count >= backoff_after {
+            let delay = 2u64.saturating_pow(failures.count - backoff_after);
+            Some(failures.last + Duration::from_secs(delay).min(self.lockout()))
+        } else {
+            None
+        }
+    }
+
+    fn retry_after_at(&self, username: &str, ip: Option<IpAddr>, now: Instant) -> Option<Duration> {
+        let failures = self.failures.lock().unwrap();
+        Self::keys(username, ip)
+            .iter()
+            .filter_map(|key| {
+                let until = self.blocked_until(key, failures.get(key)?)?;
+                until
+                    .checked_duration_since(now)
+                    .filter(|wait| *wait > Duration::ZERO)
+            })
+            .max()
+    }
+
+    fn record_failure_at(&self, username: &str, ip: Option<IpAddr>, now: Instant) {
+        let lockout = self.lockout();
+        let mut failures = self.failures.lock().unwrap();
+        // only pruned now and then, so the map stays small without scanning it every time
+        if failures.len() >= 1024 {
+            failures.retain(|_, failures| now.duration_since(failures.last) < lockout);
+        }
+        for key in Self::keys(username, ip) {
+            let entry = failures.entry(key).or_insert(Failures {
+                count: 0,
+                last: now,
+            });
+            if now.duration_since(entry.last) >= lockout {
+                entry.count = 0;
+            }
+            entry.count += 1;
+            entry.last = now;
+        }
+    }
+}
+
+/// Rejects a login until the duration has passed
+#[derive(Debug)]
+pub struct TooManyAttempts(pub Duration);
+
+impl fmt::Display for TooManyAttempts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Too many failed logins, try again in {} seconds",
+            self.seconds()
+        )
+    }
+}
+
+impl TooManyAttempts {
+    fn seconds(&self) -> u64 {
+        // rounded up, so clients waiting as told aren't rejected again
+        self.0.as_secs() + u64::from(self.0.subsec_nanos() > 0)
+    }
+}
+
+impl ResponseError for TooManyAttempts {
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::TooManyRequests()
+            .header(RETRY_AFTER, self.seconds().to_string())
+            .body(self.to_string())
+    }
+
+    fn render_response(&self) -> HttpResponse {
+        self.error_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LoginAttempts, LoginThrottling};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn backoff_and_lockout() {
+        let attempts = LoginAttempts::new(LoginThrottling {
+            user_backoff_after: 2,
+            user_lockout_after: 4,
+            ip_backoff_after: 10,
+            ip_lockout_after: 20,
+            lockout_seconds: 60,
+        });
+        let ip = Some("1.2.3.4".parse().unwrap());
+        let start = Instant::now();
+        let at = |seconds| start + Duration::from_secs(seconds);
+
+        attempts.record_failure_at("alice", ip, at(0));
+        assert_eq!(attempts.retry_after_at("alice", ip, at(0)), None);
+        attempts.record_failure_at("alice", ip, at(0));
+        assert_eq!(
+            attempts.retry_after_at("alice", ip, at(0)),
+            Some(Duration::from_secs(1))
+        );
+        attempts.record_failure_at("alice", ip, at(1));
+        assert_eq!(
+            attempts.retry_after_at("alice", ip, at(1)),
+            Some(Duration::from_secs(2))
+        );
+        attempts.record_failure_at("alice", ip, at(3));
+        assert_eq!(
+            attempts.retry_after_at("alice", ip, at(3)),
+            Some(Duration::from_secs(60))
+        );
+        // other usernames are only limited by the address
+        assert_eq!(attempts.retry_after_at("bob", ip, at(3)), None);
+        assert_eq!(attempts.retry_after_at("alice", ip, at(63)), None);
+
+        // failures are forgotten after the lockout time
+        attempts.record_failure_at("alice", ip, at(63));
+        assert_eq!(attempts.retry_after_at("alice", ip, at(63)), None);
+        attempts.record_success("alice");
+        attempts.record_failure_at("alice", ip, at(64));
+        assert_eq!(attempts.retry_after_at("alice", ip, at(64)), None);
+    }
+
+    #[test]
+    fn ipv6_networks_count_as_one() {
+        let attempts = LoginAttempts::new(LoginThrottling {
+            ip_backoff_after: 1,
+            ..LoginThrottling::default()
+        });
+        attempts.record_failure("alice", Some("2001:db8::1".parse().unwrap()));
+        assert!(attempts
+            .retry_after("bob", Some("2001:db8::2".parse().unwrap()))
+            .is_some());
+        assert!(attempts
+            .retry_after("bob", Some("2001:db8:0:1::2".parse().unwrap()))
+            .is_none());
+    }
+}
@@ -1,30 +1,58 @@
 #![warn(unused_extern_crates)]
 use actix_cors::Cors;
-use actix_web::{http::Method, web, App, HttpServer};
+use actix_web::{
+    http::{header, Method},
+    web, App, HttpServer,
+};
 use log::error;
-use regex::Regex;
+use std::{rc::Rc, sync::Arc};
 
-use actix_web_jwt_middleware::{Algorithm, JwtAuthentication, JwtKey};
+use actix_web_jwt_middleware::{JwtAuthentication, JwtKeys, Policy};
 
 pub use upowdb_models::{models, schema};
 
+mod admins;
 mod alias_generator;
+mod blobs;
 mod cli;
+mod client_ip;
+mod cloning;
 mod database;
+mod diagram;
+mod dump;
 mod handlers;
+mod integrity;
+mod invitations;
 mod logging;
+mod login_attempts;
 mod middlewares;
+mod ordering;
+mod passwords;
+mod patch;
+mod quota;
+mod revisions;
+mod sample_data;
 mod settings;
 mod solution_compare;
+mod streaming;
+mod tokens;
+mod trash;
 
 #[derive(Clone)]
 struct AppData {
     settings: settings::Settings,
+    jwt_keys: Arc<JwtKeys>,
+    login_attempts: Arc<login_attempts::LoginAttempts>,
 }
 
 impl AppData {
-    pub fn from_configuration(config: settings::Settings) -> Self {
-        Self { settings: config }
+    pub fn from_configuration(config: settings::Settings, jwt_keys: Arc<JwtKeys>) -> Self {
+        let throttling = config.login_throttling.unwrap_or_default();
+        Self {
+            settings: config,
+            jwt_keys,
+            login_attempts: Arc::new(login_attempts::LoginAttempts::new(throttling)),
+        }
     }
 }
 
@@ -39,49 +67,80 @@ fn main() {
     });
     let configuration =
         settings::Settings::new(cli_matches.value_of("config").unwrap_or("config.toml")).unwrap();
+
+    if let Some(gc_matches) = cli_matches.subcommand_matches("gc") {
+        integrity::run_gc(
+            &configuration.db_connection,
+            gc_matches.is_present("dry_run"),
+        );
+        return;
+    }
+
+    if let Some(admin_matches) = cli_matches.subcommand_matches("admin") {
+        admins::run_admin(
+            &configuration.db_connection,
+            admin_matches.value_of("username").unwrap(),
+            !admin_matches.is_present("revoke"),
+        );
+        return;
+    }
+
+    match configuration
+        .db_connection
+        .create_sqlite_connection_pool()
+        .get()
+        .map_err(|e| e.to_string())
+        .and_then(|conn| blobs::repack_legacy(&conn).map_err(|e| e.to_string()))
+    {
+        Ok(0) => {}
+        Ok(count) => log::info!("Moved {} database dumps into compressed blobs", count),
+        Err(e) => error!("Couldn't repack database dumps: {}", e),
+    }
+
     let sys = actix::System::new("udb-backend");
 
-    let appstate = AppData::from_configuration(configuration.clone());
+    // tokens signed with an empty secret could be forged by anyone
+    let jwt_keys = match configuration.jwt_keys() {
+        Ok(keys) => Arc::new(keys),
+        Err(e) => {
+            error!("Couldn't load JWT keys: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = configuration.password_hashing().validate() {
+        error!("Invalid password_hashing: {}", e);
+        std::process::exit(1);
+    }
+    let appstate = AppData::from_configuration(configuration.clone(), jwt_keys.clone());
+
+    let trash_retention_days = configuration.trash_retention_days.unwrap_or(30);
+    if trash_retention_days > 0 {
+        trash::spawn_purge_thread(
+            configuration.db_connection.create_sqlite_connection_pool(),
+            trash_retention_days,
+        );
+    }
 
-    let jwt_key = configuration.jwt_key.clone();
+    let jwt_validation = configuration.jwt_validation();
+    let token_source = configuration.token_source();
     let mut server = HttpServer::new(move || {
         App::new()
             .data(appstate.clone())
             .wrap(middlewares::upload_filter::UploadFilter { filter: false })
-            .wrap(middlewares::ownership::OwnershipChecker{})
+            .wrap(middlewares::conditional::ConditionalRequests {})
+            .wrap(middlewares::ownership::OwnershipChecker {})
             .wrap(JwtAuthentication {
-                key: JwtKey::Inline(jwt_key.clone()),
-                algorithm: Algorithm::HS512,
-                except: vec![(
-                    Regex::new(
-                        r"(?P<uuid>[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12})$",
-                    )
-                    .unwrap(),
-                    vec![Method::GET],
-                ),(
-                    Regex::new(
-                        r"/subtasks/(?P<uuid>[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12})/verify$",
-                    )
-                    .unwrap(),
-                    vec![Method::POST],
-                ),(
-                    #[allow(clippy::trivial_regex)]
-                    Regex::new(
-                        r"/account",
-                    )
-                    .unwrap(),
-                    vec![Method::GET, Method::POST, Method::PUT, Method::DELETE],
-                ),(
-                    #[allow(clippy::trivial_regex)]
-                    Regex::new(
-                        r"/alias",
-                    )
-                    .unwrap(),
-                    vec![Method::GET],
-                )],
+                keys: jwt_keys.clone(),
+                validation: jwt_validation.clone(),
+                revocation: Some(Rc::new(tokens::RevocationList)),
+                source: token_source.clone(),
             })
             .wrap(middlewares::db_connection::DatabaseConnection {
-                pool: appstate.clone().settings.db_connection.create_sqlite_connection_pool(),
+                pool: appstate
+                    .clone()
+                    .settings
+                    .db_connection
+                    .create_sqlite_connection_pool(),
             })
             .wrap({
                 let cors = Cors::new();
@@ -90,24 +149,49 @@ fn main() {
                 } else {
                     cors
                 };
-                cors
-                    .allowed_methods(&[Method::GET, Method::POST, Method::PUT, Method::DELETE])
-                    .supports_credentials()
-                    .max_age(3600)
+                cors.allowed_methods(&[
+                    Method::GET,
+                    Method::POST,
+                    Method::PUT,
+                    Method::PATCH,
+                    Method::DELETE,
+                ])
+                .expose_headers(vec![header::ETAG, header::WWW_AUTHENTICATE])
+                .supports_credentials()
+                .max_age(3600)
             })
             .wrap(Cors::default())
+            .wrap(actix_web::middleware::Compress::default())
             .wrap(actix_web::middleware::Logger::default())
             .wrap(actix_web_prom::PrometheusMetrics::new("api", "/metrics"))
-            .service(web::resource("/health").to(|| actix_web::HttpResponse::Ok().finish()))
+            .service(
+                web::resource("/health")
+                    .wrap(Policy::anonymous())
+                    .to(|| actix_web::HttpResponse::Ok().finish()),
+            )
+            .service(
+                web::resource("/.well-known/jwks.json")
+                    .wrap(Policy::anonymous())
+                    .route(web::get().to(handlers::keys::get_jwks)),
+            )
             .service(
                 web::scope("/api/v1")
                     .service(handlers::account::get_scope())
+                    .service(
+                        handlers::admin::get_scope()
+                            .wrap(Policy::required().scope(admins::ADMIN_SCOPE)),
+                    )
                     .service(handlers::courses::get_scope())
                     .service(handlers::databases::get_scope())
                     .service(handlers::worksheets::get_scope())
                     .service(handlers::tasks::get_scope())
                     .service(handlers::subtasks::get_scope())
-                    .service(handlers::alias::get_scope()),
+                    .service(handlers::tags::get_scope().wrap(Policy::required()))
+                    .service(handlers::alias::get_scope())
+                    .service(handlers::catalog::get_scope().wrap(Policy::required()))
+                    .service(handlers::invitations::get_scope().wrap(Policy::required()))
+                    .service(handlers::quotas::get_scope().wrap(Policy::required()))
+                    .service(handlers::trash::get_scope().wrap(Policy::required())),
             )
     });
     for addr in configuration.listen_addr {
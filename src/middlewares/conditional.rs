@@ -0,0 +1,230 @@
+use crate::schema;
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::{
+        header::{HeaderValue, ETAG, IF_MATCH, IF_NONE_MATCH},
+        Method,
+    },
+    Error, HttpMessage, HttpRequest, HttpResponse,
+};
+use diesel::{
+    r2d2::{self, ConnectionManager},
+    OptionalExtension, QueryDsl, RunQueryDsl, SqliteConnection,
+};
+use futures::{
+    future::{ok, FutureResult},
+    Future, Poll,
+};
+use regex::Regex;
+
+/// Sends the version of an object as `ETag` and answers `If-None-Match` with 304. The
+/// `If-Match` header of changes is left to the handlers, which compare it with
+/// `check_if_match` inside their transaction, so concurrent changes can't both pass.
+pub struct ConditionalRequests {}
+
+/// The object has changed since the version in `If-Match`
+#[derive(Debug)]
+pub struct PreconditionFailed;
+
+/// Fails if the request has an `If-Match` header not matching the current version of the
+/// object. Has to be called in an immediate transaction before the object is changed, so
+/// no other change can happen in between.
+pub fn check_if_match<E>(
+    req: &HttpRequest,
+    conn: &SqliteConnection,
+    collection: &str,
+    id: &str,
+) -> Result<(), E>
+where
+    E: From<diesel::result::Error> + From<PreconditionFailed>,
+{
+    let header = match req.headers().get(IF_MATCH) {
+        Some(header) => header,
+        None => return Ok(()),
+    };
+    // an object which doesn't exist can't match either
+    match current_version(conn, collection, id)? {
+        Some(version) if matches(header, version, false) => Ok(()),
+        _ => Err(PreconditionFailed.into()),
+    }
+}
+
+impl<S, B> Transform<S> for ConditionalRequests
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = ConditionalRequestsMiddleware<S>;
+    type Future = FutureResult<Self::Transform, Self::InitError>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(ConditionalRequestsMiddleware { service })
+    }
+}
+
+pub struct ConditionalRequestsMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service for ConditionalRequestsMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = Box<dyn Future<Item = Self::Response, Error = Self::Error>>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.service.poll_ready()
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        lazy_static::lazy_static! {
+            static ref RE: Regex = Regex::new(r"^/api/v1/(?P<collection>courses|databases|subtasks|tasks|worksheets)/(?P<uuid>[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12})$").unwrap();
+        }
+        let (collection, id) = match RE.captures(req.path()) {
+            Some(captures) => (
+                captures["collection"].to_string(),
+                captures["uuid"].to_string(),
+            ),
+            None => return Box::new(self.service.call(req)),
+        };
+        let method = req.method().clone();
+        if !["GET", "HEAD", "PUT", "PATCH", "DELETE"].contains(&method.as_str()) {
+            return Box::new(self.service.call(req));
+        }
+
+        let read = method == Method::GET || method == Method::HEAD;
+        // changes look up the version in their transaction, it's only needed afterwards
+        let version = if read {
+            let extensions = req.extensions();
+            match extensions.get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>() {
+                Some(conn) => current_version(conn, &collection, &id),
+                None => Ok(None),
+            }
+        } else {
+            Ok(None)
+        };
+        let version = match version {
+            Ok(version) => version,
+            Err(e) => {
+                log::error!("Couldn't query object version: {}", e);
+                return Box::new(ok(req.into_response(
+                    HttpResponse::InternalServerError().finish().into_body(),
+                )));
+            }
+        };
+
+        if read {
+            let not_modified = match (version, req.headers().get(IF_NONE_MATCH)) {
+                (Some(version), Some(header)) => matches(header, version, true),
+                _ => false,
+            };
+            if not_modified {
+                return Box::new(ok(req.into_response(
+                    HttpResponse::NotModified()
+                        .header(ETAG, etag(version.unwrap()))
+                        .finish()
+                        .into_body(),
+                )));
+            }
+        }
+
+        Box::new(self.service.call(req).map(move |mut res| {
+            if !res.status().is_success() || method == Method::DELETE {
+                return res;
+            }
+            let version = if read {
+                version
+            } else {
+                // the object has just been changed
+                let extensions = res.request().extensions();
+                extensions
+                    .get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>()
+                    .and_then(|conn| current_version(conn, &collection, &id).ok())
+                    .and_then(|version| version)
+            };
+            if let Some(version) = version {
+                if let Ok(value) = HeaderValue::from_str(&etag(version)) {
+                    res.headers_mut().insert(ETAG, value);
+                }
+            }
+            res
+        }))
+    }
+}
+
+/// The version of an object, `None` if it doesn't exist
+pub fn current_version(
+    conn: &SqliteConnection,
+    collection: &str,
+    id: &str,
+) -> Result<Option<i32>, diesel::result::Error> {
+    match collection {
+        "courses" => schema::courses::table
+            .find(id)
+            .select(schema::courses::version)
+            .get_result::<i32>(conn)
+            .optional(),
+        "databases" => schema::databases::table
+            .find(id)
+            .select(schema::databases::version)
+            .get_result::<i32>(conn)
+            .optional(),
+        "subtasks" => schema::subtasks::table
+            .find(id)
+            .select(schema::subtasks::version)
+            .get_result::<i32>(conn)
+            .optional(),
+        "tasks" => schema::tasks::table
+            .find(id)
+            .select(schema::tasks::version)
+            .get_result::<i32>(conn)
+            .optional(),
+        "worksheets" => schema::worksheets::table
+            .find(id)
+            .select(schema::worksheets::version)
+            .get_result::<i32>(conn)
+            .optional(),
+        _ => Ok(None),
+    }
+}
+
+pub fn etag(version: i32) -> String {
+    format!("\"{}\"", version)
+}
+
+/// Checks an `If-Match` or `If-None-Match` header against the version of an object.
+/// Weak tags only count for `If-None-Match`, where `weak` is set.
+fn matches(header: &HeaderValue, version: i32, weak: bool) -> bool {
+    let etag = etag(version);
+    match header.to_str() {
+        Ok(header) => header.split(',').map(str::trim).any(|tag| {
+            tag == "*" || tag == etag || (weak && tag.starts_with("W/") && tag[2..] == etag)
+        }),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        let header = HeaderValue::from_static("\"3\", W/\"4\"");
+        assert!(matches(&header, 3, false));
+        assert!(!matches(&header, 4, false));
+        assert!(matches(&header, 4, true));
+        assert!(!matches(&header, 5, true));
+        assert!(matches(&HeaderValue::from_static("*"), 1, false));
+    }
+}
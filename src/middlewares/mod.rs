@@ -1,3 +1,4 @@
+pub mod conditional;
 pub mod db_connection;
 pub mod ownership;
 pub mod upload_filter;
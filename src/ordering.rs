@@ -0,0 +1,311 @@
+use crate::models::ObjectType;
+use crate::revisions::{TaskRevisions, WorksheetRevisions};
+use crate::schema;
+use diesel::{prelude::*, SqliteConnection};
+
+/// Access to a link table holding the ordered children of an object type
+pub trait ChildLinks {
+    const CHILD: ObjectType;
+    /// Collection of the parents, whose version changes with their children
+    const PARENTS: &'static str;
+
+    fn children(
+        conn: &SqliteConnection,
+        parent: &str,
+    ) -> Result<Vec<String>, diesel::result::Error>;
+    fn position(
+        conn: &SqliteConnection,
+        parent: &str,
+        child: &str,
+    ) -> Result<Option<i32>, diesel::result::Error>;
+    fn insert(
+        conn: &SqliteConnection,
+        parent: &str,
+        child: &str,
+        position: i32,
+    ) -> Result<(), diesel::result::Error>;
+    fn set_position(
+        conn: &SqliteConnection,
+        parent: &str,
+        child: &str,
+        position: i32,
+    ) -> Result<(), diesel::result::Error>;
+    fn delete(
+        conn: &SqliteConnection,
+        parent: &str,
+        child: &str,
+    ) -> Result<(), diesel::result::Error>;
+    /// Moves all children with a position of at least `from` (and at most `to`) by `by`
+    fn shift(
+        conn: &SqliteConnection,
+        parent: &str,
+        from: i32,
+        to: Option<i32>,
+        by: i32,
+    ) -> Result<(), diesel::result::Error>;
+
+    /// Called before and after changing the children, to keep the parent's history
+    fn before_change(conn: &SqliteConnection, parent: &str) -> Result<(), diesel::result::Error>;
+    fn after_change(
+        conn: &SqliteConnection,
+        parent: &str,
+        user_id: &str,
+    ) -> Result<(), diesel::result::Error>;
+}
+
+// Like the revision tables, every link table needs its own diesel queries.
+// Positions are nullable in some of the link tables, hence `$position`.
+macro_rules! child_links {
+    ($links:ident, $parents:literal, $table:ident, $parent_id:ident, $child_id:ident,
+     $child:ident, $position:ty $(, $revisions:ty)?) => {
+        pub struct $links;
+
+        impl ChildLinks for $links {
+            const CHILD: ObjectType = ObjectType::$child;
+            const PARENTS: &'static str = $parents;
+
+            fn children(
+                conn: &SqliteConnection,
+                parent: &str,
+            ) -> Result<Vec<String>, diesel::result::Error> {
+                schema::$table::table
+                    .filter(schema::$table::$parent_id.eq(parent))
+                    .select(schema::$table::$child_id)
+                    .order(schema::$table::position)
+                    .load::<String>(conn)
+            }
+
+            fn position(
+                conn: &SqliteConnection,
+                parent: &str,
+                child: &str,
+            ) -> Result<Option<i32>, diesel::result::Error> {
+                let position = schema::$table::table
+                    .filter(schema::$table::$parent_id.eq(parent))
+                    .filter(schema::$table::$child_id.eq(child))
+                    .select(schema::$table::position)
+                    .get_result::<$position>(conn)?;
+                Ok(position.into())
+            }
+
+            fn insert(
+                conn: &SqliteConnection,
+                parent: &str,
+                child: &str,
+                position: i32,
+            ) -> Result<(), diesel::result::Error> {
+                diesel::insert_into(schema::$table::table)
+                    .values((
+                        schema::$table::$parent_id.eq(parent),
+                        schema::$table::$child_id.eq(child),
+                        schema::$table::position.eq(position),
+                    ))
+                    .execute(conn)?;
+                Ok(())
+            }
+
+            fn set_position(
+                conn: &SqliteConnection,
+                parent: &str,
+                child: &str,
+                position: i32,
+            ) -> Result<(), diesel::result::Error> {
+                diesel::update(
+                    schema::$table::table
+                        .filter(schema::$table::$parent_id.eq(parent))
+                        .filter(schema::$table::$child_id.eq(child)),
+                )
+                .set(schema::$table::position.eq(position))
+                .execute(conn)?;
+                Ok(())
+            }
+
+            fn delete(
+                conn: &SqliteConnection,
+                parent: &str,
+                child: &str,
+            ) -> Result<(), diesel::result::Error> {
+                diesel::delete(
+                    schema::$table::table
+                        .filter(schema::$table::$parent_id.eq(parent))
+                        .filter(schema::$table::$child_id.eq(child)),
+                )
+                .execute(conn)?;
+                Ok(())
+            }
+
+            fn shift(
+                conn: &SqliteConnection,
+                parent: &str,
+                from: i32,
+                to: Option<i32>,
+                by: i32,
+            ) -> Result<(), diesel::result::Error> {
+                let mut query = diesel::update(schema::$table::table)
+                    .filter(schema::$table::$parent_id.eq(parent))
+                    .filter(schema::$table::position.ge(from))
+                    .into_boxed();
+                if let Some(to) = to {
+                    query = query.filter(schema::$table::position.le(to));
+                }
+                query
+                    .set(schema::$table::position.eq(schema::$table::position + by))
+                    .execute(conn)?;
+                Ok(())
+            }
+
+            #[allow(unused_variables)]
+            fn before_change(
+                conn: &SqliteConnection,
+                parent: &str,
+            ) -> Result<(), diesel::result::Error> {
+                $(crate::revisions::ensure_baseline::<$revisions>(conn, parent)?;)?
+                Ok(())
+            }
+
+            #[allow(unused_variables)]
+            fn after_change(
+                conn: &SqliteConnection,
+                parent: &str,
+                user_id: &str,
+            ) -> Result<(), diesel::result::Error> {
+                $(crate::revisions::record::<$revisions>(conn, parent, user_id, None)?;)?
+                Ok(())
+            }
+        }
+    };
+}
+
+child_links!(
+    CourseWorksheets,
+    "courses",
+    worksheets_in_courses,
+    course_id,
+    worksheet_id,
+    WORKSHEET,
+    Option<i32>
+);
+child_links!(
+    WorksheetTasks,
+    "worksheets",
+    tasks_in_worksheets,
+    worksheet_id,
+    task_id,
+    TASK,
+    Option<i32>,
+    WorksheetRevisions
+);
+child_links!(
+    TaskSubtasks,
+    "tasks",
+    subtasks_in_tasks,
+    task_id,
+    subtask_id,
+    SUBTASK,
+    i32,
+    TaskRevisions
+);
+
+/// Adds a child at the given position, or at the end. Only the children behind it move.
+pub fn insert<L: ChildLinks>(
+    conn: &SqliteConnection,
+    parent: &str,
+    child: &str,
+    position: Option<u32>,
+) -> Result<(), diesel::result::Error> {
+    let count = L::children(conn, parent)?.len() as i32;
+    let position = position.map_or(count, |position| count.min(position as i32));
+    L::shift(conn, parent, position, None, 1)?;
+    L::insert(conn, parent, child, position)
+}
+
+/// Moves a child to another position, only the children in between move as well
+pub fn move_child<L: ChildLinks>(
+    conn: &SqliteConnection,
+    parent: &str,
+    child: &str,
+    position: u32,
+) -> Result<(), diesel::result::Error> {
+    let last = L::children(conn, parent)?.len() as i32 - 1;
+    let from = L::position(conn, parent, child)?.unwrap_or(last);
+    let to = last.min(position as i32);
+    if to > from {
+        L::shift(conn, parent, from + 1, Some(to), -1)?;
+    } else if to < from {
+        L::shift(conn, parent, to, Some(from - 1), 1)?;
+    }
+    L::set_position(conn, parent, child, to)
+}
+
+/// Removes a child, the children behind it close the gap
+pub fn remove<L: ChildLinks>(
+    conn: &SqliteConnection,
+    parent: &str,
+    child: &str,
+) -> Result<(), diesel::result::Error> {
+    let position = L::position(conn, parent, child)?;
+    L::delete(conn, parent, child)?;
+    if let Some(position) = position {
+        L::shift(conn, parent, position + 1, None, -1)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{seed, test_connection};
+    use diesel::connection::SimpleConnection;
+
+    /// The children with their positions, which have to be 0, 1, 2, ...
+    fn children(conn: &SqliteConnection) -> Vec<String> {
+        let children = CourseWorksheets::children(conn, "course").unwrap();
+        for (position, child) in children.iter().enumerate() {
+            assert_eq!(
+                CourseWorksheets::position(conn, "course", child).unwrap(),
+                Some(position as i32)
+            );
+        }
+        children
+    }
+
+    #[test]
+    fn test_insert() {
+        let conn = test_connection();
+        seed(&conn);
+        assert_eq!(children(&conn), vec!["a", "b", "c", "d"]);
+
+        conn.batch_execute("INSERT INTO worksheets (id) VALUES ('e'), ('f');")
+            .unwrap();
+        insert::<CourseWorksheets>(&conn, "course", "e", Some(1)).unwrap();
+        // positions behind the end append
+        insert::<CourseWorksheets>(&conn, "course", "f", Some(99)).unwrap();
+        assert_eq!(children(&conn), vec!["a", "e", "b", "c", "d", "f"]);
+    }
+
+    #[test]
+    fn test_move_child() {
+        let conn = test_connection();
+        seed(&conn);
+
+        move_child::<CourseWorksheets>(&conn, "course", "d", 1).unwrap();
+        assert_eq!(children(&conn), vec!["a", "d", "b", "c"]);
+        move_child::<CourseWorksheets>(&conn, "course", "a", 2).unwrap();
+        assert_eq!(children(&conn), vec!["d", "b", "a", "c"]);
+        move_child::<CourseWorksheets>(&conn, "course", "b", 99).unwrap();
+        assert_eq!(children(&conn), vec!["d", "a", "c", "b"]);
+        move_child::<CourseWorksheets>(&conn, "course", "c", 2).unwrap();
+        assert_eq!(children(&conn), vec!["d", "a", "c", "b"]);
+    }
+
+    #[test]
+    fn test_remove() {
+        let conn = test_connection();
+        seed(&conn);
+
+        remove::<CourseWorksheets>(&conn, "course", "b").unwrap();
+        assert_eq!(children(&conn), vec!["a", "c", "d"]);
+        remove::<CourseWorksheets>(&conn, "course", "d").unwrap();
+        assert_eq!(children(&conn), vec!["a", "c"]);
+    }
+}
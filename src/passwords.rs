@@ -0,0 +1,69 @@
+use serde::Deserialize;
+
+/// What new passwords have to look like, checked when registering and changing passwords
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct PasswordPolicy {
+    /// Characters, not bytes
+    pub(crate) min_length: usize,
+    /// How many of lowercase letters, uppercase letters, digits and other characters are used
+    pub(crate) min_classes: usize,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: 8,
+            min_classes: 1,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// Returns why the password isn't allowed, in a form that can be shown to the user
+    pub fn check(&self, username: &str, password: &str) -> Result<(), String> {
+        if password.chars().count() < self.min_length {
+            return Err(format!(
+                "Passwords need at least {} characters",
+                self.min_length
+            ));
+        }
+        let classes = [
+            password.chars().any(char::is_lowercase),
+            password.chars().any(char::is_uppercase),
+            password.chars().any(|c| c.is_ascii_digit()),
+            password.chars().any(|c| !c.is_alphanumeric()),
+        ];
+        if classes.iter().filter(|used| **used).count() < self.min_classes {
+            return Err(format!(
+                "Passwords need at least {} of lowercase letters, uppercase letters, digits and other characters",
+                self.min_classes
+            ));
+        }
+        if password.eq_ignore_ascii_case(username) {
+            return Err("The password can't be the username".to_owned());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PasswordPolicy;
+
+    #[test]
+    fn check_length_and_classes() {
+        let policy = PasswordPolicy {
+            min_length: 8,
+            min_classes: 3,
+        };
+        assert!(policy.check("alice", "Short1!").is_err());
+        assert!(policy.check("alice", "lowercaseonly").is_err());
+        assert!(policy.check("alice", "Mixed123").is_ok());
+        assert!(PasswordPolicy::default()
+            .check("Password1", "password1")
+            .is_err());
+        // characters count, not bytes
+        assert!(PasswordPolicy::default().check("alice", "äöüäöü").is_err());
+    }
+}
@@ -0,0 +1,62 @@
+use crate::integrity::IntegrityError;
+use crate::middlewares::conditional::PreconditionFailed;
+use actix_web::HttpResponse;
+use json_patch::Patch;
+use serde::{de::DeserializeOwned, Serialize};
+
+pub enum PatchError {
+    Integrity(IntegrityError),
+    Patch(json_patch::PatchError),
+    Invalid(serde_json::Error),
+}
+
+impl From<IntegrityError> for PatchError {
+    fn from(val: IntegrityError) -> PatchError {
+        PatchError::Integrity(val)
+    }
+}
+
+impl From<diesel::result::Error> for PatchError {
+    fn from(val: diesel::result::Error) -> PatchError {
+        PatchError::Integrity(IntegrityError::from(val))
+    }
+}
+
+impl From<PreconditionFailed> for PatchError {
+    fn from(val: PreconditionFailed) -> PatchError {
+        PatchError::Integrity(IntegrityError::from(val))
+    }
+}
+
+impl From<json_patch::PatchError> for PatchError {
+    fn from(val: json_patch::PatchError) -> PatchError {
+        PatchError::Patch(val)
+    }
+}
+
+impl From<serde_json::Error> for PatchError {
+    fn from(val: serde_json::Error) -> PatchError {
+        PatchError::Invalid(val)
+    }
+}
+
+impl PatchError {
+    pub fn into_response(self, action: &str) -> HttpResponse {
+        match self {
+            PatchError::Integrity(e) => e.into_response(action),
+            PatchError::Patch(json_patch::PatchError::TestFailed) => {
+                HttpResponse::Conflict().body("A test operation of the patch failed.")
+            }
+            PatchError::Patch(e) => HttpResponse::UnprocessableEntity().body(format!("{}", e)),
+            PatchError::Invalid(e) => HttpResponse::UnprocessableEntity()
+                .body(format!("The patched object is invalid: {}", e)),
+        }
+    }
+}
+
+/// Applies a JSON Patch (RFC 6902) to the JSON representation of an object
+pub fn apply<T: Serialize + DeserializeOwned>(object: &T, patch: &Patch) -> Result<T, PatchError> {
+    let mut value = serde_json::to_value(object)?;
+    json_patch::patch(&mut value, patch)?;
+    Ok(serde_json::from_value(value)?)
+}
@@ -0,0 +1,201 @@
+use crate::models::{QuotaLimits, Usage};
+use crate::schema;
+use crate::settings::Settings;
+use actix_web::{http::StatusCode, HttpResponse};
+use diesel::{dsl::sql, prelude::*, sql_types::BigInt, SqliteConnection};
+use std::fmt;
+
+/// A limit a change went beyond, with the limit
+#[derive(Debug, PartialEq)]
+pub enum Exceeded {
+    DatabaseBytes(i64),
+    Objects(i64),
+    Aliases(i64),
+}
+
+impl Exceeded {
+    /// Too much data is a too large payload, too many objects are forbidden
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Exceeded::DatabaseBytes(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Exceeded::Objects(_) | Exceeded::Aliases(_) => StatusCode::FORBIDDEN,
+        }
+    }
+}
+
+impl Exceeded {
+    pub fn into_response(self) -> HttpResponse {
+        HttpResponse::build(self.status())
+            .content_type("text/plain; charset=utf-8")
+            .body(self.to_string())
+    }
+}
+
+impl fmt::Display for Exceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Exceeded::DatabaseBytes(limit) => write!(
+                f,
+                "The databases would exceed your storage quota of {} bytes.",
+                limit
+            ),
+            Exceeded::Objects(limit) => {
+                write!(f, "You can't have more than {} objects.", limit)
+            }
+            Exceeded::Aliases(limit) => {
+                write!(f, "You can't have more than {} aliases.", limit)
+            }
+        }
+    }
+}
+
+/// Sums up the databases, objects and aliases a user has access to
+pub fn usage(conn: &SqliteConnection, user_id: &str) -> Result<Usage, diesel::result::Error> {
+    let objects = schema::access::table
+        .filter(schema::access::user_id.eq(user_id))
+        .select(schema::access::object_id);
+    // every database counts with the full size of its dump, even if the blob is shared
+    let database_bytes = schema::databases::table
+        .inner_join(schema::blobs::table)
+        .filter(schema::databases::id.eq_any(objects))
+        .select(sql::<BigInt>("COALESCE(SUM(blobs.size), 0)"))
+        .get_result::<i64>(conn)?;
+    let aliases = schema::aliases::table
+        .filter(schema::aliases::object_id.eq_any(objects))
+        .count()
+        .get_result::<i64>(conn)?;
+    Ok(Usage {
+        database_bytes,
+        objects: objects.count().get_result::<i64>(conn)?,
+        aliases,
+    })
+}
+
+/// The limits of a user, where those not set for the user come from the configuration
+pub fn limits(
+    conn: &SqliteConnection,
+    settings: &Settings,
+    user_id: &str,
+) -> Result<QuotaLimits, diesel::result::Error> {
+    let defaults = settings.default_quota.unwrap_or_default();
+    let limits = schema::quotas::table
+        .find(user_id)
+        .select((
+            schema::quotas::database_bytes,
+            schema::quotas::objects,
+            schema::quotas::aliases,
+        ))
+        .get_result::<QuotaLimits>(conn)
+        .optional()?
+        .unwrap_or_default();
+    Ok(QuotaLimits {
+        database_bytes: limits.database_bytes.or(defaults.database_bytes),
+        objects: limits.objects.or(defaults.objects),
+        aliases: limits.aliases.or(defaults.aliases),
+    })
+}
+
+/// Makes a change and fails if it took the user beyond one of their quotas. Runs inside the
+/// transaction of the handler making the change, so failing rolls the change back.
+pub fn enforce<T, E, F>(
+    conn: &SqliteConnection,
+    settings: &Settings,
+    user_id: &str,
+    change: F,
+) -> Result<T, E>
+where
+    E: From<diesel::result::Error> + From<Exceeded>,
+    F: FnOnce() -> Result<T, E>,
+{
+    let limits = limits(conn, settings, user_id)?;
+    if limits.database_bytes.is_none() && limits.objects.is_none() && limits.aliases.is_none() {
+        return change();
+    }
+    let before = usage(conn, user_id)?;
+    let result = change()?;
+    match exceeded(&before, &usage(conn, user_id)?, &limits) {
+        Some(exceeded) => Err(exceeded.into()),
+        None => Ok(result),
+    }
+}
+
+/// Fails if the user couldn't add another object, to be called before expensive work
+/// whose result would be rejected by `enforce` anyway
+pub fn check_room<E>(conn: &SqliteConnection, settings: &Settings, user_id: &str) -> Result<(), E>
+where
+    E: From<diesel::result::Error> + From<Exceeded>,
+{
+    let limits = limits(conn, settings, user_id)?;
+    if limits.database_bytes.is_none() && limits.objects.is_none() {
+        return Ok(());
+    }
+    let before = usage(conn, user_id)?;
+    let after = Usage {
+        database_bytes: before.database_bytes + 1,
+        objects: before.objects + 1,
+        aliases: before.aliases,
+    };
+    match exceeded(&before, &after, &limits) {
+        Some(exceeded) => Err(exceeded.into()),
+        None => Ok(()),
+    }
+}
+
+/// Finds a limit a change went beyond. Changes which don't grow the usage are always
+/// allowed, so users above a lowered quota can still clean up.
+pub fn exceeded(before: &Usage, after: &Usage, limits: &QuotaLimits) -> Option<Exceeded> {
+    let beyond = |before: i64, after: i64, limit: Option<i64>| match limit {
+        Some(limit) => after > limit && after > before,
+        None => false,
+    };
+    if beyond(
+        before.database_bytes,
+        after.database_bytes,
+        limits.database_bytes,
+    ) {
+        limits.database_bytes.map(Exceeded::DatabaseBytes)
+    } else if beyond(before.objects, after.objects, limits.objects) {
+        limits.objects.map(Exceeded::Objects)
+    } else if beyond(before.aliases, after.aliases, limits.aliases) {
+        limits.aliases.map(Exceeded::Aliases)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(database_bytes: i64, objects: i64) -> Usage {
+        Usage {
+            database_bytes,
+            objects,
+            aliases: 0,
+        }
+    }
+
+    #[test]
+    fn test_exceeded() {
+        let limits = QuotaLimits {
+            database_bytes: Some(100),
+            objects: Some(3),
+            aliases: None,
+        };
+        assert_eq!(exceeded(&usage(0, 0), &usage(100, 3), &limits), None);
+        assert_eq!(
+            exceeded(&usage(0, 0), &usage(101, 1), &limits),
+            Some(Exceeded::DatabaseBytes(100))
+        );
+        assert_eq!(
+            exceeded(&usage(0, 3), &usage(0, 4), &limits),
+            Some(Exceeded::Objects(3))
+        );
+        // shrinking stays possible above the limit
+        assert_eq!(exceeded(&usage(500, 9), &usage(400, 9), &limits), None);
+        assert_eq!(
+            exceeded(&usage(0, 0), &usage(1000, 1000), &QuotaLimits::default()),
+            None
+        );
+    }
+}
@@ -0,0 +1,376 @@
+use crate::handlers::{subtasks, tasks, worksheets};
+use crate::integrity::IntegrityError;
+use crate::models::{self, QueryableRevision, RevisionChange};
+use crate::schema;
+use diesel::{prelude::*, SqliteConnection};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+/// Access to the revision table of one object type, and to the object itself.
+pub trait RevisionStore {
+    type Object: Serialize + DeserializeOwned;
+
+    fn load(conn: &SqliteConnection, id: &str) -> Result<Self::Object, diesel::result::Error>;
+    fn save(
+        conn: &SqliteConnection,
+        id: &str,
+        object: Self::Object,
+    ) -> Result<(), diesel::result::Error>;
+    /// Fails if the object refers to objects which are missing or not accessible
+    fn check_references(
+        conn: &SqliteConnection,
+        user_id: &str,
+        object: &Self::Object,
+    ) -> Result<(), IntegrityError>;
+
+    fn latest(conn: &SqliteConnection, id: &str) -> Result<Option<i32>, diesel::result::Error>;
+    fn insert(
+        conn: &SqliteConnection,
+        id: &str,
+        revision: i32,
+        user_id: Option<&str>,
+        restored_from: Option<i32>,
+        content: String,
+    ) -> Result<(), diesel::result::Error>;
+    fn list(
+        conn: &SqliteConnection,
+        id: &str,
+    ) -> Result<Vec<QueryableRevision>, diesel::result::Error>;
+    fn get(
+        conn: &SqliteConnection,
+        id: &str,
+        revision: i32,
+    ) -> Result<QueryableRevision, diesel::result::Error>;
+    fn delete_all(conn: &SqliteConnection, id: &str) -> Result<(), diesel::result::Error>;
+}
+
+// Every object type has its own revision table, so the diesel queries can't be shared
+// through generics.
+macro_rules! revision_store {
+    ($store:ident, $object:ty, $table:ident, $object_id:ident, $load:path, $save:path
+     $(, $check:path)?) => {
+        pub struct $store;
+
+        impl RevisionStore for $store {
+            type Object = $object;
+
+            fn load(
+                conn: &SqliteConnection,
+                id: &str,
+            ) -> Result<Self::Object, diesel::result::Error> {
+                $load(conn, id)
+            }
+
+            fn save(
+                conn: &SqliteConnection,
+                id: &str,
+                object: Self::Object,
+            ) -> Result<(), diesel::result::Error> {
+                $save(conn, id, object)
+            }
+
+            #[allow(unused_variables)]
+            fn check_references(
+                conn: &SqliteConnection,
+                user_id: &str,
+                object: &Self::Object,
+            ) -> Result<(), IntegrityError> {
+                $($check(conn, user_id, object)?;)?
+                Ok(())
+            }
+
+            fn latest(
+                conn: &SqliteConnection,
+                id: &str,
+            ) -> Result<Option<i32>, diesel::result::Error> {
+                schema::$table::table
+                    .filter(schema::$table::$object_id.eq(id))
+                    .select(diesel::dsl::max(schema::$table::revision))
+                    .get_result::<Option<i32>>(conn)
+            }
+
+            fn insert(
+                conn: &SqliteConnection,
+                id: &str,
+                revision: i32,
+                user_id: Option<&str>,
+                restored_from: Option<i32>,
+                content: String,
+            ) -> Result<(), diesel::result::Error> {
+                diesel::insert_into(schema::$table::table)
+                    .values((
+                        schema::$table::$object_id.eq(id),
+                        schema::$table::revision.eq(revision),
+                        schema::$table::user_id.eq(user_id),
+                        schema::$table::created_at.eq(chrono::Utc::now().naive_utc()),
+                        schema::$table::restored_from.eq(restored_from),
+                        schema::$table::content.eq(content),
+                    ))
+                    .execute(conn)?;
+                Ok(())
+            }
+
+            fn list(
+                conn: &SqliteConnection,
+                id: &str,
+            ) -> Result<Vec<QueryableRevision>, diesel::result::Error> {
+                schema::$table::table
+                    .filter(schema::$table::$object_id.eq(id))
+                    .select((
+                        schema::$table::revision,
+                        schema::$table::user_id,
+                        schema::$table::created_at,
+                        schema::$table::restored_from,
+                        schema::$table::content,
+                    ))
+                    .order(schema::$table::revision)
+                    .load::<QueryableRevision>(conn)
+            }
+
+            fn get(
+                conn: &SqliteConnection,
+                id: &str,
+                revision: i32,
+            ) -> Result<QueryableRevision, diesel::result::Error> {
+                schema::$table::table
+                    .filter(schema::$table::$object_id.eq(id))
+                    .filter(schema::$table::revision.eq(revision))
+                    .select((
+                        schema::$table::revision,
+                        schema::$table::user_id,
+                        schema::$table::created_at,
+                        schema::$table::restored_from,
+                        schema::$table::content,
+                    ))
+                    .get_result::<QueryableRevision>(conn)
+            }
+
+            fn delete_all(conn: &SqliteConnection, id: &str) -> Result<(), diesel::result::Error> {
+                diesel::delete(schema::$table::table.filter(schema::$table::$object_id.eq(id)))
+                    .execute(conn)?;
+                Ok(())
+            }
+        }
+    };
+}
+
+revision_store!(
+    SubtaskRevisions,
+    models::Subtask,
+    subtask_revisions,
+    subtask_id,
+    subtasks::load_subtask,
+    subtasks::save_subtask
+);
+revision_store!(
+    TaskRevisions,
+    models::Task,
+    task_revisions,
+    task_id,
+    tasks::load_task,
+    tasks::save_task,
+    tasks::check_references
+);
+revision_store!(
+    WorksheetRevisions,
+    models::Worksheet,
+    worksheet_revisions,
+    worksheet_id,
+    worksheets::load_worksheet,
+    worksheets::save_worksheet,
+    worksheets::check_references
+);
+
+fn to_json<T: Serialize>(object: &T) -> Result<String, diesel::result::Error> {
+    serde_json::to_string(object)
+        .map_err(|e| diesel::result::Error::SerializationError(Box::new(e)))
+}
+
+/// Appends the current state of an object to its history and returns the revision number
+pub fn record<S: RevisionStore>(
+    conn: &SqliteConnection,
+    id: &str,
+    user_id: &str,
+    restored_from: Option<i32>,
+) -> Result<i32, diesel::result::Error> {
+    let content = to_json(&S::load(conn, id)?)?;
+    let revision = S::latest(conn, id)?.unwrap_or(0) + 1;
+    S::insert(conn, id, revision, Some(user_id), restored_from, content)?;
+    Ok(revision)
+}
+
+/// Objects created before revisions were tracked get their current state recorded
+/// as a baseline, to be called before changing them.
+pub fn ensure_baseline<S: RevisionStore>(
+    conn: &SqliteConnection,
+    id: &str,
+) -> Result<(), diesel::result::Error> {
+    if S::latest(conn, id)?.is_none() {
+        let baseline = to_json(&S::load(conn, id)?)?;
+        S::insert(conn, id, 1, None, None, baseline)?;
+    }
+    Ok(())
+}
+
+/// Overwrites an object and records the change
+pub fn update<S: RevisionStore>(
+    conn: &SqliteConnection,
+    id: &str,
+    user_id: &str,
+    object: S::Object,
+    restored_from: Option<i32>,
+) -> Result<i32, diesel::result::Error> {
+    ensure_baseline::<S>(conn, id)?;
+    S::save(conn, id, object)?;
+    record::<S>(conn, id, user_id, restored_from)
+}
+
+/// Writes an older revision back to the object, which creates a new revision. Objects
+/// it referred to may have been deleted since, then it can't be restored.
+pub fn restore<S: RevisionStore>(
+    conn: &SqliteConnection,
+    id: &str,
+    user_id: &str,
+    revision: i32,
+) -> Result<i32, IntegrityError> {
+    let old = S::get(conn, id, revision)?;
+    let object = serde_json::from_str::<S::Object>(&old.content)
+        .map_err(|e| diesel::result::Error::DeserializationError(Box::new(e)))?;
+    S::check_references(conn, user_id, &object)?;
+    Ok(update::<S>(conn, id, user_id, object, Some(revision))?)
+}
+
+/// Lists all values that differ between two JSON documents
+pub fn diff(from: &Value, to: &Value) -> Vec<RevisionChange> {
+    let mut changes = Vec::new();
+    diff_at("", from, to, &mut changes);
+    changes
+}
+
+fn diff_at(path: &str, from: &Value, to: &Value, changes: &mut Vec<RevisionChange>) {
+    match (from, to) {
+        (Value::Object(from), Value::Object(to)) => {
+            for (key, from_value) in from.iter() {
+                let key_path = format!("{}/{}", path, key);
+                match to.get(key) {
+                    Some(to_value) => diff_at(&key_path, from_value, to_value, changes),
+                    None => changes.push(RevisionChange {
+                        path: key_path,
+                        from: Some(from_value.clone()),
+                        to: None,
+                    }),
+                }
+            }
+            for (key, to_value) in to.iter() {
+                if !from.contains_key(key) {
+                    changes.push(RevisionChange {
+                        path: format!("{}/{}", path, key),
+                        from: None,
+                        to: Some(to_value.clone()),
+                    });
+                }
+            }
+        }
+        (Value::Array(from), Value::Array(to)) => {
+            for index in 0..from.len().max(to.len()) {
+                let index_path = format!("{}/{}", path, index);
+                match (from.get(index), to.get(index)) {
+                    (Some(from_value), Some(to_value)) => {
+                        diff_at(&index_path, from_value, to_value, changes)
+                    }
+                    (from_value, to_value) => changes.push(RevisionChange {
+                        path: index_path,
+                        from: from_value.cloned(),
+                        to: to_value.cloned(),
+                    }),
+                }
+            }
+        }
+        (from, to) => {
+            if from != to {
+                changes.push(RevisionChange {
+                    path: path.to_string(),
+                    from: Some(from.clone()),
+                    to: Some(to.clone()),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::{seed, test_connection};
+    use crate::integrity::IntegrityError;
+    use crate::models::RevisionChange;
+    use crate::revisions::{diff, restore, RevisionStore, TaskRevisions};
+    use serde_json::json;
+
+    #[test]
+    fn diff_identical() {
+        let subtask = json!({"id": "a", "instruction": "select all", "solution_visible": true});
+        assert!(diff(&subtask, &subtask).is_empty());
+    }
+
+    #[test]
+    fn diff_nested() {
+        let from = json!({
+            "instruction": "select all",
+            "content": {"sql": {"solution": {"rows": [["Rock"], ["Jazz"]]}}},
+            "subtasks": ["a", "b"]
+        });
+        let to = json!({
+            "instruction": "select all genres",
+            "content": {"sql": {"solution": {"rows": [["Rock"], ["Blues"]]}}},
+            "subtasks": ["a"],
+            "name": "new"
+        });
+        assert_eq!(
+            diff(&from, &to),
+            vec![
+                RevisionChange {
+                    path: "/content/sql/solution/rows/1/0".to_string(),
+                    from: Some(json!("Jazz")),
+                    to: Some(json!("Blues")),
+                },
+                RevisionChange {
+                    path: "/instruction".to_string(),
+                    from: Some(json!("select all")),
+                    to: Some(json!("select all genres")),
+                },
+                RevisionChange {
+                    path: "/subtasks/1".to_string(),
+                    from: Some(json!("b")),
+                    to: None,
+                },
+                RevisionChange {
+                    path: "/name".to_string(),
+                    from: None,
+                    to: Some(json!("new")),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn restore_missing_reference() {
+        let conn = test_connection();
+        seed(&conn);
+        for (revision, database) in [(1, "deleted"), (2, "database")].iter() {
+            let content = json!({"id": "task", "database": database, "subtasks": []});
+            TaskRevisions::insert(&conn, "task", *revision, None, None, content.to_string())
+                .unwrap();
+        }
+
+        match restore::<TaskRevisions>(&conn, "task", "user", 1) {
+            Err(IntegrityError::InvalidReferences(invalid)) => {
+                assert_eq!(invalid.missing, vec!["deleted"])
+            }
+            _ => panic!("restored a revision referring to a deleted database"),
+        }
+        assert_eq!(
+            restore::<TaskRevisions>(&conn, "task", "user", 2).ok(),
+            Some(3)
+        );
+    }
+}
@@ -0,0 +1,555 @@
+use crate::dump::{quote_identifier, quote_literal, DumpError};
+use crate::models::{GeneratedColumn, GeneratedTable, GeneratorRequest, ValueGenerator};
+use chrono::Duration;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+
+/// Upper bound for all rows of a generated database together
+const MAX_ROWS: u64 = 100_000;
+
+/// How often a row is made up again when its primary key exists already
+const MAX_ATTEMPTS: usize = 20;
+
+const FIRST_NAMES: &[&str] = &[
+    "Anna", "Ben", "Clara", "David", "Emma", "Felix", "Greta", "Hannah", "Jonas", "Julia", "Karl",
+    "Lea", "Leon", "Lina", "Lukas", "Marie", "Max", "Mia", "Noah", "Paul", "Sophie", "Tim", "Lena",
+    "Elias", "Amelie", "Finn", "Laura", "Moritz", "Nina", "Oskar",
+];
+
+const LAST_NAMES: &[&str] = &[
+    "Müller",
+    "Schmidt",
+    "Schneider",
+    "Fischer",
+    "Weber",
+    "Meyer",
+    "Wagner",
+    "Becker",
+    "Schulz",
+    "Hoffmann",
+    "Koch",
+    "Richter",
+    "Klein",
+    "Wolf",
+    "Schröder",
+    "Neumann",
+    "Schwarz",
+    "Braun",
+    "Zimmermann",
+    "Krüger",
+    "Hartmann",
+    "Lange",
+    "Werner",
+    "Krause",
+    "Lehmann",
+    "Köhler",
+];
+
+const CITIES: &[&str] = &[
+    "Berlin",
+    "Hamburg",
+    "München",
+    "Köln",
+    "Frankfurt",
+    "Stuttgart",
+    "Düsseldorf",
+    "Leipzig",
+    "Dortmund",
+    "Essen",
+    "Bremen",
+    "Dresden",
+    "Hannover",
+    "Nürnberg",
+    "Karlsruhe",
+    "Mannheim",
+    "Freiburg",
+    "Heidelberg",
+    "Ulm",
+    "Konstanz",
+];
+
+const WORDS: &[&str] = &[
+    "lorem",
+    "ipsum",
+    "dolor",
+    "sit",
+    "amet",
+    "consectetur",
+    "adipiscing",
+    "elit",
+    "sed",
+    "do",
+    "eiusmod",
+    "tempor",
+    "incididunt",
+    "ut",
+    "labore",
+    "et",
+    "dolore",
+    "magna",
+    "aliqua",
+    "enim",
+    "minim",
+    "veniam",
+    "quis",
+    "nostrud",
+    "exercitation",
+    "ullamco",
+    "laboris",
+    "nisi",
+];
+
+/// A generated value, kept to be picked by foreign keys
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Null,
+    Integer(i64),
+    Real(f64, u32),
+    Text(String),
+}
+
+impl Value {
+    fn to_sql(&self) -> String {
+        match self {
+            Value::Null => "NULL".to_string(),
+            Value::Integer(value) => value.to_string(),
+            Value::Real(value, decimals) => format!("{:.*}", *decimals as usize, value),
+            Value::Text(value) => quote_literal(value),
+        }
+    }
+}
+
+fn invalid(reason: String) -> DumpError {
+    DumpError::Invalid(reason)
+}
+
+/// Orders the tables so every table comes after the tables it references.
+/// References of a table to itself are allowed, other cycles are not.
+fn table_order(tables: &[GeneratedTable]) -> Result<Vec<&GeneratedTable>, DumpError> {
+    let mut order: Vec<&GeneratedTable> = Vec::new();
+    while order.len() < tables.len() {
+        let next = tables.iter().find(|table| {
+            !order.iter().any(|done| done.name == table.name)
+                && table.columns.iter().all(|column| match &column.references {
+                    Some(reference) => {
+                        reference.table == table.name
+                            || order.iter().any(|done| done.name == reference.table)
+                    }
+                    None => true,
+                })
+        });
+        match next {
+            Some(table) => order.push(table),
+            None => {
+                return Err(invalid(
+                    "The foreign keys of the tables form a cycle.".to_string(),
+                ))
+            }
+        }
+    }
+    Ok(order)
+}
+
+/// Checks names, types and references before generating anything
+fn check_request(request: &GeneratorRequest) -> Result<(), DumpError> {
+    lazy_static::lazy_static! {
+        static ref TYPE: Regex = Regex::new(r"^[A-Za-z][A-Za-z0-9_ ]*(\(\s*\d+\s*(,\s*\d+\s*)?\))?$").unwrap();
+    }
+    if request.tables.is_empty() {
+        return Err(invalid("There are no tables to generate.".to_string()));
+    }
+    let rows = request
+        .tables
+        .iter()
+        .map(|table| u64::from(table.rows))
+        .sum::<u64>();
+    if rows > MAX_ROWS {
+        return Err(invalid(format!(
+            "At most {} rows can be generated at once.",
+            MAX_ROWS
+        )));
+    }
+
+    let mut table_names = HashSet::new();
+    for table in &request.tables {
+        if table.name.trim().is_empty() || !table_names.insert(table.name.to_lowercase()) {
+            return Err(invalid(format!(
+                "The table name \"{}\" is empty or used twice.",
+                table.name
+            )));
+        }
+        if table.columns.is_empty() {
+            return Err(invalid(format!("The table {} has no columns.", table.name)));
+        }
+        let mut column_names = HashSet::new();
+        for column in &table.columns {
+            if column.name.trim().is_empty() || !column_names.insert(column.name.to_lowercase()) {
+                return Err(invalid(format!(
+                    "The column name \"{}\" of the table {} is empty or used twice.",
+                    column.name, table.name
+                )));
+            }
+            if let Some(column_type) = &column.column_type {
+                if !TYPE.is_match(column_type) {
+                    return Err(invalid(format!(
+                        "The type of the column {}.{} is invalid.",
+                        table.name, column.name
+                    )));
+                }
+            }
+            if let Some(reference) = &column.references {
+                let referenced = request
+                    .tables
+                    .iter()
+                    .find(|t| t.name == reference.table)
+                    .and_then(|t| t.columns.iter().find(|c| c.name == reference.column));
+                if referenced.is_none() {
+                    return Err(invalid(format!(
+                        "The column {}.{} references {}.{}, which doesn't exist.",
+                        table.name, column.name, reference.table, reference.column
+                    )));
+                }
+            }
+            if let Some(ValueGenerator::Enum { values }) = &column.generator {
+                if values.is_empty() {
+                    return Err(invalid(format!(
+                        "The column {}.{} has no values to choose from.",
+                        table.name, column.name
+                    )));
+                }
+            }
+            if let Some(ValueGenerator::Range { min, max, .. }) = &column.generator {
+                if !(min.is_finite() && max.is_finite() && min <= max) {
+                    return Err(invalid(format!(
+                        "The range of the column {}.{} is invalid.",
+                        table.name, column.name
+                    )));
+                }
+            }
+            if let Some(ValueGenerator::Date { from, to }) = &column.generator {
+                if from > to {
+                    return Err(invalid(format!(
+                        "The dates of the column {}.{} are in the wrong order.",
+                        table.name, column.name
+                    )));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn column_type(column: &GeneratedColumn) -> String {
+    column
+        .column_type
+        .clone()
+        .unwrap_or_else(|| "TEXT".to_string())
+}
+
+/// The generator used when a column has none, depending on its type
+fn default_generator(column: &GeneratedColumn) -> ValueGenerator {
+    let column_type = column_type(column).to_uppercase();
+    if column_type.contains("INT") {
+        if column.primary_key {
+            ValueGenerator::Sequence { start: 1 }
+        } else {
+            ValueGenerator::Range {
+                min: 0.0,
+                max: 1000.0,
+                decimals: 0,
+            }
+        }
+    } else if column_type.contains("REAL")
+        || column_type.contains("FLOA")
+        || column_type.contains("DOUB")
+        || column_type.contains("NUM")
+        || column_type.contains("DEC")
+    {
+        ValueGenerator::Range {
+            min: 0.0,
+            max: 1000.0,
+            decimals: 2,
+        }
+    } else if column_type.contains("BOOL") {
+        ValueGenerator::Boolean
+    } else if column_type.contains("DATE") {
+        ValueGenerator::Date {
+            from: chrono::NaiveDate::from_ymd(2000, 1, 1),
+            to: chrono::NaiveDate::from_ymd(2020, 12, 31),
+        }
+    } else {
+        ValueGenerator::Text { words: 3 }
+    }
+}
+
+fn generate_value(generator: &ValueGenerator, row: usize, rng: &mut StdRng) -> Value {
+    match generator {
+        ValueGenerator::Sequence { start } => Value::Integer(start + row as i64),
+        ValueGenerator::Range { min, max, decimals } => {
+            let value = if min < max {
+                rng.gen_range(*min, *max)
+            } else {
+                *min
+            };
+            if *decimals == 0 {
+                Value::Integer(value.round() as i64)
+            } else {
+                Value::Real(value, *decimals)
+            }
+        }
+        ValueGenerator::Enum { values } => Value::Text(values.choose(rng).unwrap().clone()),
+        ValueGenerator::Name => Value::Text(format!(
+            "{} {}",
+            FIRST_NAMES.choose(rng).unwrap(),
+            LAST_NAMES.choose(rng).unwrap()
+        )),
+        ValueGenerator::FirstName => Value::Text(FIRST_NAMES.choose(rng).unwrap().to_string()),
+        ValueGenerator::LastName => Value::Text(LAST_NAMES.choose(rng).unwrap().to_string()),
+        ValueGenerator::Email => {
+            let name = format!(
+                "{}.{}{}",
+                FIRST_NAMES.choose(rng).unwrap(),
+                LAST_NAMES.choose(rng).unwrap(),
+                rng.gen_range(1, 100)
+            )
+            .to_lowercase()
+            .replace('ä', "ae")
+            .replace('ö', "oe")
+            .replace('ü', "ue");
+            Value::Text(format!("{}@example.org", name))
+        }
+        ValueGenerator::City => Value::Text(CITIES.choose(rng).unwrap().to_string()),
+        ValueGenerator::Date { from, to } => {
+            let days = rng.gen_range(0, (*to - *from).num_days() + 1);
+            Value::Text(
+                (*from + Duration::days(days))
+                    .format("%Y-%m-%d")
+                    .to_string(),
+            )
+        }
+        ValueGenerator::Boolean => Value::Integer(rng.gen_range(0, 2)),
+        ValueGenerator::Text { words } => {
+            let mut text = (0..(*words).max(1))
+                .map(|_| *WORDS.choose(rng).unwrap())
+                .collect::<Vec<_>>()
+                .join(" ");
+            text[..1].make_ascii_uppercase();
+            text.push('.');
+            Value::Text(text)
+        }
+    }
+}
+
+/// Builds the SQL statements for a database with made up data. Foreign keys only take
+/// values of rows which exist in the referenced table.
+pub fn generate(request: &GeneratorRequest) -> Result<String, DumpError> {
+    check_request(request)?;
+    let mut rng = match request.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+
+    // the values of every generated column, by table and column name
+    let mut generated: HashMap<(String, String), Vec<Value>> = HashMap::new();
+    let mut dump = String::new();
+    for table in table_order(&request.tables)? {
+        let primary_key = table
+            .columns
+            .iter()
+            .enumerate()
+            .filter(|(_, column)| column.primary_key)
+            .map(|(c, _)| c)
+            .collect::<Vec<_>>();
+        let generators = table
+            .columns
+            .iter()
+            .map(|column| {
+                column
+                    .generator
+                    .clone()
+                    .unwrap_or_else(|| default_generator(column))
+            })
+            .collect::<Vec<_>>();
+
+        let mut rows: Vec<Vec<Value>> = Vec::new();
+        let mut keys = HashSet::new();
+        for row in 0..table.rows as usize {
+            let mut attempts = 0;
+            let values = loop {
+                let mut values = Vec::new();
+                for (c, column) in table.columns.iter().enumerate() {
+                    let null_ratio = column.null_ratio.unwrap_or(0.0).clamp(0.0, 1.0);
+                    let value =
+                        if column.nullable && !column.primary_key && rng.gen_bool(null_ratio) {
+                            Value::Null
+                        } else if let Some(reference) = &column.references {
+                            // a reference to the own table picks from the rows before
+                            let candidates = if reference.table == table.name {
+                                let referenced = table
+                                    .columns
+                                    .iter()
+                                    .position(|c| c.name == reference.column)
+                                    .unwrap();
+                                rows.iter()
+                                    .map(|row| row[referenced].clone())
+                                    .filter(|value| *value != Value::Null)
+                                    .collect::<Vec<_>>()
+                            } else {
+                                generated
+                                    .get(&(reference.table.clone(), reference.column.clone()))
+                                    .map(|values| {
+                                        values
+                                            .iter()
+                                            .filter(|value| **value != Value::Null)
+                                            .cloned()
+                                            .collect::<Vec<_>>()
+                                    })
+                                    .unwrap_or_default()
+                            };
+                            match candidates.choose(&mut rng) {
+                                Some(value) => value.clone(),
+                                None if column.nullable && !column.primary_key => Value::Null,
+                                None => {
+                                    return Err(invalid(format!(
+                                        "The column {}.{} references {}.{}, which has no values.",
+                                        table.name, column.name, reference.table, reference.column
+                                    )))
+                                }
+                            }
+                        } else {
+                            generate_value(&generators[c], row, &mut rng)
+                        };
+                    values.push(value);
+                }
+                let key = primary_key
+                    .iter()
+                    .map(|c| values[*c].to_sql())
+                    .collect::<Vec<_>>();
+                if primary_key.is_empty() || keys.insert(key) {
+                    break Some(values);
+                }
+                attempts += 1;
+                if attempts >= MAX_ATTEMPTS {
+                    break None;
+                }
+            };
+            match values {
+                Some(values) => rows.push(values),
+                // there are not enough distinct primary keys, so the table gets fewer rows
+                None => break,
+            }
+        }
+
+        let mut definitions = table
+            .columns
+            .iter()
+            .map(|column| {
+                let mut definition =
+                    format!("{} {}", quote_identifier(&column.name), column_type(column));
+                if !column.nullable {
+                    definition.push_str(" NOT NULL");
+                }
+                if let Some(reference) = &column.references {
+                    definition.push_str(&format!(
+                        " REFERENCES {}({})",
+                        quote_identifier(&reference.table),
+                        quote_identifier(&reference.column)
+                    ));
+                }
+                definition
+            })
+            .collect::<Vec<_>>();
+        if !primary_key.is_empty() {
+            definitions.push(format!(
+                "PRIMARY KEY ({})",
+                primary_key
+                    .iter()
+                    .map(|c| quote_identifier(&table.columns[*c].name))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        dump.push_str(&format!(
+            "CREATE TABLE {} ({});\n",
+            quote_identifier(&table.name),
+            definitions.join(", ")
+        ));
+        for row in &rows {
+            dump.push_str(&format!(
+                "INSERT INTO {} VALUES({});\n",
+                quote_identifier(&table.name),
+                row.iter().map(Value::to_sql).collect::<Vec<_>>().join(",")
+            ));
+        }
+
+        for (c, column) in table.columns.iter().enumerate() {
+            generated.insert(
+                (table.name.clone(), column.name.clone()),
+                rows.iter().map(|row| row[c].clone()).collect(),
+            );
+        }
+    }
+    Ok(dump)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(seed: Option<u64>) -> GeneratorRequest {
+        serde_json::from_str(&format!(
+            r#"{{"name": "shop", "seed": {}, "tables": [
+                {{"name": "orders", "rows": 50, "columns": [
+                    {{"name": "id", "type": "INTEGER", "primary_key": true}},
+                    {{"name": "customer", "type": "INTEGER",
+                      "references": {{"table": "customers", "column": "id"}}}},
+                    {{"name": "status", "generator": {{"kind": "enum", "values": ["open", "paid"]}}}}
+                ]}},
+                {{"name": "customers", "rows": 10, "columns": [
+                    {{"name": "id", "type": "INTEGER", "primary_key": true,
+                      "generator": {{"kind": "sequence", "start": 100}}}},
+                    {{"name": "name", "generator": {{"kind": "name"}}}}
+                ]}}
+            ]}}"#,
+            seed.map_or("null".to_string(), |seed| seed.to_string())
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_seed() {
+        assert_eq!(
+            generate(&request(Some(7))).unwrap(),
+            generate(&request(Some(7))).unwrap()
+        );
+        assert_ne!(
+            generate(&request(Some(7))).unwrap(),
+            generate(&request(Some(8))).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_foreign_keys() {
+        let dump = generate(&request(None)).unwrap();
+        // the referenced table is created first
+        assert!(dump.starts_with("CREATE TABLE \"customers\""));
+        for line in dump
+            .lines()
+            .filter(|line| line.starts_with("INSERT INTO \"orders\""))
+        {
+            let customer = line.split(',').nth(1).unwrap().parse::<i64>().unwrap();
+            assert!((100..110).contains(&customer));
+        }
+    }
+
+    #[test]
+    fn test_cycle() {
+        let mut request = request(Some(1));
+        request.tables[1].columns[1].references = Some(crate::models::ColumnReference {
+            table: "orders".to_string(),
+            column: "id".to_string(),
+        });
+        assert!(generate(&request).is_err());
+    }
+}
@@ -1,19 +1,142 @@
 use crate::database::DatabaseConnectionConfig;
+use crate::login_attempts::LoginThrottling;
+use crate::models::{PasswordHashing, QuotaLimits};
+use crate::passwords::PasswordPolicy;
+use actix_web::cookie::SameSite;
+use actix_web_jwt_middleware::{Algorithm, CookieConfig, JwtKey, JwtKeys, TokenSource, Validation};
 use config::{Config, ConfigError, Environment, File};
 use serde::Deserialize;
+use std::path::PathBuf;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Settings {
+    /// Without open registration, new accounts need an invitation code
     pub(crate) open_registration: Option<bool>,
-    pub(crate) jwt_key: String,
+    /// argon2id costs of new hashes, existing ones are rehashed when logging in
+    pub(crate) password_hashing: Option<PasswordHashing>,
+    pub(crate) password_policy: Option<PasswordPolicy>,
+    pub(crate) login_throttling: Option<LoginThrottling>,
+    /// HMAC secret for HS512 tokens
+    pub(crate) jwt_key: Option<String>,
+    /// Asymmetric keys, the first one with a private key signs new tokens
+    pub(crate) jwt_keys: Option<Vec<KeyConfig>>,
+    /// Seconds of clock skew tolerated when checking token times
+    pub(crate) jwt_leeway: Option<i64>,
+    /// Put into issued tokens and required from presented ones, if set
+    pub(crate) jwt_issuer: Option<String>,
+    pub(crate) jwt_audience: Option<String>,
+    /// Lifetimes of access and refresh tokens in seconds
+    pub(crate) access_token_lifetime: Option<i64>,
+    pub(crate) refresh_token_lifetime: Option<i64>,
+    /// Where clients send their tokens, the `Authorization` header by default
+    pub(crate) auth_mode: Option<AuthMode>,
+    /// Cookies are only sent over HTTPS unless this is false, e.g. for development
+    pub(crate) cookie_secure: Option<bool>,
+    pub(crate) cookie_same_site: Option<CookieSameSite>,
     pub(crate) http_timeout: Option<u64>,
     pub(crate) listen_addr: Vec<std::net::SocketAddr>,
+    /// Proxies whose `X-Forwarded-For` header tells the address of the client
     pub(crate) trusted_proxies: Option<Vec<std::net::IpAddr>>,
     pub(crate) db_connection: DatabaseConnectionConfig,
     pub(crate) allowed_frontend: Option<String>,
+    pub(crate) trash_retention_days: Option<i64>,
+    pub(crate) default_quota: Option<QuotaLimits>,
+}
+
+/// A key pair in PEM files, a public key alone only verifies tokens, e.g. of a rotated key
+#[derive(Debug, Deserialize, Clone)]
+pub struct KeyConfig {
+    pub(crate) kid: Option<String>,
+    pub(crate) algorithm: String,
+    pub(crate) private_key: Option<PathBuf>,
+    pub(crate) public_key: Option<PathBuf>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthMode {
+    Header,
+    Cookie,
+    Both,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum CookieSameSite {
+    Strict,
+    Lax,
+    None,
 }
 
 impl Settings {
+    /// Loads the configured keys. With asymmetric keys, a set `jwt_key` still verifies the
+    /// tokens issued before switching to them.
+    pub fn jwt_keys(&self) -> Result<JwtKeys, String> {
+        let mut keys = Vec::new();
+        for config in self.jwt_keys.iter().flatten() {
+            let name = config.kid.as_deref().unwrap_or("without id");
+            let algorithm = Algorithm::from_name(&config.algorithm)
+                .ok_or_else(|| format!("Unknown algorithm {} of key {}", config.algorithm, name))?;
+            let key = match (&config.private_key, &config.public_key) {
+                (Some(path), _) => JwtKey::private_pem_file(config.kid.clone(), algorithm, path),
+                (None, Some(path)) => JwtKey::public_pem_file(config.kid.clone(), algorithm, path),
+                (None, None) => return Err(format!("Key {} has no key file", name)),
+            };
+            keys.push(key.map_err(|e| format!("Key {}: {}", name, e))?);
+        }
+        match self.jwt_key.as_deref() {
+            Some("") | None if keys.is_empty() => {
+                return Err("jwt_key has to be set to a secret".to_owned())
+            }
+            Some("") | None => (),
+            Some(secret) => keys
+                .push(JwtKey::secret(None, Algorithm::HS512, secret).map_err(|e| e.to_string())?),
+        }
+        let keys = JwtKeys::new(keys);
+        if keys.signing_key().is_none() {
+            return Err("None of the JWT keys has a private key".to_owned());
+        }
+        Ok(keys)
+    }
+
+    pub fn jwt_validation(&self) -> Validation {
+        Validation {
+            leeway: self.jwt_leeway.unwrap_or(Validation::default().leeway),
+            require_exp: true,
+            issuer: self.jwt_issuer.clone(),
+            audience: self.jwt_audience.clone(),
+        }
+    }
+
+    pub fn token_source(&self) -> TokenSource {
+        let config = CookieConfig {
+            secure: self.cookie_secure.unwrap_or(true),
+            same_site: match self.cookie_same_site {
+                Some(CookieSameSite::Strict) | None => SameSite::Strict,
+                Some(CookieSameSite::Lax) => SameSite::Lax,
+                Some(CookieSameSite::None) => SameSite::None,
+            },
+            ..CookieConfig::default()
+        };
+        match self.auth_mode.unwrap_or(AuthMode::Header) {
+            AuthMode::Header => TokenSource::Header,
+            AuthMode::Cookie => TokenSource::Cookie(config),
+            AuthMode::Both => TokenSource::Both(config),
+        }
+    }
+
+    pub fn password_hashing(&self) -> PasswordHashing {
+        self.password_hashing.unwrap_or_default()
+    }
+
+    pub fn password_policy(&self) -> PasswordPolicy {
+        self.password_policy.unwrap_or_default()
+    }
+
+    pub fn trusted_proxies(&self) -> &[std::net::IpAddr] {
+        self.trusted_proxies.as_deref().unwrap_or(&[])
+    }
+
     pub fn new(config_file_path: &str) -> Result<Self, ConfigError> {
         let mut s = Config::new();
         s.merge(File::with_name(config_file_path))?;
@@ -0,0 +1,85 @@
+use actix_web::{web::Bytes, Error};
+use futures::{stream, Async, Poll, Stream};
+use std::io::Read;
+
+/// Size of the chunks a body is read in
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Body stream reading chunks from a reader, so big dumps are never in memory as a whole
+pub struct ReadStream<R> {
+    reader: R,
+}
+
+impl<R: Read> ReadStream<R> {
+    pub fn new(reader: R) -> Self {
+        ReadStream { reader }
+    }
+}
+
+impl<R: Read> Stream for ReadStream<R> {
+    type Item = Bytes;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Bytes>, Error> {
+        let mut buffer = vec![0; CHUNK_SIZE];
+        match self.reader.read(&mut buffer) {
+            Ok(0) => Ok(Async::Ready(None)),
+            Ok(read) => {
+                buffer.truncate(read);
+                Ok(Async::Ready(Some(Bytes::from(buffer))))
+            }
+            Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => self.poll(),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Escapes a piece of a string for a JSON string literal. Only ASCII characters need
+/// escaping, so chunks may split multi-byte characters.
+fn escape_json(chunk: &[u8]) -> Bytes {
+    let mut escaped = Vec::with_capacity(chunk.len());
+    for &byte in chunk {
+        match byte {
+            b'"' => escaped.extend_from_slice(b"\\\""),
+            b'\\' => escaped.extend_from_slice(b"\\\\"),
+            b'\n' => escaped.extend_from_slice(b"\\n"),
+            b'\r' => escaped.extend_from_slice(b"\\r"),
+            b'\t' => escaped.extend_from_slice(b"\\t"),
+            0..=0x1f => escaped.extend_from_slice(format!("\\u{:04x}", byte).as_bytes()),
+            _ => escaped.push(byte),
+        }
+    }
+    Bytes::from(escaped)
+}
+
+/// Streams a JSON object whose last field is the string read from the reader. `prefix` has
+/// to be the object up to the opening quote of that string, e.g. `{"id":"..","database":"`.
+pub fn json_with_string<R: Read + 'static>(
+    prefix: String,
+    reader: R,
+) -> impl Stream<Item = Bytes, Error = Error> {
+    stream::once(Ok(Bytes::from(prefix)))
+        .chain(ReadStream::new(reader).map(|chunk| escape_json(&chunk)))
+        .chain(stream::once(Ok(Bytes::from_static(b"\"}"))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::Future;
+
+    #[test]
+    fn test_json_with_string() {
+        let content = "INSERT INTO t VALUES('\"ä\\\n\t\u{1}');\n".repeat(5000);
+        let body = json_with_string(
+            r#"{"id":"1","database":""#.to_string(),
+            std::io::Cursor::new(content.clone().into_bytes()),
+        )
+        .concat2()
+        .wait()
+        .unwrap();
+        let parsed: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(parsed["id"], "1");
+        assert_eq!(parsed["database"], content.as_str());
+    }
+}
@@ -0,0 +1,358 @@
+use crate::admins::{is_admin, ADMIN_SCOPE};
+use crate::models;
+use crate::schema;
+use crate::settings::Settings;
+use actix_web::{dev::ServiceRequest, http::header::WWW_AUTHENTICATE, HttpMessage, HttpResponse};
+use actix_web_jwt_middleware::{Claims, JwtKeys, KeyError, RevocationCheck};
+use blake2_rfc::blake2b::blake2b;
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::{
+    prelude::*,
+    r2d2::{self, ConnectionManager},
+    SqliteConnection,
+};
+use rand::RngCore;
+use serde_json::json;
+use uuid::Uuid;
+
+/// Default lifetime of access tokens, 15 minutes
+const ACCESS_TOKEN_LIFETIME: i64 = 15 * 60;
+/// Default lifetime of refresh tokens, 30 days
+const REFRESH_TOKEN_LIFETIME: i64 = 30 * 24 * 60 * 60;
+
+pub enum TokenError {
+    Diesel(diesel::result::Error),
+    Key(KeyError),
+    /// The refresh token is unknown, expired or was used already
+    InvalidRefreshToken,
+    /// The user of the refresh token has been disabled
+    Disabled,
+}
+
+impl From<diesel::result::Error> for TokenError {
+    fn from(val: diesel::result::Error) -> TokenError {
+        TokenError::Diesel(val)
+    }
+}
+
+impl From<KeyError> for TokenError {
+    fn from(val: KeyError) -> TokenError {
+        TokenError::Key(val)
+    }
+}
+
+impl TokenError {
+    pub fn into_response(self, action: &str) -> HttpResponse {
+        match self {
+            TokenError::Diesel(e) => {
+                log::error!("Couldn't {}: {}", action, e);
+                HttpResponse::InternalServerError().finish()
+            }
+            TokenError::Key(e) => {
+                log::error!("Couldn't {}: {}", action, e);
+                HttpResponse::InternalServerError().finish()
+            }
+            TokenError::InvalidRefreshToken => HttpResponse::Unauthorized()
+                .header(
+                    WWW_AUTHENTICATE,
+                    "Bearer error=\"invalid_token\", error_description=\"The refresh token is invalid\"",
+                )
+                .finish(),
+            TokenError::Disabled => HttpResponse::Unauthorized()
+                .header(
+                    WWW_AUTHENTICATE,
+                    "Bearer error=\"invalid_token\", error_description=\"The account is disabled\"",
+                )
+                .finish(),
+        }
+    }
+}
+
+/// Only hashes of refresh tokens are stored, so a leaked table can't be used for logging in
+fn hash(refresh_token: &str) -> String {
+    blake2b(32, &[], refresh_token.as_bytes())
+        .as_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Time for the `iat` claim of new tokens. A revocation rejects all tokens issued up to the
+/// second it happened in, so tokens issued later in that second claim the next one.
+fn issued_at(valid_after: Option<NaiveDateTime>) -> i64 {
+    let now = Utc::now().timestamp();
+    match valid_after {
+        Some(valid_after) => now.max(valid_after.timestamp() + 1),
+        None => now,
+    }
+}
+
+fn access_token(
+    settings: &Settings,
+    keys: &JwtKeys,
+    user_id: &str,
+    admin: bool,
+    now: i64,
+) -> Result<String, TokenError> {
+    let mut claims = json!({
+        "sub": user_id,
+        "iat": now,
+        "exp": now + settings.access_token_lifetime.unwrap_or(ACCESS_TOKEN_LIFETIME),
+        "jti": Uuid::new_v4().to_hyphenated().to_string(),
+    });
+    if admin {
+        claims["scope"] = json!(ADMIN_SCOPE);
+    }
+    if let Some(issuer) = &settings.jwt_issuer {
+        claims["iss"] = json!(issuer);
+    }
+    if let Some(audience) = &settings.jwt_audience {
+        claims["aud"] = json!(audience);
+    }
+    Ok(keys.sign(&claims)?)
+}
+
+/// Seconds a refresh token is valid for
+pub fn refresh_token_lifetime(settings: &Settings) -> i64 {
+    settings
+        .refresh_token_lifetime
+        .unwrap_or(REFRESH_TOKEN_LIFETIME)
+}
+
+/// Creates an access token and a refresh token for the user
+pub fn issue(
+    conn: &SqliteConnection,
+    settings: &Settings,
+    keys: &JwtKeys,
+    user_id: &str,
+) -> Result<models::Token, TokenError> {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let refresh_token = base64::encode_config(&bytes, base64::URL_SAFE_NO_PAD);
+    let now = Utc::now().naive_utc();
+    // expired tokens of the user are cleaned up on the way
+    diesel::delete(
+        schema::refresh_tokens::table
+            .filter(schema::refresh_tokens::user_id.eq(user_id))
+            .filter(schema::refresh_tokens::expires_at.le(now)),
+    )
+    .execute(conn)?;
+    diesel::insert_into(schema::refresh_tokens::table)
+        .values((
+            schema::refresh_tokens::token_hash.eq(hash(&refresh_token)),
+            schema::refresh_tokens::user_id.eq(user_id),
+            schema::refresh_tokens::created_at.eq(now),
+            schema::refresh_tokens::expires_at
+                .eq(now + Duration::seconds(refresh_token_lifetime(settings))),
+        ))
+        .execute(conn)?;
+    let valid_after = schema::users::table
+        .find(user_id)
+        .select(schema::users::tokens_valid_after)
+        .get_result::<Option<NaiveDateTime>>(conn)?;
+    Ok(models::Token {
+        token: Some(access_token(
+            settings,
+            keys,
+            user_id,
+            is_admin(conn, user_id)?,
+            issued_at(valid_after),
+        )?),
+        expires_in: Some(
+            settings
+                .access_token_lifetime
+                .unwrap_or(ACCESS_TOKEN_LIFETIME),
+        ),
+        refresh_token: Some(refresh_token),
+    })
+}
+
+/// Replaces a refresh token with a new pair of tokens. Using one of a disabled user
+/// revokes all tokens of the user.
+pub fn refresh(
+    conn: &SqliteConnection,
+    settings: &Settings,
+    keys: &JwtKeys,
+    refresh_token: &str,
+) -> Result<models::Token, TokenError> {
+    // the revocation is committed, so the failure is only returned afterwards
+    let token = conn.transaction::<_, TokenError, _>(|| {
+        let token_hash = hash(refresh_token);
+        let user_id = schema::refresh_tokens::table
+            .find(&token_hash)
+            .filter(schema::refresh_tokens::expires_at.gt(Utc::now().naive_utc()))
+            .select(schema::refresh_tokens::user_id)
+            .get_result::<String>(conn)
+            .optional()?
+            .ok_or(TokenError::InvalidRefreshToken)?;
+        let disabled = schema::users::table
+            .find(&user_id)
+            .select(schema::users::disabled)
+            .get_result::<bool>(conn)?;
+        if disabled {
+            revoke_all(conn, &user_id)?;
+            return Ok(None);
+        }
+        diesel::delete(schema::refresh_tokens::table.find(&token_hash)).execute(conn)?;
+        issue(conn, settings, keys, &user_id).map(Some)
+    })?;
+    token.ok_or(TokenError::Disabled)
+}
+
+/// Makes a refresh token of the user unusable, unknown and expired ones are left alone
+pub fn revoke(
+    conn: &SqliteConnection,
+    user_id: &str,
+    refresh_token: &str,
+) -> Result<(), TokenError> {
+    diesel::delete(
+        schema::refresh_tokens::table
+            .find(hash(refresh_token))
+            .filter(schema::refresh_tokens::user_id.eq(user_id)),
+    )
+    .execute(conn)?;
+    Ok(())
+}
+
+/// Rejects an access token from now on, until it expires anyway
+pub fn revoke_access(
+    conn: &SqliteConnection,
+    settings: &Settings,
+    claims: &Claims,
+) -> Result<(), TokenError> {
+    let (jti, user_id) = match (&claims.jti, &claims.sub) {
+        (Some(jti), Some(user_id)) => (jti, user_id),
+        // tokens without id can only be revoked together with all others
+        _ => return Ok(()),
+    };
+    let now = Utc::now();
+    let expires_at = claims.exp.unwrap_or_else(|| {
+        now.timestamp()
+            + settings
+                .access_token_lifetime
+                .unwrap_or(ACCESS_TOKEN_LIFETIME)
+    });
+    diesel::delete(
+        schema::revoked_tokens::table
+            .filter(schema::revoked_tokens::expires_at.le(now.naive_utc())),
+    )
+    .execute(conn)?;
+    diesel::replace_into(schema::revoked_tokens::table)
+        .values((
+            schema::revoked_tokens::jti.eq(jti),
+            schema::revoked_tokens::user_id.eq(user_id),
+            schema::revoked_tokens::expires_at.eq(NaiveDateTime::from_timestamp(expires_at, 0)),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Invalidates every access and refresh token of the user issued so far
+pub fn revoke_all(conn: &SqliteConnection, user_id: &str) -> Result<(), diesel::result::Error> {
+    // token times only have seconds, so tokens issued in this second are rejected as well
+    let now = NaiveDateTime::from_timestamp(Utc::now().timestamp(), 0);
+    diesel::update(schema::users::table.find(user_id))
+        .set(schema::users::tokens_valid_after.eq(now))
+        .execute(conn)?;
+    diesel::delete(
+        schema::refresh_tokens::table.filter(schema::refresh_tokens::user_id.eq(user_id)),
+    )
+    .execute(conn)?;
+    Ok(())
+}
+
+fn is_revoked(conn: &SqliteConnection, claims: &Claims) -> Result<bool, diesel::result::Error> {
+    if let Some(jti) = &claims.jti {
+        if diesel::select(diesel::dsl::exists(schema::revoked_tokens::table.find(jti)))
+            .get_result::<bool>(conn)?
+        {
+            return Ok(true);
+        }
+    }
+    let valid_after = match &claims.sub {
+        Some(user_id) => match schema::users::table
+            .find(user_id)
+            .select((schema::users::tokens_valid_after, schema::users::disabled))
+            .get_result::<(Option<NaiveDateTime>, bool)>(conn)
+            .optional()?
+        {
+            // disabled users are signed out even of sessions started in the second they were
+            Some((_, true)) => return Ok(true),
+            Some((valid_after, false)) => valid_after,
+            None => None,
+        },
+        None => None,
+    };
+    Ok(match (valid_after, claims.iat) {
+        (Some(valid_after), Some(iat)) => iat <= valid_after.timestamp(),
+        (Some(_), None) => true,
+        (None, _) => false,
+    })
+}
+
+/// Checks the revoked tokens, disabled users and the time the tokens of the user are valid
+/// after, lets the JWT middleware reject tokens before they expire
+pub struct RevocationList;
+
+impl RevocationCheck for RevocationList {
+    fn is_revoked(&self, req: &ServiceRequest, claims: &Claims) -> bool {
+        let extensions = req.extensions();
+        let conn =
+            match extensions.get::<r2d2::PooledConnection<ConnectionManager<SqliteConnection>>>() {
+                Some(conn) => conn,
+                None => {
+                    log::error!("No database connection for checking token revocation");
+                    return true;
+                }
+            };
+        // tokens are refused if it's unknown whether they are revoked
+        is_revoked(conn, claims).unwrap_or_else(|e| {
+            log::error!("Couldn't check token revocation: {}", e);
+            true
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{seed, test_connection};
+    use diesel::connection::SimpleConnection;
+
+    fn claims(iat: i64, jti: &str) -> Claims {
+        Claims {
+            sub: Some("user".to_string()),
+            exp: Some(iat + ACCESS_TOKEN_LIFETIME),
+            iat: Some(iat),
+            jti: Some(jti.to_string()),
+            all: json!({}),
+        }
+    }
+
+    fn valid_after(conn: &SqliteConnection) -> Option<NaiveDateTime> {
+        schema::users::table
+            .find("user")
+            .select(schema::users::tokens_valid_after)
+            .get_result(conn)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_revoke_all() {
+        let conn = test_connection();
+        seed(&conn);
+        let now = Utc::now().timestamp();
+        assert!(!is_revoked(&conn, &claims(now, "a")).unwrap());
+
+        revoke_all(&conn, "user").unwrap();
+        // also tokens issued in the same second as the revocation
+        assert!(is_revoked(&conn, &claims(now - 1, "a")).unwrap());
+        assert!(is_revoked(&conn, &claims(now, "a")).unwrap());
+        let after = issued_at(valid_after(&conn));
+        assert!(after > now);
+        assert!(!is_revoked(&conn, &claims(after, "b")).unwrap());
+
+        conn.batch_execute("UPDATE users SET disabled = 1").unwrap();
+        assert!(is_revoked(&conn, &claims(after, "b")).unwrap());
+    }
+}
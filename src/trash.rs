@@ -0,0 +1,637 @@
+use crate::blobs;
+use crate::handlers::{courses, subtasks, tasks, worksheets};
+use crate::integrity;
+use crate::models::{self, ObjectType};
+use crate::revisions::{RevisionStore, SubtaskRevisions, TaskRevisions, WorksheetRevisions};
+use crate::schema;
+use diesel::{
+    prelude::*,
+    r2d2::{self, ConnectionManager},
+    SqliteConnection,
+};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// How often expired trash entries are looked for
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum TrashedObject {
+    Course(models::Course),
+    Worksheet(models::Worksheet),
+    Task(models::Task),
+    Subtask(models::Subtask),
+    Database(TrashedDatabase),
+}
+
+/// The dump of a trashed database stays in its blob, which the trash entry refers to.
+/// Entries from before dumps were stored as blobs hold the dump itself.
+#[derive(Serialize, Deserialize)]
+struct TrashedDatabase {
+    id: String,
+    name: String,
+    #[serde(rename = "database", default, skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
+}
+
+/// An object the trashed object was part of, e.g. the worksheet of a deleted task
+#[derive(Serialize, Deserialize)]
+struct ParentLink {
+    parent_id: String,
+    position: Option<i32>,
+}
+
+/// Everything needed to put a deleted object back where it was
+#[derive(Serialize, Deserialize)]
+struct TrashContent {
+    object: TrashedObject,
+    parents: Vec<ParentLink>,
+    tags: Vec<String>,
+}
+
+/// Why a trashed object can't be put back
+#[derive(Debug)]
+pub enum RestoreError {
+    Diesel(diesel::result::Error),
+    /// The object needs another one which is gone, with a message naming it
+    MissingReference(String),
+}
+
+impl From<diesel::result::Error> for RestoreError {
+    fn from(val: diesel::result::Error) -> RestoreError {
+        RestoreError::Diesel(val)
+    }
+}
+
+/// Removes an object and all links to it, and keeps a copy in the trash.
+/// Access rows and revisions are kept until the object is purged.
+pub fn move_to_trash(
+    conn: &SqliteConnection,
+    id: &str,
+    object_type: ObjectType,
+    user_id: &str,
+) -> Result<(), diesel::result::Error> {
+    let mut content_hash = None;
+    let (object, name) = match object_type {
+        ObjectType::COURSE => {
+            let course = courses::load_course(conn, id)?;
+            let name = Some(course.name.clone());
+            (TrashedObject::Course(course), name)
+        }
+        ObjectType::WORKSHEET => {
+            let worksheet = worksheets::load_worksheet(conn, id)?;
+            let name = worksheet.name.clone();
+            (TrashedObject::Worksheet(worksheet), name)
+        }
+        ObjectType::TASK => (TrashedObject::Task(tasks::load_task(conn, id)?), None),
+        ObjectType::SUBTASK => {
+            let subtask = subtasks::load_subtask(conn, id)?;
+            let name = Some(subtask.instruction.clone());
+            (TrashedObject::Subtask(subtask), name)
+        }
+        ObjectType::DATABASE => {
+            let database = schema::databases::table
+                .find(id)
+                .select(models::QueryableDatabase::COLUMNS)
+                .get_result::<models::QueryableDatabase>(conn)?;
+            let name = Some(database.name.clone());
+            content_hash = Some(database.content_hash);
+            (
+                TrashedObject::Database(TrashedDatabase {
+                    id: database.id,
+                    name: database.name,
+                    content: None,
+                }),
+                name,
+            )
+        }
+    };
+    let content = TrashContent {
+        object,
+        parents: load_parents(conn, id, object_type)?,
+        tags: load_tags(conn, id, object_type)?,
+    };
+
+    // the entry refers to the blob of a database before the database releases it
+    diesel::insert_into(schema::trash::table)
+        .values(models::TrashEntry {
+            object_id: id.to_string(),
+            object_type,
+            name,
+            deleted_by: user_id.to_string(),
+            deleted_at: chrono::Utc::now().naive_utc(),
+            content: serde_json::to_string(&content)
+                .map_err(|e| diesel::result::Error::SerializationError(Box::new(e)))?,
+            content_hash,
+        })
+        .execute(conn)?;
+
+    remove(conn, id, object_type)
+}
+
+fn load_parents(
+    conn: &SqliteConnection,
+    id: &str,
+    object_type: ObjectType,
+) -> Result<Vec<ParentLink>, diesel::result::Error> {
+    let parents = match object_type {
+        ObjectType::WORKSHEET => schema::worksheets_in_courses::table
+            .filter(schema::worksheets_in_courses::worksheet_id.eq(id))
+            .select((
+                schema::worksheets_in_courses::course_id,
+                schema::worksheets_in_courses::position,
+            ))
+            .load::<(String, Option<i32>)>(conn)?,
+        ObjectType::TASK => schema::tasks_in_worksheets::table
+            .filter(schema::tasks_in_worksheets::task_id.eq(id))
+            .select((
+                schema::tasks_in_worksheets::worksheet_id,
+                schema::tasks_in_worksheets::position,
+            ))
+            .load::<(String, Option<i32>)>(conn)?,
+        ObjectType::SUBTASK => schema::subtasks_in_tasks::table
+            .filter(schema::subtasks_in_tasks::subtask_id.eq(id))
+            .select((
+                schema::subtasks_in_tasks::task_id,
+                schema::subtasks_in_tasks::position,
+            ))
+            .load::<(String, i32)>(conn)?
+            .into_iter()
+            .map(|(parent_id, position)| (parent_id, Some(position)))
+            .collect(),
+        ObjectType::COURSE | ObjectType::DATABASE => Vec::new(),
+    };
+    Ok(parents
+        .into_iter()
+        .map(|(parent_id, position)| ParentLink {
+            parent_id,
+            position,
+        })
+        .collect())
+}
+
+fn load_tags(
+    conn: &SqliteConnection,
+    id: &str,
+    object_type: ObjectType,
+) -> Result<Vec<String>, diesel::result::Error> {
+    match object_type {
+        ObjectType::SUBTASK => schema::subtasks_in_tags::table
+            .filter(schema::subtasks_in_tags::subtask_id.eq(id))
+            .select(schema::subtasks_in_tags::tag_id)
+            .load::<String>(conn),
+        ObjectType::DATABASE => schema::databases_in_tags::table
+            .filter(schema::databases_in_tags::database_id.eq(id))
+            .select(schema::databases_in_tags::tag_id)
+            .load::<String>(conn),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Deletes the row of an object together with links from and to it
+pub fn remove(
+    conn: &SqliteConnection,
+    id: &str,
+    object_type: ObjectType,
+) -> Result<(), diesel::result::Error> {
+    match object_type {
+        ObjectType::COURSE => {
+            diesel::delete(
+                schema::worksheets_in_courses::table
+                    .filter(schema::worksheets_in_courses::course_id.eq(id)),
+            )
+            .execute(conn)?;
+            diesel::delete(schema::courses::table.find(id)).execute(conn)?;
+        }
+        ObjectType::WORKSHEET => {
+            diesel::delete(
+                schema::tasks_in_worksheets::table
+                    .filter(schema::tasks_in_worksheets::worksheet_id.eq(id)),
+            )
+            .execute(conn)?;
+            diesel::delete(
+                schema::worksheets_in_courses::table
+                    .filter(schema::worksheets_in_courses::worksheet_id.eq(id)),
+            )
+            .execute(conn)?;
+            diesel::delete(schema::worksheets::table.find(id)).execute(conn)?;
+        }
+        ObjectType::TASK => {
+            diesel::delete(
+                schema::subtasks_in_tasks::table.filter(schema::subtasks_in_tasks::task_id.eq(id)),
+            )
+            .execute(conn)?;
+            diesel::delete(
+                schema::tasks_in_worksheets::table
+                    .filter(schema::tasks_in_worksheets::task_id.eq(id)),
+            )
+            .execute(conn)?;
+            diesel::delete(schema::tasks::table.find(id)).execute(conn)?;
+        }
+        ObjectType::SUBTASK => {
+            diesel::delete(
+                schema::subtasks_in_tasks::table
+                    .filter(schema::subtasks_in_tasks::subtask_id.eq(id)),
+            )
+            .execute(conn)?;
+            diesel::delete(
+                schema::subtasks_in_tags::table.filter(schema::subtasks_in_tags::subtask_id.eq(id)),
+            )
+            .execute(conn)?;
+            diesel::delete(schema::subtasks::table.find(id)).execute(conn)?;
+        }
+        ObjectType::DATABASE => {
+            diesel::delete(
+                schema::databases_in_tags::table
+                    .filter(schema::databases_in_tags::database_id.eq(id)),
+            )
+            .execute(conn)?;
+            diesel::delete(schema::databases::table.find(id)).execute(conn)?;
+        }
+    }
+    Ok(())
+}
+
+/// Puts a trashed object back. Children and parents which have been deleted in the
+/// meantime are skipped, the object is inserted at its old position in its parents.
+/// A task can't be restored without its database.
+pub fn restore(conn: &SqliteConnection, id: &str) -> Result<(), RestoreError> {
+    let entry = schema::trash::table
+        .find(id)
+        .get_result::<models::TrashEntry>(conn)?;
+    let content = serde_json::from_str::<TrashContent>(&entry.content)
+        .map_err(|e| diesel::result::Error::DeserializationError(Box::new(e)))?;
+
+    let parent_type = match content.object {
+        TrashedObject::Course(course) => {
+            diesel::insert_into(schema::courses::table)
+                .values(models::QueryableCourse::from_course(course.clone()))
+                .execute(conn)?;
+            for (position, worksheet_id) in
+                integrity::existing(conn, ObjectType::WORKSHEET, &course.worksheets)?
+                    .into_iter()
+                    .enumerate()
+            {
+                diesel::insert_into(schema::worksheets_in_courses::table)
+                    .values(models::WorksheetsInCourse {
+                        worksheet_id,
+                        course_id: id.to_string(),
+                        position: position as i32,
+                    })
+                    .execute(conn)?;
+            }
+            None
+        }
+        TrashedObject::Worksheet(worksheet) => {
+            diesel::insert_into(schema::worksheets::table)
+                .values(models::QueryableWorksheet::from_worksheet(
+                    worksheet.clone(),
+                ))
+                .execute(conn)?;
+            for (position, task_id) in
+                integrity::existing(conn, ObjectType::TASK, &worksheet.tasks)?
+                    .into_iter()
+                    .enumerate()
+            {
+                diesel::insert_into(schema::tasks_in_worksheets::table)
+                    .values(models::TasksInWorksheet {
+                        task_id,
+                        worksheet_id: id.to_string(),
+                        position: position as i32,
+                    })
+                    .execute(conn)?;
+            }
+            Some(ObjectType::COURSE)
+        }
+        TrashedObject::Task(task) => {
+            let database = std::slice::from_ref(&task.database_id);
+            if integrity::existing(conn, ObjectType::DATABASE, database)?.is_empty() {
+                let trashed = diesel::select(diesel::dsl::exists(
+                    schema::trash::table.find(&task.database_id),
+                ))
+                .get_result::<bool>(conn)?;
+                return Err(RestoreError::MissingReference(if trashed {
+                    format!(
+                        "The database {} of the task is in the trash, it has to be restored first.",
+                        task.database_id
+                    )
+                } else {
+                    format!(
+                        "The database {} of the task has been deleted for good.",
+                        task.database_id
+                    )
+                }));
+            }
+            diesel::insert_into(schema::tasks::table)
+                .values(models::QueryableTask::from_task(task.clone()))
+                .execute(conn)?;
+            for (position, subtask_id) in
+                integrity::existing(conn, ObjectType::SUBTASK, &task.subtasks)?
+                    .into_iter()
+                    .enumerate()
+            {
+                diesel::insert_into(schema::subtasks_in_tasks::table)
+                    .values(models::SubtasksInTask {
+                        subtask_id,
+                        task_id: id.to_string(),
+                        position: position as i32,
+                    })
+                    .execute(conn)?;
+            }
+            Some(ObjectType::WORKSHEET)
+        }
+        TrashedObject::Subtask(subtask) => {
+            diesel::insert_into(schema::subtasks::table)
+                .values(subtask)
+                .execute(conn)?;
+            Some(ObjectType::TASK)
+        }
+        TrashedObject::Database(database) => {
+            match (database.content, entry.content_hash.clone()) {
+                (_, Some(content_hash)) => {
+                    diesel::insert_into(schema::databases::table)
+                        .values(models::QueryableDatabase {
+                            id: database.id,
+                            name: database.name,
+                            content_hash,
+                        })
+                        .execute(conn)?;
+                }
+                (Some(content), None) => blobs::insert_database(
+                    conn,
+                    models::Database {
+                        id: database.id,
+                        name: database.name,
+                        content,
+                    },
+                )?,
+                (None, None) => return Err(diesel::result::Error::NotFound.into()),
+            }
+            None
+        }
+    };
+
+    if let Some(parent_type) = parent_type {
+        let parent_ids: Vec<String> = content
+            .parents
+            .iter()
+            .map(|parent| parent.parent_id.clone())
+            .collect();
+        let parent_ids = integrity::existing(conn, parent_type, &parent_ids)?;
+        for parent in content
+            .parents
+            .into_iter()
+            .filter(|parent| parent_ids.contains(&parent.parent_id))
+        {
+            insert_into_parent(conn, id, entry.object_type, parent)?;
+        }
+    }
+
+    for tag_id in content.tags {
+        let tag_exists = diesel::select(diesel::dsl::exists(schema::tags::table.find(&tag_id)))
+            .get_result::<bool>(conn)?;
+        if !tag_exists {
+            continue;
+        }
+        match entry.object_type {
+            ObjectType::SUBTASK => diesel::insert_into(schema::subtasks_in_tags::table)
+                .values(models::SubtasksInTag {
+                    subtask_id: id.to_string(),
+                    tag_id,
+                })
+                .execute(conn)?,
+            _ => diesel::insert_into(schema::databases_in_tags::table)
+                .values(models::DatabasesInTag {
+                    database_id: id.to_string(),
+                    tag_id,
+                })
+                .execute(conn)?,
+        };
+    }
+
+    diesel::delete(schema::trash::table.find(id)).execute(conn)?;
+    Ok(())
+}
+
+/// Re-creates a link at its old position, moving the objects behind it one step back
+fn insert_into_parent(
+    conn: &SqliteConnection,
+    id: &str,
+    object_type: ObjectType,
+    parent: ParentLink,
+) -> Result<(), diesel::result::Error> {
+    match object_type {
+        ObjectType::WORKSHEET => {
+            if let Some(position) = parent.position {
+                diesel::update(
+                    schema::worksheets_in_courses::table
+                        .filter(schema::worksheets_in_courses::course_id.eq(&parent.parent_id))
+                        .filter(schema::worksheets_in_courses::position.ge(position)),
+                )
+                .set(
+                    schema::worksheets_in_courses::position
+                        .eq(schema::worksheets_in_courses::position + 1),
+                )
+                .execute(conn)?;
+            }
+            diesel::insert_into(schema::worksheets_in_courses::table)
+                .values((
+                    schema::worksheets_in_courses::worksheet_id.eq(id),
+                    schema::worksheets_in_courses::course_id.eq(&parent.parent_id),
+                    schema::worksheets_in_courses::position.eq(parent.position),
+                ))
+                .execute(conn)?;
+        }
+        ObjectType::TASK => {
+            if let Some(position) = parent.position {
+                diesel::update(
+                    schema::tasks_in_worksheets::table
+                        .filter(schema::tasks_in_worksheets::worksheet_id.eq(&parent.parent_id))
+                        .filter(schema::tasks_in_worksheets::position.ge(position)),
+                )
+                .set(
+                    schema::tasks_in_worksheets::position
+                        .eq(schema::tasks_in_worksheets::position + 1),
+                )
+                .execute(conn)?;
+            }
+            diesel::insert_into(schema::tasks_in_worksheets::table)
+                .values((
+                    schema::tasks_in_worksheets::task_id.eq(id),
+                    schema::tasks_in_worksheets::worksheet_id.eq(&parent.parent_id),
+                    schema::tasks_in_worksheets::position.eq(parent.position),
+                ))
+                .execute(conn)?;
+        }
+        ObjectType::SUBTASK => {
+            let position = parent.position.unwrap_or(0);
+            diesel::update(
+                schema::subtasks_in_tasks::table
+                    .filter(schema::subtasks_in_tasks::task_id.eq(&parent.parent_id))
+                    .filter(schema::subtasks_in_tasks::position.ge(position)),
+            )
+            .set(schema::subtasks_in_tasks::position.eq(schema::subtasks_in_tasks::position + 1))
+            .execute(conn)?;
+            diesel::insert_into(schema::subtasks_in_tasks::table)
+                .values(models::SubtasksInTask {
+                    subtask_id: id.to_string(),
+                    task_id: parent.parent_id,
+                    position,
+                })
+                .execute(conn)?;
+        }
+        ObjectType::COURSE | ObjectType::DATABASE => {}
+    }
+    Ok(())
+}
+
+/// Deletes a trashed object for good, together with everything referring to it
+pub fn purge(conn: &SqliteConnection, id: &str) -> Result<(), diesel::result::Error> {
+    let entry = schema::trash::table
+        .find(id)
+        .get_result::<models::TrashEntry>(conn)?;
+
+    match entry.object_type {
+        ObjectType::WORKSHEET => WorksheetRevisions::delete_all(conn, id)?,
+        ObjectType::TASK => TaskRevisions::delete_all(conn, id)?,
+        ObjectType::SUBTASK => SubtaskRevisions::delete_all(conn, id)?,
+        ObjectType::COURSE | ObjectType::DATABASE => {}
+    }
+    diesel::delete(schema::aliases::table.filter(schema::aliases::object_id.eq(id)))
+        .execute(conn)?;
+
+    // a published copy can't be cloned anymore
+    let catalog_entries = schema::catalog_entries::table
+        .filter(schema::catalog_entries::object_id.eq(id))
+        .select(schema::catalog_entries::id)
+        .load::<String>(conn)?;
+    diesel::delete(
+        schema::access::table.filter(schema::access::object_id.eq_any(&catalog_entries)),
+    )
+    .execute(conn)?;
+    diesel::delete(
+        schema::catalog_entries::table.filter(schema::catalog_entries::object_id.eq(id)),
+    )
+    .execute(conn)?;
+
+    diesel::delete(schema::access::table.filter(schema::access::object_id.eq(id))).execute(conn)?;
+    diesel::delete(schema::trash::table.find(id)).execute(conn)?;
+    Ok(())
+}
+
+/// Purges everything that has been in the trash for longer than the given number of days
+pub fn purge_expired(conn: &SqliteConnection, days: i64) -> Result<usize, diesel::result::Error> {
+    let cutoff = chrono::Utc::now().naive_utc() - chrono::Duration::days(days);
+    conn.transaction::<usize, diesel::result::Error, _>(|| {
+        let expired = schema::trash::table
+            .filter(schema::trash::deleted_at.lt(cutoff))
+            .select(schema::trash::object_id)
+            .load::<String>(conn)?;
+        for id in expired.iter() {
+            purge(conn, id)?;
+        }
+        Ok(expired.len())
+    })
+}
+
+/// Starts a thread which regularly empties the trash of expired objects
+pub fn spawn_purge_thread(pool: r2d2::Pool<ConnectionManager<SqliteConnection>>, days: i64) {
+    std::thread::spawn(move || loop {
+        match pool.get() {
+            Ok(conn) => match purge_expired(&conn, days) {
+                Ok(0) => {}
+                Ok(count) => log::info!("Purged {} objects from the trash", count),
+                Err(e) => log::error!("Couldn't purge trash: {}", e),
+            },
+            Err(e) => log::error!("Couldn't get database connection to purge trash: {}", e),
+        }
+        std::thread::sleep(PURGE_INTERVAL);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{seed, test_connection};
+    use crate::ordering::{ChildLinks, CourseWorksheets};
+
+    fn worksheets(conn: &SqliteConnection) -> Vec<String> {
+        CourseWorksheets::children(conn, "course").unwrap()
+    }
+
+    fn trashed(conn: &SqliteConnection, id: &str) -> bool {
+        diesel::select(diesel::dsl::exists(schema::trash::table.find(id)))
+            .get_result::<bool>(conn)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_restore() {
+        let conn = test_connection();
+        seed(&conn);
+
+        move_to_trash(&conn, "b", ObjectType::WORKSHEET, "user").unwrap();
+        assert_eq!(worksheets(&conn), vec!["a", "c", "d"]);
+        assert!(trashed(&conn, "b"));
+
+        restore(&conn, "b").unwrap();
+        assert_eq!(worksheets(&conn), vec!["a", "b", "c", "d"]);
+        assert!(!trashed(&conn, "b"));
+    }
+
+    #[test]
+    fn test_restore_after_purge() {
+        let conn = test_connection();
+        seed(&conn);
+
+        move_to_trash(&conn, "b", ObjectType::WORKSHEET, "user").unwrap();
+        purge(&conn, "b").unwrap();
+        match restore(&conn, "b") {
+            Err(RestoreError::Diesel(diesel::result::Error::NotFound)) => {}
+            result => panic!("restored a purged object: {:?}", result),
+        }
+        assert_eq!(worksheets(&conn), vec!["a", "c", "d"]);
+    }
+
+    #[test]
+    fn test_restore_task_without_database() {
+        let conn = test_connection();
+        seed(&conn);
+
+        move_to_trash(&conn, "task", ObjectType::TASK, "user").unwrap();
+        move_to_trash(&conn, "database", ObjectType::DATABASE, "user").unwrap();
+        match restore(&conn, "task") {
+            Err(RestoreError::MissingReference(message)) => assert!(message.contains("trash")),
+            result => panic!("restored a task without its database: {:?}", result),
+        }
+        assert!(trashed(&conn, "task"));
+
+        purge(&conn, "database").unwrap();
+        match restore(&conn, "task") {
+            Err(RestoreError::MissingReference(message)) => {
+                assert!(message.contains("for good"))
+            }
+            result => panic!("restored a task without its database: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_restore_task_after_database() {
+        let conn = test_connection();
+        seed(&conn);
+
+        move_to_trash(&conn, "task", ObjectType::TASK, "user").unwrap();
+        move_to_trash(&conn, "database", ObjectType::DATABASE, "user").unwrap();
+        restore(&conn, "database").unwrap();
+        restore(&conn, "task").unwrap();
+        assert_eq!(
+            schema::tasks::table
+                .find("task")
+                .select(schema::tasks::database_id)
+                .get_result::<String>(&conn)
+                .unwrap(),
+            "database"
+        );
+    }
+}
@@ -8,4 +8,7 @@ pub struct Account {
     pub username: String,
     #[serde(rename = "password")]
     pub password: String,
+    /// Needed for registering while registration is closed
+    #[serde(rename = "invitation", default, skip_serializing_if = "Option::is_none")]
+    pub invitation: Option<String>,
 }
@@ -0,0 +1,16 @@
+use crate::schema::blobs;
+use diesel::{Insertable, Queryable};
+
+/// Blob: A database dump, stored once for all databases with the same content
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[table_name = "blobs"]
+pub struct Blob {
+    /// Hex encoded BLAKE2b hash of the uncompressed content
+    pub hash: String,
+    pub content: Vec<u8>,
+    pub compressed: bool,
+    /// Size of the uncompressed content in bytes
+    pub size: i64,
+    /// Number of databases and trash entries referring to the blob, kept by triggers
+    pub ref_count: i32,
+}
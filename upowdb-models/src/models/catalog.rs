@@ -0,0 +1,30 @@
+use crate::models::ObjectType;
+use crate::schema::catalog_entries;
+use chrono::NaiveDateTime;
+use diesel::{Insertable, Queryable};
+use serde::{Deserialize, Serialize};
+
+/// CatalogEntry: A task, worksheet or course a teacher published to the shared catalog.
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Insertable)]
+#[table_name = "catalog_entries"]
+pub struct CatalogEntry {
+    pub id: String,
+    pub object_id: String,
+    pub object_type: ObjectType,
+    pub title: String,
+    pub description: String,
+    pub license: String,
+    pub published_by: String,
+    pub published_at: NaiveDateTime,
+}
+
+/// CatalogEntryRequest: This struct is passed to the catalog endpoint for publishing an object,
+/// and for changing the description of an existing entry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CatalogEntryRequest {
+    pub object_id: String,
+    pub object_type: ObjectType,
+    pub title: String,
+    pub description: String,
+    pub license: String,
+}
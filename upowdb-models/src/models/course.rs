@@ -25,6 +25,10 @@ pub struct QueryableCourse {
 }
 
 impl QueryableCourse {
+    /// The columns the struct is loaded from, the table also holds the version
+    pub const COLUMNS: (courses::id, courses::name, courses::description) =
+        (courses::id, courses::name, courses::description);
+
     pub fn from_course(course: Course) -> Self {
         Self {
             id: course.id,
@@ -13,7 +13,7 @@ use diesel::{Insertable, Queryable};
 /// Database : The root of the Database type's schema.
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, Queryable, Insertable, Clone, AsChangeset)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Database {
     #[serde(rename = "id")]
     pub id: String,
@@ -22,3 +22,30 @@ pub struct Database {
     #[serde(rename = "database")]
     pub content: String,
 }
+
+/// The row of a database, its dump is stored in the blob with the hash
+#[derive(Debug, Queryable, Insertable, AsChangeset)]
+#[table_name = "databases"]
+pub struct QueryableDatabase {
+    pub id: String,
+    pub name: String,
+    pub content_hash: String,
+}
+
+impl QueryableDatabase {
+    /// The columns the struct is loaded from, the table also holds the version
+    pub const COLUMNS: (databases::id, databases::name, databases::content_hash) =
+        (databases::id, databases::name, databases::content_hash);
+}
+
+/// DatabaseSummary : A database without its dump, for listing many databases at once
+#[derive(Debug, Serialize, Deserialize, Clone, Queryable)]
+pub struct DatabaseSummary {
+    #[serde(rename = "id")]
+    pub id: String,
+    #[serde(rename = "name")]
+    pub name: String,
+    /// Size of the dump in bytes
+    #[serde(rename = "size")]
+    pub size: i64,
+}
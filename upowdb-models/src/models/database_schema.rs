@@ -0,0 +1,55 @@
+use serde::{Deserialize, Serialize};
+
+/// DatabaseSchema: The tables and views of a database, e.g. for building queries.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatabaseSchema {
+    #[serde(rename = "tables")]
+    pub tables: Vec<TableSchema>,
+    #[serde(rename = "views")]
+    pub views: Vec<ViewSchema>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableSchema {
+    #[serde(rename = "name")]
+    pub name: String,
+    #[serde(rename = "columns")]
+    pub columns: Vec<ColumnSchema>,
+    #[serde(rename = "primary_key")]
+    pub primary_key: Vec<String>,
+    #[serde(rename = "foreign_keys")]
+    pub foreign_keys: Vec<ForeignKeySchema>,
+    #[serde(rename = "row_count")]
+    pub row_count: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ViewSchema {
+    #[serde(rename = "name")]
+    pub name: String,
+    #[serde(rename = "columns")]
+    pub columns: Vec<ColumnSchema>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ColumnSchema {
+    #[serde(rename = "name")]
+    pub name: String,
+    /// The declared type, which is empty for columns without one
+    #[serde(rename = "type")]
+    pub column_type: String,
+    #[serde(rename = "nullable")]
+    pub nullable: bool,
+    #[serde(rename = "default", skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForeignKeySchema {
+    #[serde(rename = "columns")]
+    pub columns: Vec<String>,
+    #[serde(rename = "referenced_table")]
+    pub referenced_table: String,
+    #[serde(rename = "referenced_columns")]
+    pub referenced_columns: Vec<String>,
+}
@@ -0,0 +1,100 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+/// GeneratorRequest: This struct is passed to the generate endpoint for creating a database
+/// filled with sample data. The same seed always leads to the same data.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeneratorRequest {
+    #[serde(rename = "name")]
+    pub name: String,
+    #[serde(rename = "seed", default)]
+    pub seed: Option<u64>,
+    #[serde(rename = "tables")]
+    pub tables: Vec<GeneratedTable>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeneratedTable {
+    #[serde(rename = "name")]
+    pub name: String,
+    #[serde(rename = "rows")]
+    pub rows: u32,
+    #[serde(rename = "columns")]
+    pub columns: Vec<GeneratedColumn>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeneratedColumn {
+    #[serde(rename = "name")]
+    pub name: String,
+    /// The declared type, `TEXT` if none is given
+    #[serde(rename = "type", default)]
+    pub column_type: Option<String>,
+    #[serde(rename = "primary_key", default)]
+    pub primary_key: bool,
+    #[serde(rename = "nullable", default)]
+    pub nullable: bool,
+    /// Share of NULL values in a nullable column, between 0 and 1
+    #[serde(rename = "null_ratio", default)]
+    pub null_ratio: Option<f64>,
+    /// Values of a foreign key are taken from the referenced column
+    #[serde(rename = "references", default)]
+    pub references: Option<ColumnReference>,
+    /// How values are made up, a default is chosen from the type if none is given
+    #[serde(rename = "generator", default)]
+    pub generator: Option<ValueGenerator>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ColumnReference {
+    #[serde(rename = "table")]
+    pub table: String,
+    #[serde(rename = "column")]
+    pub column: String,
+}
+
+/// ValueGenerator: Hint for the kind of values a column gets
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ValueGenerator {
+    /// Consecutive numbers, e.g. for primary keys
+    Sequence {
+        #[serde(default = "default_start")]
+        start: i64,
+    },
+    /// Numbers between `min` and `max`, with `decimals` digits after the point
+    Range {
+        min: f64,
+        max: f64,
+        #[serde(default)]
+        decimals: u32,
+    },
+    /// One of the given values
+    Enum {
+        values: Vec<String>,
+    },
+    Name,
+    FirstName,
+    LastName,
+    Email,
+    City,
+    /// Dates between `from` and `to`
+    Date {
+        from: NaiveDate,
+        to: NaiveDate,
+    },
+    Boolean,
+    /// Sentences of made up words
+    Text {
+        #[serde(default = "default_words")]
+        words: u32,
+    },
+}
+
+fn default_start() -> i64 {
+    1
+}
+
+fn default_words() -> u32 {
+    5
+}
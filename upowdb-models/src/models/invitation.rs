@@ -0,0 +1,31 @@
+use chrono::NaiveDateTime;
+use diesel::Queryable;
+use serde::{Deserialize, Serialize};
+
+/// Invitation: A code for registering while registration is closed
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable)]
+pub struct Invitation {
+    #[serde(rename = "code")]
+    pub code: String,
+    #[serde(rename = "created_by")]
+    pub created_by: String,
+    #[serde(rename = "created_at")]
+    pub created_at: NaiveDateTime,
+    #[serde(rename = "expires_at", skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<NaiveDateTime>,
+    #[serde(rename = "max_uses")]
+    pub max_uses: i32,
+    #[serde(rename = "uses")]
+    pub uses: i32,
+}
+
+/// InvitationRequest: This struct is passed to the endpoint for minting invitation codes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InvitationRequest {
+    /// The code never expires if this is left out
+    #[serde(rename = "expires_at", default)]
+    pub expires_at: Option<NaiveDateTime>,
+    /// How many accounts may be registered with the code, one by default
+    #[serde(rename = "max_uses", default)]
+    pub max_uses: Option<i32>,
+}
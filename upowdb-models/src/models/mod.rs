@@ -1,11 +1,29 @@
 mod account;
 pub use self::account::Account;
+mod blob;
+pub use self::blob::Blob;
+mod catalog;
+pub use self::catalog::{CatalogEntry, CatalogEntryRequest};
 mod content;
 pub use self::content::Content;
 mod course;
 pub use self::course::{Course, QueryableCourse, WorksheetsInCourse};
 mod database;
-pub use self::database::Database;
+pub use self::database::{Database, DatabaseSummary, QueryableDatabase};
+mod database_schema;
+pub use self::database_schema::{
+    ColumnSchema, DatabaseSchema, ForeignKeySchema, TableSchema, ViewSchema,
+};
+mod generator;
+pub use self::generator::{
+    ColumnReference, GeneratedColumn, GeneratedTable, GeneratorRequest, ValueGenerator,
+};
+mod invitation;
+pub use self::invitation::{Invitation, InvitationRequest};
+mod quota;
+pub use self::quota::{QuotaLimits, QuotaUsage, Usage};
+mod revision;
+pub use self::revision::{QueryableRevision, Revision, RevisionChange};
 mod solution;
 pub use self::solution::{
     MCSolution, MCSolutionResult, PlaintextSolution, PlaintextSolutionResult, SQLSolution,
@@ -13,10 +31,14 @@ pub use self::solution::{
 };
 mod subtask;
 pub use self::subtask::{Subtask, AllowedSQL};
+mod tag;
+pub use self::tag::{DatabasesInTag, QueryableTag, SubtasksInTag, Tag};
 mod task;
 pub use self::task::{QueryableTask, SubtasksInTask, Task};
 mod token;
-pub use self::token::Token;
+pub use self::token::{RefreshRequest, Token};
+mod trash;
+pub use self::trash::TrashEntry;
 mod worksheet;
 pub use self::worksheet::{QueryableWorksheet, TasksInWorksheet, Worksheet};
 mod access;
@@ -24,4 +46,6 @@ pub use self::access::Access;
 pub mod alias;
 pub use self::alias::{Alias, AliasRequest, ObjectType};
 mod user;
-pub use self::user::User;
+pub use self::user::{
+    AccessReassignment, PasswordHashing, PasswordReset, User, UserSummary, UserUpdate,
+};
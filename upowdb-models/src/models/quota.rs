@@ -0,0 +1,34 @@
+use diesel::Queryable;
+use serde::{Deserialize, Serialize};
+
+/// QuotaLimits: The most a user may store, where `None` means unlimited.
+/// Used for the configured defaults and for the limits of single users.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Queryable)]
+pub struct QuotaLimits {
+    #[serde(rename = "database_bytes", default)]
+    pub database_bytes: Option<i64>,
+    #[serde(rename = "objects", default)]
+    pub objects: Option<i64>,
+    #[serde(rename = "aliases", default)]
+    pub aliases: Option<i64>,
+}
+
+/// Usage: What a user stores, counting everything listed in the access table
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Usage {
+    #[serde(rename = "database_bytes")]
+    pub database_bytes: i64,
+    #[serde(rename = "objects")]
+    pub objects: i64,
+    #[serde(rename = "aliases")]
+    pub aliases: i64,
+}
+
+/// QuotaUsage: This struct is returned by the quota endpoints
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuotaUsage {
+    #[serde(rename = "usage")]
+    pub usage: Usage,
+    #[serde(rename = "quota")]
+    pub quota: QuotaLimits,
+}
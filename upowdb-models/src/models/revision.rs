@@ -0,0 +1,50 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+/// Revision: One entry in the history of a subtask, task or worksheet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Revision {
+    #[serde(rename = "revision")]
+    pub revision: i32,
+    #[serde(rename = "user_id", skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+    #[serde(rename = "created_at")]
+    pub created_at: NaiveDateTime,
+    #[serde(rename = "restored_from", skip_serializing_if = "Option::is_none")]
+    pub restored_from: Option<i32>,
+    #[serde(rename = "content")]
+    pub content: serde_json::Value,
+}
+
+#[derive(Debug, Queryable)]
+pub struct QueryableRevision {
+    pub revision: i32,
+    pub user_id: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub restored_from: Option<i32>,
+    pub content: String,
+}
+
+impl QueryableRevision {
+    pub fn into_revision(self) -> serde_json::Result<Revision> {
+        Ok(Revision {
+            revision: self.revision,
+            user_id: self.user_id,
+            created_at: self.created_at,
+            restored_from: self.restored_from,
+            content: serde_json::from_str(&self.content)?,
+        })
+    }
+}
+
+/// RevisionChange: A single value that differs between two revisions.
+/// The path points into the object, e.g. `/content/sql/solution/rows/3`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RevisionChange {
+    #[serde(rename = "path")]
+    pub path: String,
+    #[serde(rename = "from", skip_serializing_if = "Option::is_none")]
+    pub from: Option<serde_json::Value>,
+    #[serde(rename = "to", skip_serializing_if = "Option::is_none")]
+    pub to: Option<serde_json::Value>,
+}
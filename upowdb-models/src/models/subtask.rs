@@ -53,3 +53,20 @@ pub struct Subtask {
     #[serde(rename = "content")]
     pub content: Content,
 }
+
+impl Subtask {
+    /// The columns the struct is loaded from, the table also holds the version
+    pub const COLUMNS: (
+        subtasks::id,
+        subtasks::instruction,
+        subtasks::is_solution_verifiable,
+        subtasks::is_solution_visible,
+        subtasks::content,
+    ) = (
+        subtasks::id,
+        subtasks::instruction,
+        subtasks::is_solution_verifiable,
+        subtasks::is_solution_visible,
+        subtasks::content,
+    );
+}
@@ -0,0 +1,50 @@
+use crate::schema::databases_in_tags;
+use crate::schema::subtasks_in_tags;
+use crate::schema::tags;
+
+/// Tag : A label for subtasks and databases, e.g. a topic like "JOIN" or a difficulty.
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tag {
+    #[serde(rename = "id")]
+    pub id: String,
+    #[serde(rename = "name")]
+    pub name: String,
+    #[serde(rename = "category", skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    #[serde(rename = "subtasks", default)]
+    pub subtasks: Vec<String>,
+    #[serde(rename = "databases", default)]
+    pub databases: Vec<String>,
+}
+
+#[derive(Debug, Queryable, Insertable, AsChangeset)]
+#[table_name = "tags"]
+pub struct QueryableTag {
+    pub id: String,
+    pub name: String,
+    pub category: Option<String>,
+}
+
+impl QueryableTag {
+    pub fn from_tag(tag: Tag) -> Self {
+        Self {
+            id: tag.id,
+            name: tag.name,
+            category: tag.category,
+        }
+    }
+}
+
+#[derive(Debug, Queryable, Insertable)]
+pub struct SubtasksInTag {
+    pub subtask_id: String,
+    pub tag_id: String,
+}
+
+#[derive(Debug, Queryable, Insertable)]
+pub struct DatabasesInTag {
+    pub database_id: String,
+    pub tag_id: String,
+}
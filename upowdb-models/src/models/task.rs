@@ -21,6 +21,9 @@ pub struct QueryableTask {
 }
 
 impl QueryableTask {
+    /// The columns the struct is loaded from, the table also holds the version
+    pub const COLUMNS: (tasks::id, tasks::database_id) = (tasks::id, tasks::database_id);
+
     pub fn from_task(task: Task) -> Self {
         Self {
             id: task.id,
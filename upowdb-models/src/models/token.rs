@@ -1,7 +1,22 @@
 use serde::{Deserialize, Serialize};
 
+/// Token: The tokens handed out at login and when refreshing
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Token {
+    /// The short-lived access token for the `Authorization` header
     #[serde(rename = "token", skip_serializing_if = "Option::is_none")]
     pub token: Option<String>,
+    /// Seconds until the access token expires
+    #[serde(rename = "expires_in", skip_serializing_if = "Option::is_none")]
+    pub expires_in: Option<i64>,
+    /// Used once for getting the next pair of tokens
+    #[serde(rename = "refresh_token", skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+}
+
+/// RefreshRequest: Body of the refresh and logout endpoints
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RefreshRequest {
+    #[serde(rename = "refresh_token")]
+    pub refresh_token: String,
 }
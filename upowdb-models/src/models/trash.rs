@@ -0,0 +1,22 @@
+use crate::models::ObjectType;
+use crate::schema::trash;
+use chrono::NaiveDateTime;
+use diesel::{Insertable, Queryable};
+use serde::{Deserialize, Serialize};
+
+/// TrashEntry: A deleted course, worksheet, task, subtask or database that can still be restored.
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Insertable)]
+#[table_name = "trash"]
+pub struct TrashEntry {
+    pub object_id: String,
+    pub object_type: ObjectType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub deleted_by: String,
+    pub deleted_at: NaiveDateTime,
+    #[serde(skip)]
+    pub content: String,
+    /// The blob of a trashed database
+    #[serde(skip)]
+    pub content_hash: Option<String>,
+}
@@ -1,8 +1,10 @@
 use crate::schema::users;
 use argon2rs;
 use base64;
+use chrono::NaiveDateTime;
+use constant_time_eq::constant_time_eq;
 use diesel::{Insertable, Queryable};
-use rand::random;
+use rand::RngCore;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -11,27 +13,45 @@ use uuid::Uuid;
 pub struct User {
     id: String,
     pub name: String,
+    /// PHC string of an argon2id hash, or a legacy argon2d hash encoded in base64
     pub password_hash: String,
+    /// Salt of legacy hashes, empty for PHC strings, which contain their salt
     pub salt: String,
+    /// Tokens issued before are rejected, `None` when never set
+    #[serde(skip)]
+    pub tokens_valid_after: Option<NaiveDateTime>,
+    pub is_admin: bool,
+    /// Disabled users can't log in
+    pub disabled: bool,
 }
 
 impl User {
-    pub fn new(name: String, password: String, id: Option<Uuid>) -> User {
-        let salt: String = (0..32).map(|_| random::<char>()).collect();
+    pub fn new(name: String, password: &str, id: Option<Uuid>, hashing: &PasswordHashing) -> User {
         let id = match id {
             Some(id) => id.to_hyphenated().to_string(),
             None => Uuid::new_v4().to_hyphenated().to_string(),
         };
-        let password_hash = base64::encode(&argon2rs::argon2d_simple(&password, &salt));
         User {
             id,
             name,
-            password_hash,
-            salt,
+            password_hash: hashing.hash(password),
+            salt: String::new(),
+            tokens_valid_after: None,
+            is_admin: false,
+            disabled: false,
         }
     }
-    pub fn verify_password(&self, password: String) -> bool {
-        self.password_hash == base64::encode(&argon2rs::argon2d_simple(&password, &self.salt))
+    pub fn verify_password(&self, password: &str) -> bool {
+        if self.password_hash.starts_with("$argon2") {
+            argon2::verify_encoded(&self.password_hash, password.as_bytes()).unwrap_or(false)
+        } else {
+            let legacy = base64::encode(&argon2rs::argon2d_simple(password, &self.salt));
+            constant_time_eq(self.password_hash.as_bytes(), legacy.as_bytes())
+        }
+    }
+    /// Whether the hash is a legacy one or was made with other costs than configured now
+    pub fn needs_rehash(&self, hashing: &PasswordHashing) -> bool {
+        !self.password_hash.starts_with(&hashing.prefix())
     }
     pub fn returnable_userdata(&self) -> serde_json::Value {
         let mut map = serde_json::map::Map::new();
@@ -40,6 +60,7 @@ impl User {
             "name".to_string(),
             serde_json::Value::String(self.name.clone()),
         );
+        map.insert("admin".to_string(), serde_json::Value::Bool(self.is_admin));
         serde_json::Value::Object(map)
     }
     pub fn get_raw_id(&self) -> String {
@@ -49,3 +70,111 @@ impl User {
         Uuid::parse_str(&self.id).unwrap()
     }
 }
+
+/// PasswordHashing: The argon2id costs for new password hashes
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PasswordHashing {
+    /// Memory in KiB
+    #[serde(rename = "memory_kib")]
+    pub memory_kib: u32,
+    #[serde(rename = "iterations")]
+    pub iterations: u32,
+    #[serde(rename = "parallelism")]
+    pub parallelism: u32,
+}
+
+impl Default for PasswordHashing {
+    fn default() -> PasswordHashing {
+        PasswordHashing {
+            memory_kib: 19456,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+impl PasswordHashing {
+    /// Hashes the password with a random salt into a PHC string
+    pub fn hash(&self, password: &str) -> String {
+        let mut salt = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut salt);
+        let config = argon2::Config {
+            variant: argon2::Variant::Argon2id,
+            version: argon2::Version::Version13,
+            mem_cost: self.memory_kib,
+            time_cost: self.iterations,
+            lanes: self.parallelism,
+            thread_mode: argon2::ThreadMode::from_threads(self.parallelism),
+            ..argon2::Config::default()
+        };
+        argon2::hash_encoded(password.as_bytes(), &salt, &config)
+            .expect("Invalid password hashing costs")
+    }
+    /// Checks a password against a fixed hash made with these costs, which takes as long as
+    /// checking the password of an existing user. Never matches.
+    pub fn verify_dummy(&self, password: &str) -> bool {
+        let dummy = format!(
+            "{}c29tZXNhbHRzb21lc2FsdA${}",
+            self.prefix(),
+            "A".repeat(43)
+        );
+        argon2::verify_encoded(&dummy, password.as_bytes()).unwrap_or(false)
+    }
+    /// Checks the costs are in the ranges argon2 allows, so hashing can't fail later
+    pub fn validate(&self) -> Result<(), String> {
+        if self.iterations < 1 {
+            Err("At least one iteration is needed".to_owned())
+        } else if !(1..=0x00ff_ffff).contains(&self.parallelism) {
+            Err("Parallelism has to be between 1 and 16777215".to_owned())
+        } else if self.memory_kib < 8 * self.parallelism {
+            Err("At least 8 KiB of memory per lane are needed".to_owned())
+        } else {
+            Ok(())
+        }
+    }
+    /// Start of the PHC strings of hashes made with these costs
+    fn prefix(&self) -> String {
+        format!(
+            "$argon2id$v=19$m={},t={},p={}$",
+            self.memory_kib, self.iterations, self.parallelism
+        )
+    }
+}
+
+/// UserSummary: A user as listed to admins
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable)]
+pub struct UserSummary {
+    #[serde(rename = "id")]
+    pub id: String,
+    #[serde(rename = "name")]
+    pub name: String,
+    #[serde(rename = "admin")]
+    pub is_admin: bool,
+    #[serde(rename = "disabled")]
+    pub disabled: bool,
+}
+
+/// UserUpdate: This struct is passed by admins for changing the role or state of a user,
+/// fields left out stay unchanged
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserUpdate {
+    #[serde(rename = "admin", default)]
+    pub is_admin: Option<bool>,
+    #[serde(rename = "disabled", default)]
+    pub disabled: Option<bool>,
+}
+
+/// PasswordReset: This struct is passed by admins for setting a new password for a user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PasswordReset {
+    #[serde(rename = "password")]
+    pub password: String,
+}
+
+/// AccessReassignment: This struct is passed by admins for giving objects to another user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccessReassignment {
+    #[serde(rename = "user_id")]
+    pub user_id: String,
+}
@@ -27,6 +27,19 @@ pub struct QueryableWorksheet {
 }
 
 impl QueryableWorksheet {
+    /// The columns the struct is loaded from, the table also holds the version
+    pub const COLUMNS: (
+        worksheets::id,
+        worksheets::name,
+        worksheets::is_online,
+        worksheets::is_solution_online,
+    ) = (
+        worksheets::id,
+        worksheets::name,
+        worksheets::is_online,
+        worksheets::is_solution_online,
+    );
+
     pub fn from_worksheet(worksheet: Worksheet) -> Self {
         Self {
             id: worksheet.id,
@@ -13,11 +13,42 @@ table! {
     }
 }
 
+table! {
+    blobs (hash) {
+        hash -> Text,
+        content -> Binary,
+        compressed -> Bool,
+        size -> BigInt,
+        ref_count -> Integer,
+    }
+}
+
+table! {
+    blob_schemas (hash) {
+        hash -> Text,
+        schema -> Text,
+    }
+}
+
+table! {
+    catalog_entries (id) {
+        id -> Text,
+        object_id -> Text,
+        object_type -> Integer,
+        title -> Text,
+        description -> Text,
+        license -> Text,
+        published_by -> Text,
+        published_at -> Timestamp,
+    }
+}
+
 table! {
     courses (id) {
         id -> Text,
         name -> Text,
         description -> Nullable<Text>,
+        version -> Integer,
     }
 }
 
@@ -25,10 +56,66 @@ table! {
     databases (id) {
         id -> Text,
         name -> Text,
+        content_hash -> Text,
+        version -> Integer,
+    }
+}
+
+table! {
+    databases_in_tags (database_id, tag_id) {
+        database_id -> Text,
+        tag_id -> Text,
+    }
+}
+
+table! {
+    subtask_revisions (subtask_id, revision) {
+        subtask_id -> Text,
+        revision -> Integer,
+        user_id -> Nullable<Text>,
+        created_at -> Timestamp,
+        restored_from -> Nullable<Integer>,
         content -> Text,
     }
 }
 
+table! {
+    invitations (code) {
+        code -> Text,
+        created_by -> Text,
+        created_at -> Timestamp,
+        expires_at -> Nullable<Timestamp>,
+        max_uses -> Integer,
+        uses -> Integer,
+    }
+}
+
+table! {
+    quotas (user_id) {
+        user_id -> Text,
+        database_bytes -> Nullable<BigInt>,
+        objects -> Nullable<BigInt>,
+        aliases -> Nullable<BigInt>,
+    }
+}
+
+table! {
+    refresh_tokens (token_hash) {
+        token_hash -> Text,
+        user_id -> Text,
+        created_at -> Timestamp,
+        expires_at -> Timestamp,
+    }
+}
+
+table! {
+    revoked_tokens (jti) {
+        jti -> Text,
+        user_id -> Text,
+        expires_at -> Timestamp,
+    }
+}
+
 table! {
     subtasks (id) {
         id -> Text,
@@ -36,6 +123,7 @@ table! {
         is_solution_verifiable -> Bool,
         is_solution_visible -> Bool,
         content -> Text,
+        version -> Integer,
     }
 }
 
@@ -47,10 +135,37 @@ table! {
     }
 }
 
+table! {
+    subtasks_in_tags (subtask_id, tag_id) {
+        subtask_id -> Text,
+        tag_id -> Text,
+    }
+}
+
+table! {
+    tags (id) {
+        id -> Text,
+        name -> Text,
+        category -> Nullable<Text>,
+    }
+}
+
+table! {
+    task_revisions (task_id, revision) {
+        task_id -> Text,
+        revision -> Integer,
+        user_id -> Nullable<Text>,
+        created_at -> Timestamp,
+        restored_from -> Nullable<Integer>,
+        content -> Text,
+    }
+}
+
 table! {
     tasks (id) {
         id -> Text,
         database_id -> Text,
+        version -> Integer,
     }
 }
 
@@ -62,12 +177,38 @@ table! {
     }
 }
 
+table! {
+    trash (object_id) {
+        object_id -> Text,
+        object_type -> Integer,
+        name -> Nullable<Text>,
+        deleted_by -> Text,
+        deleted_at -> Timestamp,
+        content -> Text,
+        content_hash -> Nullable<Text>,
+    }
+}
+
 table! {
     users (id) {
         id -> Text,
         name -> Text,
         password_hash -> Text,
         salt -> Text,
+        tokens_valid_after -> Nullable<Timestamp>,
+        is_admin -> Bool,
+        disabled -> Bool,
+    }
+}
+
+table! {
+    worksheet_revisions (worksheet_id, revision) {
+        worksheet_id -> Text,
+        revision -> Integer,
+        user_id -> Nullable<Text>,
+        created_at -> Timestamp,
+        restored_from -> Nullable<Integer>,
+        content -> Text,
     }
 }
 
@@ -77,6 +218,7 @@ table! {
         name -> Nullable<Text>,
         is_online -> Bool,
         is_solution_online -> Bool,
+        version -> Integer,
     }
 }
 
@@ -88,6 +230,17 @@ table! {
     }
 }
 
+joinable!(blob_schemas -> blobs (hash));
+joinable!(catalog_entries -> users (published_by));
+joinable!(databases -> blobs (content_hash));
+joinable!(databases_in_tags -> databases (database_id));
+joinable!(databases_in_tags -> tags (tag_id));
+joinable!(invitations -> users (created_by));
+joinable!(quotas -> users (user_id));
+joinable!(refresh_tokens -> users (user_id));
+joinable!(revoked_tokens -> users (user_id));
+joinable!(subtasks_in_tags -> subtasks (subtask_id));
+joinable!(subtasks_in_tags -> tags (tag_id));
 joinable!(subtasks_in_tasks -> subtasks (subtask_id));
 joinable!(subtasks_in_tasks -> tasks (task_id));
 joinable!(tasks -> databases (database_id));
@@ -99,13 +252,27 @@ joinable!(worksheets_in_courses -> worksheets (worksheet_id));
 allow_tables_to_appear_in_same_query!(
     access,
     aliases,
+    blob_schemas,
+    blobs,
+    catalog_entries,
     courses,
     databases,
+    databases_in_tags,
+    invitations,
+    quotas,
+    refresh_tokens,
+    revoked_tokens,
+    subtask_revisions,
     subtasks,
+    subtasks_in_tags,
     subtasks_in_tasks,
+    tags,
+    task_revisions,
     tasks,
     tasks_in_worksheets,
+    trash,
     users,
+    worksheet_revisions,
     worksheets,
     worksheets_in_courses,
 );